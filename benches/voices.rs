@@ -0,0 +1,83 @@
+//! Voices-per-core benchmark
+//!
+//! Renders a chord of sustained FM voices through a `VoiceManager` and
+//! reports how many voices one core can run in real time, and how much
+//! faster rendering gets on all available cores.
+//!
+//! Usage: cargo bench --bench voices
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use corroza::generator::sine::fast_sin;
+use corroza::pipeline::parser::{KeyDirection, Note};
+use corroza::pipeline::voicemgr::{VoiceConfig, VoiceManager};
+
+const SAMPLE_RATE: u32 = 44100;
+const FRAME_SIZE: usize = 64;
+const VOICES: usize = 32;
+const SECONDS: usize = 10;
+
+/// Render the voices on `threads` threads and return the real-time factor
+fn real_time_factor(threads: usize) -> f64 {
+    let mut manager =
+        VoiceManager::new(VoiceConfig::default(), 110.0, SAMPLE_RATE).with_render_threads(threads);
+    for i in 0..VOICES as i32 {
+        let note = Note::from_semitone_index(36 + i).expect("note in range");
+        manager.handle_event(&note, KeyDirection::Down);
+    }
+
+    let mut buffer = [0.0f32; FRAME_SIZE];
+    let frames = SAMPLE_RATE as usize * SECONDS / FRAME_SIZE;
+    let start = Instant::now();
+    for _ in 0..frames {
+        manager.process_frame(&mut buffer);
+        black_box(&buffer);
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    assert_eq!(manager.voice_count(), VOICES);
+
+    let audio_seconds = (frames * FRAME_SIZE) as f64 / SAMPLE_RATE as f64;
+    audio_seconds / elapsed
+}
+
+fn main() {
+    let realtime_factor = real_time_factor(1);
+    println!(
+        "{} voices: {:.1}x real time on one thread",
+        VOICES, realtime_factor
+    );
+    println!(
+        "≈ {:.0} voices per core at {} Hz",
+        VOICES as f64 * realtime_factor,
+        SAMPLE_RATE
+    );
+
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    if threads > 1 {
+        println!(
+            "{} voices: {:.1}x real time on {} threads",
+            VOICES,
+            real_time_factor(threads),
+            threads
+        );
+    }
+
+    // Sine cost on its own
+    let count = 10_000_000;
+    let step = std::f32::consts::TAU / count as f32;
+    let time_sine = |sine: fn(f32) -> f32| {
+        let start = Instant::now();
+        let mut sum = 0.0f32;
+        for i in 0..count {
+            sum += sine(black_box(i as f32 * step));
+        }
+        black_box(sum);
+        start.elapsed().as_secs_f64() * 1e9 / count as f64
+    };
+    println!(
+        "sine: fast_sin {:.2} ns, f32::sin {:.2} ns",
+        time_sine(fast_sin),
+        time_sine(f32::sin)
+    );
+}
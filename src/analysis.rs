@@ -0,0 +1,606 @@
+//! Level and loudness measurement
+//!
+//! [`LoudnessMeter`] measures rendered audio in one pass:
+//! - Sample peak, and true peak from the signal oversampled 4 times
+//!   (ITU-R BS.1770), which catches peaks between samples
+//! - RMS level of the unweighted signal
+//! - Integrated loudness in LUFS: K-weighted, gated mean square over
+//!   400 ms blocks (ITU-R BS.1770, EBU R128)
+//! - Loudness range in LU: spread of the 3 s short-term loudness
+//!   (EBU Tech 3342)
+//!
+//! [`NormalizeTarget`] turns a measurement into the gain that brings the
+//! audio to a loudness or peak level.
+
+use crate::resample::sinc_row;
+use std::sync::OnceLock;
+
+/// Loudness of a block relative to its mean square (BS.1770)
+const LOUDNESS_OFFSET: f64 = -0.691;
+
+/// Blocks quieter than this (in LUFS) are never counted
+const ABSOLUTE_GATE: f64 = -70.0;
+
+/// Integrated loudness ignores blocks this far (in LU) below the ungated level
+const INTEGRATED_RELATIVE_GATE: f64 = -10.0;
+
+/// Loudness range ignores short-term values this far (in LU) below the
+/// ungated level
+const RANGE_RELATIVE_GATE: f64 = -20.0;
+
+/// Gating steps per second: blocks start every 100 ms
+const STEPS_PER_SECOND: u32 = 10;
+
+/// Steps in a 400 ms momentary block
+const MOMENTARY_STEPS: usize = 4;
+
+/// Steps in a 3 s short-term block
+const SHORT_TERM_STEPS: usize = 30;
+
+/// Interpolated points per sample interval for the true peak
+const OVERSAMPLING: usize = 4;
+
+/// Samples weighted for each interpolated point
+const TRUE_PEAK_TAPS: usize = 32;
+
+/// Highest true peak (in dBTP) a loudness target may raise the audio to
+///
+/// EBU R128 recommends -1 dBTP so the audio survives lossy encoding
+/// without clipping.
+pub const LOUDNESS_PEAK_CEILING: f64 = -1.0;
+
+/// Convert a linear level to decibels
+pub fn to_db(level: f64) -> f64 {
+    20.0 * level.log10()
+}
+
+/// Convert decibels to a linear level
+pub fn from_db(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+/// Loudness of a mean square value
+fn loudness(mean_square: f64) -> f64 {
+    LOUDNESS_OFFSET + 10.0 * mean_square.log10()
+}
+
+/// One second-order section of the K-weighting filter
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self {
+            b,
+            a,
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+}
+
+/// K-weighting for one channel: a high shelf modelling the head, then a
+/// high-pass
+///
+/// The BS.1770 coefficients are given for 48 kHz; these are derived from
+/// the analog prototypes so any sample rate works.
+#[derive(Debug, Clone, Copy)]
+struct KWeighting {
+    shelf: Biquad,
+    high_pass: Biquad,
+}
+
+impl KWeighting {
+    fn new(sample_rate: u32) -> Self {
+        let rate = sample_rate as f64;
+
+        let frequency = 1681.974450955533;
+        let gain_db = 3.999843853973347;
+        let q = 0.7071752369554196;
+        let k = (std::f64::consts::PI * frequency / rate).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad::new(
+            [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        let frequency = 38.13547087602444;
+        let q = 0.5003270373238773;
+        let k = (std::f64::consts::PI * frequency / rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad::new(
+            [1.0, -2.0, 1.0],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        Self { shelf, high_pass }
+    }
+
+    fn process(&mut self, input: f32) -> f64 {
+        self.high_pass.process(self.shelf.process(input as f64))
+    }
+}
+
+/// Finds the largest value between the samples of one channel
+///
+/// Interpolates `OVERSAMPLING - 1` points between neighbouring samples with
+/// a windowed sinc; the samples themselves are covered by the sample peak.
+#[derive(Debug, Clone)]
+struct TruePeakDetector {
+    /// Input not yet interpolated after, with the history the taps need
+    buffer: Vec<f32>,
+    peak: f32,
+}
+
+impl TruePeakDetector {
+    fn new() -> Self {
+        Self {
+            buffer: vec![0.0; TRUE_PEAK_TAPS / 2 - 1],
+            peak: 0.0,
+        }
+    }
+
+    /// Filter taps for each interpolated point
+    fn table() -> &'static [f32] {
+        static TABLE: OnceLock<Vec<f32>> = OnceLock::new();
+        TABLE.get_or_init(|| {
+            (1..OVERSAMPLING)
+                .flat_map(|p| sinc_row(p as f64 / OVERSAMPLING as f64, TRUE_PEAK_TAPS, 1.0))
+                .collect()
+        })
+    }
+
+    fn process(&mut self, samples: impl Iterator<Item = f32>) {
+        self.buffer.extend(samples);
+        let table = Self::table();
+        let ready = (self.buffer.len() + 1).saturating_sub(TRUE_PEAK_TAPS);
+        for start in 0..ready {
+            let inputs = &self.buffer[start..start + TRUE_PEAK_TAPS];
+            for row in table.chunks_exact(TRUE_PEAK_TAPS) {
+                let value: f32 = inputs.iter().zip(row).map(|(x, w)| x * w).sum();
+                self.peak = self.peak.max(value.abs());
+            }
+        }
+        self.buffer.drain(..ready);
+    }
+
+    /// Interpolate up to the last sample, treating the input as silent
+    /// afterwards
+    fn finish(&mut self) -> f32 {
+        self.process(std::iter::repeat_n(0.0, TRUE_PEAK_TAPS / 2));
+        self.peak
+    }
+}
+
+/// Levels measured by a [`LoudnessMeter`]
+///
+/// Levels are linear (1.0 = full scale); use the `_db` methods for
+/// decibels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Loudness {
+    /// Largest absolute sample value
+    pub peak: f32,
+    /// Largest absolute value between samples (4x oversampled)
+    pub true_peak: f32,
+    /// Root mean square of all samples
+    pub rms: f32,
+    /// Gated integrated loudness in LUFS (negative infinity for silence or
+    /// audio shorter than 400 ms)
+    pub integrated: f64,
+    /// Loudness range in LU (0 for audio shorter than 3 s)
+    pub range: f64,
+}
+
+impl Loudness {
+    /// Sample peak in dBFS
+    pub fn peak_db(&self) -> f64 {
+        to_db(self.peak as f64)
+    }
+
+    /// True peak in dBTP
+    pub fn true_peak_db(&self) -> f64 {
+        to_db(self.true_peak as f64)
+    }
+
+    /// RMS level in dBFS
+    pub fn rms_db(&self) -> f64 {
+        to_db(self.rms as f64)
+    }
+
+    /// The levels after multiplying the audio by `gain`
+    pub fn scaled(&self, gain: f64) -> Loudness {
+        Loudness {
+            peak: (self.peak as f64 * gain) as f32,
+            true_peak: (self.true_peak as f64 * gain) as f32,
+            rms: (self.rms as f64 * gain) as f32,
+            integrated: self.integrated + to_db(gain),
+            range: self.range,
+        }
+    }
+}
+
+/// Streaming loudness and peak meter
+///
+/// Feed interleaved audio with [`LoudnessMeter::process`] in blocks of any
+/// size, then call [`LoudnessMeter::finish`]. Every channel is weighted
+/// equally, as for mono and stereo in BS.1770.
+///
+/// # Example
+/// ```
+/// use corroza::analysis::LoudnessMeter;
+///
+/// let tone: Vec<f32> = (0..48000)
+///     .map(|i| 0.1 * (i as f32 * std::f32::consts::TAU * 1000.0 / 48000.0).sin())
+///     .collect();
+/// let mut meter = LoudnessMeter::new(48000, 1);
+/// meter.process(&tone);
+/// let loudness = meter.finish();
+/// assert!((loudness.integrated + 23.0).abs() < 0.1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct LoudnessMeter {
+    channels: usize,
+    filters: Vec<KWeighting>,
+    true_peaks: Vec<TruePeakDetector>,
+    peak: f32,
+    sum_squares: f64,
+    samples: u64,
+    /// Frames in a 100 ms gating step
+    step_frames: usize,
+    step_fill: usize,
+    /// Sum of K-weighted squares in the current step
+    step_energy: f64,
+    /// Sum of K-weighted squares of each complete step
+    steps: Vec<f64>,
+}
+
+impl LoudnessMeter {
+    /// Create a meter
+    ///
+    /// # Arguments
+    /// * `sample_rate` - Sample rate in Hz
+    /// * `channels` - Number of interleaved channels
+    ///
+    /// # Panics
+    /// Panics if the sample rate or channel count is zero
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        assert!(
+            sample_rate > 0 && channels > 0,
+            "Invalid meter format: {} Hz, {} channels",
+            sample_rate,
+            channels
+        );
+        Self {
+            channels,
+            filters: vec![KWeighting::new(sample_rate); channels],
+            true_peaks: vec![TruePeakDetector::new(); channels],
+            peak: 0.0,
+            sum_squares: 0.0,
+            samples: 0,
+            step_frames: (sample_rate / STEPS_PER_SECOND).max(1) as usize,
+            step_fill: 0,
+            step_energy: 0.0,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Measure a block of interleaved samples
+    ///
+    /// # Panics
+    /// Panics if the block does not hold whole frames
+    pub fn process(&mut self, samples: &[f32]) {
+        assert!(
+            samples.len().is_multiple_of(self.channels),
+            "Invalid block: {} samples for {} channels",
+            samples.len(),
+            self.channels
+        );
+        for frame in samples.chunks_exact(self.channels) {
+            for (&sample, filter) in frame.iter().zip(self.filters.iter_mut()) {
+                self.peak = self.peak.max(sample.abs());
+                self.sum_squares += sample as f64 * sample as f64;
+                let weighted = filter.process(sample);
+                self.step_energy += weighted * weighted;
+            }
+            self.step_fill += 1;
+            if self.step_fill == self.step_frames {
+                self.steps.push(self.step_energy);
+                self.step_energy = 0.0;
+                self.step_fill = 0;
+            }
+        }
+        self.samples += samples.len() as u64;
+
+        for (channel, detector) in self.true_peaks.iter_mut().enumerate() {
+            detector.process(samples.iter().skip(channel).step_by(self.channels).copied());
+        }
+    }
+
+    /// Finish measuring and return the levels
+    pub fn finish(mut self) -> Loudness {
+        let true_peak = self
+            .true_peaks
+            .iter_mut()
+            .map(TruePeakDetector::finish)
+            .fold(self.peak, f32::max);
+        let rms = if self.samples > 0 {
+            (self.sum_squares / self.samples as f64).sqrt() as f32
+        } else {
+            0.0
+        };
+        Loudness {
+            peak: self.peak,
+            true_peak,
+            rms,
+            integrated: self.integrated(),
+            range: self.range(),
+        }
+    }
+
+    /// Mean squares of blocks of `steps` gating steps, one per step
+    fn block_mean_squares(&self, steps: usize) -> Vec<f64> {
+        let frames = (steps * self.step_frames) as f64;
+        self.steps
+            .windows(steps)
+            .map(|block| block.iter().sum::<f64>() / frames)
+            .collect()
+    }
+
+    /// Gated integrated loudness (BS.1770)
+    fn integrated(&self) -> f64 {
+        let blocks = self.block_mean_squares(MOMENTARY_STEPS);
+        let gated = |threshold: f64| {
+            let kept: Vec<f64> = blocks
+                .iter()
+                .copied()
+                .filter(|&z| loudness(z) > threshold)
+                .collect();
+            if kept.is_empty() {
+                f64::NEG_INFINITY
+            } else {
+                loudness(kept.iter().sum::<f64>() / kept.len() as f64)
+            }
+        };
+        let ungated = gated(ABSOLUTE_GATE);
+        if ungated == f64::NEG_INFINITY {
+            return ungated;
+        }
+        gated(ungated + INTEGRATED_RELATIVE_GATE)
+    }
+
+    /// Loudness range: 10th to 95th percentile of gated short-term
+    /// loudness (EBU Tech 3342)
+    fn range(&self) -> f64 {
+        let blocks: Vec<f64> = self
+            .block_mean_squares(SHORT_TERM_STEPS)
+            .into_iter()
+            .filter(|&z| loudness(z) > ABSOLUTE_GATE)
+            .collect();
+        if blocks.is_empty() {
+            return 0.0;
+        }
+        let threshold =
+            loudness(blocks.iter().sum::<f64>() / blocks.len() as f64) + RANGE_RELATIVE_GATE;
+        let mut levels: Vec<f64> = blocks
+            .into_iter()
+            .map(loudness)
+            .filter(|&l| l > threshold)
+            .collect();
+        levels.sort_by(f64::total_cmp);
+        let percentile = |p: f64| levels[((levels.len() - 1) as f64 * p).round() as usize];
+        percentile(0.95) - percentile(0.10)
+    }
+}
+
+/// Measure mono audio
+pub fn analyze(samples: &[f32], sample_rate: u32) -> Loudness {
+    let mut meter = LoudnessMeter::new(sample_rate, 1);
+    meter.process(samples);
+    meter.finish()
+}
+
+/// Level to normalize audio to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NormalizeTarget {
+    /// Integrated loudness in LUFS, without raising the true peak above
+    /// [`LOUDNESS_PEAK_CEILING`]
+    Loudness(f64),
+    /// Sample peak in dBFS
+    Peak(f64),
+    /// True peak in dBTP
+    TruePeak(f64),
+}
+
+impl NormalizeTarget {
+    /// Linear gain that brings audio measured as `loudness` to the target
+    ///
+    /// Silent audio is left unchanged (gain 1).
+    pub fn gain(&self, loudness: &Loudness) -> f64 {
+        let towards = |target_db: f64, level_db: f64| {
+            if level_db.is_finite() {
+                from_db(target_db - level_db)
+            } else {
+                1.0
+            }
+        };
+        match *self {
+            NormalizeTarget::Loudness(lufs) => {
+                let gain = towards(lufs, loudness.integrated);
+                gain.min(towards(LOUDNESS_PEAK_CEILING, loudness.true_peak_db()))
+            }
+            NormalizeTarget::Peak(db) => towards(db, loudness.peak_db()),
+            NormalizeTarget::TruePeak(db) => towards(db, loudness.true_peak_db()),
+        }
+    }
+}
+
+impl std::str::FromStr for NormalizeTarget {
+    type Err = String;
+
+    /// Parse a level with its unit, e.g. `-16lufs`, `-1db` (sample peak) or
+    /// `-1dbtp` (true peak)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.trim().to_ascii_lowercase();
+        let (value, target): (&str, fn(f64) -> NormalizeTarget) =
+            if let Some(value) = lower.strip_suffix("lufs") {
+                (value, NormalizeTarget::Loudness)
+            } else if let Some(value) = lower.strip_suffix("dbtp") {
+                (value, NormalizeTarget::TruePeak)
+            } else if let Some(value) = lower
+                .strip_suffix("dbfs")
+                .or_else(|| lower.strip_suffix("db"))
+            {
+                (value, NormalizeTarget::Peak)
+            } else {
+                return Err(format!("Invalid normalization target: {}", s));
+            };
+        match value.trim().parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(target(value)),
+            _ => Err(format!("Invalid normalization target: {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::TAU;
+
+    /// Sine at `amplitude`, interleaved into `channels` identical channels
+    fn tone(
+        frequency: f64,
+        amplitude: f64,
+        sample_rate: u32,
+        seconds: f64,
+        channels: usize,
+    ) -> Vec<f32> {
+        let frames = (seconds * sample_rate as f64) as usize;
+        (0..frames)
+            .flat_map(|i| {
+                let value = amplitude * (TAU * frequency * i as f64 / sample_rate as f64).sin();
+                std::iter::repeat_n(value as f32, channels)
+            })
+            .collect()
+    }
+
+    fn measure(samples: &[f32], sample_rate: u32, channels: usize) -> Loudness {
+        let mut meter = LoudnessMeter::new(sample_rate, channels);
+        for block in samples.chunks(1000 * channels) {
+            meter.process(block);
+        }
+        meter.finish()
+    }
+
+    #[test]
+    fn test_peak_and_rms() {
+        let loudness = analyze(&tone(100.0, 0.5, 48000, 1.0, 1), 48000);
+        assert!((loudness.peak - 0.5).abs() < 1e-4);
+        assert!((loudness.rms - 0.5 / 2f32.sqrt()).abs() < 1e-4);
+        assert!((loudness.peak_db() - to_db(0.5)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_true_peak_between_samples() {
+        // A quarter of the sample rate, sampled 45° away from its crests,
+        // faded in and out so the edges don't ring
+        let samples: Vec<f32> = (0..4800)
+            .map(|i| {
+                let fade = (i.min(4799 - i) as f64 / 480.0).min(1.0);
+                (fade * (TAU * (i as f64 / 4.0 + 0.125)).sin()) as f32
+            })
+            .collect();
+        let loudness = analyze(&samples, 48000);
+        assert!((loudness.peak - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-3);
+        assert!(
+            (loudness.true_peak - 1.0).abs() < 0.01,
+            "{}",
+            loudness.true_peak
+        );
+    }
+
+    #[test]
+    fn test_integrated_loudness_of_reference_tone() {
+        // 1 kHz at -20 dBFS reads -23 LUFS per channel, +3 LU in stereo
+        let mono = measure(&tone(1000.0, 0.1, 48000, 1.0, 1), 48000, 1);
+        assert!((mono.integrated + 23.0).abs() < 0.05, "{}", mono.integrated);
+        let stereo = measure(&tone(1000.0, 0.1, 44100, 1.0, 2), 44100, 2);
+        assert!(
+            (stereo.integrated + 20.0).abs() < 0.05,
+            "{}",
+            stereo.integrated
+        );
+    }
+
+    #[test]
+    fn test_gating_ignores_silence() {
+        let mut samples = tone(1000.0, 0.1, 48000, 1.0, 1);
+        samples.resize(samples.len() * 3, 0.0);
+        let loudness = analyze(&samples, 48000);
+        // Seven blocks of tone and three partly covering its end count,
+        // the silent blocks do not
+        let expected = -23.0 + 10.0 * (8.5f64 / 10.0).log10();
+        assert!((loudness.integrated - expected).abs() < 0.05);
+        assert!(loudness.rms_db() < to_db(0.1 / 2f64.sqrt()) - 4.0);
+
+        let silent = analyze(&[0.0; 48000], 48000);
+        assert_eq!(silent.integrated, f64::NEG_INFINITY);
+        assert_eq!(silent.range, 0.0);
+        assert_eq!(NormalizeTarget::Loudness(-16.0).gain(&silent), 1.0);
+    }
+
+    #[test]
+    fn test_loudness_range_of_two_levels() {
+        // EBU Tech 3342 case 1 (20 s at -20 dBFS, then 20 s at -30 dBFS),
+        // at a low rate to keep the test quick
+        let mut samples = tone(500.0, 0.1, 4000, 20.0, 1);
+        samples.extend(tone(500.0, 0.1 / 10f64.sqrt(), 4000, 20.0, 1));
+        let loudness = analyze(&samples, 4000);
+        assert!((loudness.range - 10.0).abs() < 1.0, "{}", loudness.range);
+    }
+
+    #[test]
+    fn test_normalize_gain() {
+        let loudness = analyze(&tone(1000.0, 0.1, 48000, 1.0, 1), 48000);
+
+        let gain = NormalizeTarget::Loudness(-17.0).gain(&loudness);
+        assert!((to_db(gain) - 6.0).abs() < 0.05);
+        assert!((loudness.scaled(gain).integrated + 17.0).abs() < 0.05);
+
+        // Louder targets stop at the true peak ceiling
+        let gain = NormalizeTarget::Loudness(0.0).gain(&loudness);
+        assert!((loudness.scaled(gain).true_peak_db() - LOUDNESS_PEAK_CEILING).abs() < 1e-4);
+
+        let gain = NormalizeTarget::Peak(-6.0).gain(&loudness);
+        assert!((loudness.scaled(gain).peak_db() + 6.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!("-16LUFS".parse(), Ok(NormalizeTarget::Loudness(-16.0)));
+        assert_eq!("-1dbtp".parse(), Ok(NormalizeTarget::TruePeak(-1.0)));
+        assert_eq!("-0.5dB".parse(), Ok(NormalizeTarget::Peak(-0.5)));
+        assert_eq!("0dbfs".parse(), Ok(NormalizeTarget::Peak(0.0)));
+        assert!("-16".parse::<NormalizeTarget>().is_err());
+        assert!("loudlufs".parse::<NormalizeTarget>().is_err());
+    }
+}
@@ -219,15 +219,9 @@ fn find_sharp_change(samples: &[f32], threshold: f32) -> Option<(usize, f32)> {
             eprintln!("  Context around discontinuity:");
             let start = i.saturating_sub(5);
             let end = (i + 5).min(samples.len());
-            for j in start..end {
-                let marker = if j == i {
-                    " <--"
-                } else if j == i - 1 {
-                    ""
-                } else {
-                    ""
-                };
-                eprintln!("    [{}]: {:.6}{}", j, samples[j], marker);
+            for (j, sample) in samples.iter().enumerate().take(end).skip(start) {
+                let marker = if j == i { " <--" } else { "" };
+                eprintln!("    [{}]: {:.6}{}", j, sample, marker);
             }
             return Some((i, diff));
         }
@@ -268,12 +262,13 @@ mod tests {
             2 * silence_samples
         );
 
-        for i in 0..silence_samples {
-            assert_eq!(samples[i], 0.0, "Sample {} at start should be silence", i);
+        for (i, &sample) in samples.iter().enumerate().take(silence_samples) {
+            assert_eq!(sample, 0.0, "Sample {} at start should be silence", i);
         }
 
-        for i in samples.len() - silence_samples..samples.len() {
-            assert_eq!(samples[i], 0.0, "Sample {} at end should be silence", i);
+        let tail_start = samples.len() - silence_samples;
+        for (i, &sample) in samples.iter().enumerate().skip(tail_start) {
+            assert_eq!(sample, 0.0, "Sample {} at end should be silence", i);
         }
     }
 
@@ -1,18 +1,33 @@
 //! CLI tool for generating audio from musical transcription
 //!
-//! Usage: play <input.txt> [output.wav]
+//! Usage: play [options] <input.txt> [output.wav]
 //!
 //! If output is not specified, generates <input>.wav
 
-use corroza::generator::fm_synth::FmSynthParams;
-use corroza::pipeline::parser::parse_transcription;
-use corroza::pipeline::scheduler::{Pipeline, PipelineConfig};
-use corroza::pipeline::voicemgr::VoiceConfig;
+use corroza::analysis::{to_db, NormalizeTarget};
+use corroza::generator::fm_synth::{FmSynthParams, GlideCurve};
+use corroza::generator::sampler::Sample;
+use corroza::pipeline::parser::parse_tracks_with;
+use corroza::pipeline::parser::{Note, Track, CONCERT_A, MAX_OCTAVE};
+use corroza::pipeline::preprocess::FileLoader;
+use corroza::pipeline::processor::{
+    apply_processor, ArpPattern, Arpeggiator, ArpeggiatorConfig, ChordMemory,
+};
+use corroza::pipeline::scheduler::{Pipeline, PipelineConfig, CONCERT_PITCH};
+use corroza::pipeline::transform::{humanize, quantize, scale_time, transpose, HumanizeParams};
+use corroza::pipeline::tuning::{KeyboardMapping, Scale, Tuning};
+use corroza::pipeline::voicemgr::{
+    NotePriority, RetriggerPolicy, SampleMap, SampleZone, StealPolicy, VoiceConfig, VoiceMode,
+};
+use corroza::wav::{Dither, SampleFormat, SampleLoop, SamplerInfo, WavMetadata, WavSpec};
+use std::collections::HashMap;
 use std::env;
 use std::fs;
+use std::path::Path;
 use std::process;
+use std::sync::Arc;
 
-const USAGE: &str = "Usage: play <input.txt> [output.wav]
+const USAGE: &str = "Usage: play [options] <input.txt> [output.wav]
 
 Generate audio from musical transcription file.
 
@@ -20,24 +35,692 @@ Arguments:
   input.txt     Path to transcription file
   output.wav    Output WAV file path (optional, defaults to <input>.wav)
 
+Options:
+  --transpose <semitones>      Shift all notes (negative is down)
+  --stretch <ratio>            Scale timing (2.0 = half tempo, 0.5 = double tempo)
+  --quantize <timesteps>       Snap lines to a grid of timesteps
+  --humanize <timing>,<vel>    Random jitter: max timesteps and max velocity offset
+  --chord <intervals>          Play a chord for every key (e.g. 0,4,7)
+  --arp <pattern>[:rate[:oct]] Arpeggiate held notes; pattern is up, down,
+                               updown, random or played; rate in timesteps
+  --seed <n>                   Seed for --humanize and random arpeggios (default 0)
+  --polyphony <n>              Maximum voices per track (default unlimited)
+  --steal <policy>             Voice to steal at the limit: oldest (default),
+                               quietest, releasing or same
+  --retrigger <policy>         Key-down on a sounding note: ignore (default),
+                               retrigger or layer
+  --mono <priority>            One voice per track; priority is last, low or high
+  --legato                     In mono mode, don't restart envelopes between
+                               overlapping notes
+  --glide <ms>[:curve]         Portamento time in mono mode; curve is exp
+                               (default) or linear
+  --sample-accurate            Start and stop notes at their exact sample
+                               instead of the next 64-sample frame
+  --sample <file>[:<low>:<high>[:<root>]]
+                               Play a WAV sample for keys <low> to <high>
+                               (e.g. C2:B3, default every key); repeat for
+                               more zones. The root note and loop come from
+                               the file's smpl chunk unless <root> is given
+                               (default C4)
+  --crossfade <ms>             Crossfade at the end of sample loops
+  --fm-level <level>           Level of the FM synthesizer under the samples
+                               (default 1, 0 = samples only)
+  --threads <n>                Render each track's voices on <n> threads
+                               (default 1; the output is identical)
+  --format <format>            Output format: pcm16 (default), pcm24, pcm32
+                               or float32
+  --dither <type>              Dither for integer formats: none (default),
+                               tpdf or shaped
+  --channels <n>               Write the mix to <n> channels (default 1)
+  --rate <hz>                  Output sample rate (default 44100)
+  --oversample <n>             Render at <n> times 44.1 kHz and convert to
+                               the output rate (reduces FM aliasing)
+  --title <text>               Title tag for the output file
+  --artist <text>              Artist tag for the output file
+  --markers                    Add a cue marker at every note-on
+  --normalize <target>         Bring the output to a loudness or peak level:
+                               e.g. -16lufs, -1dbtp (true peak) or -1db
+  --root <pitch>               Write sampler data with this root note
+                               (e.g. A4), tuned to how the note sounds
+  --loop <start>:<end>         Sampler loop in samples (end inclusive);
+                               needs --root
+  --concert                    Tune to concert pitch (4a = 440 Hz) instead of
+                               1C = 110 Hz
+  --a4 <hz>                    Tune to concert pitch with 4a at <hz>
+  --reference <pitch>=<hz>     Tune so a note sounds at <hz> (e.g. C4=261.63)
+  --scl <file>                 Tune notes to a Scala scale (degree 0 on 1C)
+  --kbm <file>                 Scala keyboard mapping for the scale
+  --edo <n>[:cents]            Equal divisions of the octave (or of a
+                               stretched period in cents)
+
+Structure:
+  :name ... :end        Define a section; play it with `*name` or `*name x<count>`
+  |: ... :| x<count>    Repeat a block (default twice)
+  @include <file>       Insert another file (relative to the including file)
+
+Controls (on event lines):
+  ped+ / ped-           Press / lift the sustain pedal
+  sos+ / sos-           Press / lift the sostenuto pedal
+  bend=<-1..1>          Pitch bend (two semitones at full bend)
+  mod=<0..1>            Mod wheel (more FM modulation)
+  expr=<0..1>           Expression (output level)
+  cc<n>=<0..1>          Any controller number
+
+Tracks:
+  Files may declare tracks with `@track <name> [instrument=<name>] [gain=<value>]`.
+  Built-in instruments: lead (default), bass, pad
+
 Examples:
   play song.txt
   play song.txt output.wav
+  play --transpose -3 --stretch 1.5 song.txt
 ";
 
+/// Event stream transforms requested on the command line
+#[derive(Debug, Default)]
+struct Transforms {
+    transpose: Option<i32>,
+    stretch: Option<f64>,
+    quantize: Option<usize>,
+    chord: Option<Vec<i32>>,
+    arp: Option<ArpeggiatorConfig>,
+    humanize: Option<HumanizeParams>,
+}
+
+/// Voice allocation settings requested on the command line
+#[derive(Debug, Default)]
+struct VoiceOptions {
+    max_voices: Option<usize>,
+    steal_policy: Option<StealPolicy>,
+    retrigger_policy: Option<RetriggerPolicy>,
+    mono: Option<NotePriority>,
+    legato: bool,
+    glide_ms: Option<f32>,
+    glide_curve: Option<GlideCurve>,
+    /// Sample zones: file, key range and root note
+    samples: Vec<SampleOption>,
+    crossfade_ms: Option<f32>,
+    fm_level: Option<f32>,
+}
+
+/// A `--sample` zone
+#[derive(Debug)]
+struct SampleOption {
+    path: String,
+    keys: Option<(Note, Note)>,
+    root: Option<Note>,
+}
+
+impl SampleOption {
+    /// Parse `<file>[:<low>:<high>[:<root>]]`
+    fn parse(value: &str) -> Result<Self, String> {
+        let pitch = |s: &str| {
+            Note::from_scientific_pitch(s)
+                .ok_or_else(|| format!("invalid pitch for --sample: {}", s))
+        };
+        let mut parts = value.split(':');
+        let path = parts.next().unwrap_or_default().to_string();
+        let keys = match (parts.next(), parts.next()) {
+            (Some(low), Some(high)) => Some((pitch(low)?, pitch(high)?)),
+            (None, _) => None,
+            _ => return Err(format!("--sample expects <file>[:<low>:<high>]: {}", value)),
+        };
+        let root = parts.next().map(pitch).transpose()?;
+        if path.is_empty() || parts.next().is_some() {
+            return Err(format!("--sample expects <file>[:<low>:<high>]: {}", value));
+        }
+        Ok(Self { path, keys, root })
+    }
+}
+
+impl VoiceOptions {
+    /// Apply the options to an instrument's voice configuration
+    fn apply(&self, config: &mut VoiceConfig, sample_rate: u32, samples: &SampleMap) {
+        if self.max_voices.is_some() {
+            config.max_voices = self.max_voices;
+        }
+        if let Some(policy) = self.steal_policy {
+            config.steal_policy = policy;
+        }
+        if let Some(policy) = self.retrigger_policy {
+            config.retrigger_policy = policy;
+        }
+        if let Some(priority) = self.mono {
+            config.mode = VoiceMode::Mono;
+            config.note_priority = priority;
+        }
+        config.legato |= self.legato;
+        if let Some(ms) = self.glide_ms {
+            config.glide_samples = (ms * sample_rate as f32 / 1000.0).round() as usize;
+        }
+        if let Some(curve) = self.glide_curve {
+            config.glide_curve = curve;
+        }
+        if !samples.is_empty() {
+            config.samples = samples.clone();
+        }
+        if let Some(level) = self.fm_level {
+            config.fm_level = level;
+        }
+    }
+
+    /// Read the `--sample` files into a sample map
+    fn load_samples(&self) -> Result<SampleMap, String> {
+        let lowest = Note::from_semitone_index(0).expect("0C is a note");
+        let highest = Note::from_semitone_index(MAX_OCTAVE as i32 * 12 + 11).expect("top note");
+        let mut map = SampleMap::default();
+        for option in &self.samples {
+            let mut sample =
+                Sample::load(&option.path).map_err(|e| format!("{}: {}", option.path, e))?;
+            if let Some(root) = option.root {
+                let midi = root
+                    .midi_number()
+                    .ok_or_else(|| format!("root note out of MIDI range: {}", root))?;
+                sample = sample.with_root_frequency(SamplerInfo::new(midi).frequency());
+            }
+            if let Some(ms) = self.crossfade_ms {
+                let crossfade = (ms * sample.sample_rate() as f32 / 1000.0).round() as usize;
+                sample = sample.with_crossfade(crossfade);
+            }
+            let (low, high) = option.keys.unwrap_or((lowest, highest));
+            map = map.with_zone(SampleZone::new(Arc::new(sample), low, high));
+        }
+        Ok(map)
+    }
+}
+
+/// Tuning requested on the command line
+#[derive(Debug, Default)]
+struct TuningOptions {
+    scl: Option<String>,
+    kbm: Option<String>,
+    edo: Option<(usize, f64)>,
+}
+
+impl TuningOptions {
+    /// Build the tuning, or `None` to keep 12-TET from `base_frequency`
+    ///
+    /// Without a keyboard mapping, degree 0 sits on 1C at `base_frequency`.
+    fn build(&self, base_frequency: f32) -> Result<Option<Tuning>, String> {
+        let scale = match (&self.scl, self.edo) {
+            (Some(_), Some(_)) => return Err("--scl and --edo cannot be combined".to_string()),
+            (Some(path), None) => Some(Scale::load(path).map_err(|e| format!("{}: {}", path, e))?),
+            (None, Some((divisions, period))) => Some(Scale::equal(divisions, period)),
+            (None, None) => None,
+        };
+        let mapping = match &self.kbm {
+            Some(path) => {
+                Some(KeyboardMapping::load(path).map_err(|e| format!("{}: {}", path, e))?)
+            }
+            None => None,
+        };
+        if scale.is_none() && mapping.is_none() {
+            return Ok(None);
+        }
+
+        let scale = scale.unwrap_or_else(Scale::equal_temperament);
+        let mapping =
+            mapping.unwrap_or_else(|| KeyboardMapping::linear(24, 24, base_frequency as f64));
+        Tuning::new(scale, mapping)
+            .map(Some)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Output file format options
+#[derive(Debug, Default)]
+struct OutputOptions {
+    /// Sample format and bits, e.g. `(Pcm, 24)`
+    format: Option<(SampleFormat, u16)>,
+    dither: Option<Dither>,
+    channels: Option<u16>,
+    /// Sample rate of the file; 44.1 kHz when `None`
+    sample_rate: Option<u32>,
+    title: Option<String>,
+    artist: Option<String>,
+    /// Cue marker at every note-on
+    markers: bool,
+    /// Loudness or peak level to normalize to
+    normalize: Option<NormalizeTarget>,
+    /// Sampler root note
+    root: Option<Note>,
+    /// Sampler loop start and end in samples
+    sample_loop: Option<(u32, u32)>,
+}
+
+impl OutputOptions {
+    /// Output spec (16-bit mono unless overridden)
+    fn spec(&self, default_rate: u32) -> WavSpec {
+        let sample_rate = self.sample_rate.unwrap_or(default_rate);
+        let channels = self.channels.unwrap_or(1);
+        let spec = match self.format.unwrap_or((SampleFormat::Pcm, 16)) {
+            (SampleFormat::Float, _) => WavSpec::float(sample_rate, channels),
+            (SampleFormat::Pcm, bits) => WavSpec::pcm(sample_rate, channels, bits),
+        };
+        spec.with_dither(self.dither.unwrap_or_default())
+    }
+
+    /// Tags and sampler data for the output file
+    ///
+    /// The root note is tuned from the frequency the pipeline plays it at.
+    fn metadata(&self, pipeline: &Pipeline) -> Result<WavMetadata, String> {
+        let sampler = match self.root {
+            Some(root) => {
+                let frequency = pipeline
+                    .note_frequency(&root)
+                    .ok_or_else(|| format!("--root {} is not mapped by the tuning", root))?;
+                let mut sampler = SamplerInfo::from_frequency(frequency as f64);
+                if let Some((start, end)) = self.sample_loop {
+                    sampler = sampler.with_loop(SampleLoop::forward(start, end));
+                }
+                Some(sampler)
+            }
+            None => None,
+        };
+        Ok(WavMetadata {
+            title: self.title.clone(),
+            artist: self.artist.clone(),
+            software: Some(format!("corroza {}", env!("CARGO_PKG_VERSION"))),
+            sampler,
+            ..Default::default()
+        })
+    }
+}
+
+/// Parse an output format name
+fn parse_format(name: &str) -> Result<(SampleFormat, u16), String> {
+    match name {
+        "pcm16" => Ok((SampleFormat::Pcm, 16)),
+        "pcm24" => Ok((SampleFormat::Pcm, 24)),
+        "pcm32" => Ok((SampleFormat::Pcm, 32)),
+        "float32" => Ok((SampleFormat::Float, 32)),
+        _ => Err(format!("invalid output format: {}", name)),
+    }
+}
+
+/// Parsed command line arguments
+#[derive(Debug)]
+struct Args {
+    input_path: String,
+    output_path: Option<String>,
+    transforms: Transforms,
+    voice: VoiceOptions,
+    tuning: TuningOptions,
+    /// Reference note and frequency replacing 1C = 110 Hz
+    reference_pitch: Option<(Note, f32)>,
+    sample_accurate: bool,
+    render_threads: usize,
+    /// Factor applied to the 44.1 kHz render rate
+    oversample: u32,
+    output: OutputOptions,
+}
+
+/// Parse the value following an option flag
+fn option_value<T: std::str::FromStr>(name: &str, value: Option<&String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} requires a value", name))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", name, value))
+}
+
+/// Check that a frequency option is positive and finite
+fn positive_frequency(name: &str, frequency: f32) -> Result<f32, String> {
+    if frequency.is_finite() && frequency > 0.0 {
+        Ok(frequency)
+    } else {
+        Err(format!("invalid value for {}: {}", name, frequency))
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut positional = Vec::new();
+    let mut transforms = Transforms::default();
+    let mut seed = 0u64;
+    let mut voice = VoiceOptions::default();
+    let mut tuning = TuningOptions::default();
+    let mut reference_pitch = None;
+    let mut sample_accurate = false;
+    let mut render_threads = 1;
+    let mut oversample = 1;
+    let mut output = OutputOptions::default();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--transpose" => transforms.transpose = Some(option_value(arg, iter.next())?),
+            "--stretch" => transforms.stretch = Some(option_value(arg, iter.next())?),
+            "--quantize" => transforms.quantize = Some(option_value(arg, iter.next())?),
+            "--seed" => seed = option_value(arg, iter.next())?,
+            "--polyphony" => {
+                let voices: usize = option_value(arg, iter.next())?;
+                if voices == 0 {
+                    return Err("--polyphony must be at least 1".to_string());
+                }
+                voice.max_voices = Some(voices);
+            }
+            "--steal" => {
+                let value: String = option_value(arg, iter.next())?;
+                voice.steal_policy = Some(value.parse()?);
+            }
+            "--retrigger" => {
+                let value: String = option_value(arg, iter.next())?;
+                voice.retrigger_policy = Some(value.parse()?);
+            }
+            "--mono" => {
+                let value: String = option_value(arg, iter.next())?;
+                voice.mono = Some(value.parse()?);
+            }
+            "--legato" => voice.legato = true,
+            "--glide" => {
+                let value: String = option_value(arg, iter.next())?;
+                let (ms, curve) = match value.split_once(':') {
+                    Some((ms, "linear")) => (ms, Some(GlideCurve::Linear)),
+                    Some((ms, "exp")) => (ms, Some(GlideCurve::Exponential)),
+                    Some((_, curve)) => return Err(format!("invalid glide curve: {}", curve)),
+                    None => (value.as_str(), None),
+                };
+                let ms: f32 = option_value("--glide", Some(&ms.to_string()))?;
+                if !(ms.is_finite() && ms >= 0.0) {
+                    return Err(format!("invalid value for --glide: {}", value));
+                }
+                voice.glide_ms = Some(ms);
+                voice.glide_curve = curve;
+            }
+            "--sample-accurate" => sample_accurate = true,
+            "--sample" => {
+                let value: String = option_value(arg, iter.next())?;
+                voice.samples.push(SampleOption::parse(&value)?);
+            }
+            "--crossfade" => {
+                let ms: f32 = option_value(arg, iter.next())?;
+                if !(ms.is_finite() && ms >= 0.0) {
+                    return Err(format!("invalid value for --crossfade: {}", ms));
+                }
+                voice.crossfade_ms = Some(ms);
+            }
+            "--fm-level" => {
+                let level: f32 = option_value(arg, iter.next())?;
+                if !(level.is_finite() && level >= 0.0) {
+                    return Err(format!("invalid value for --fm-level: {}", level));
+                }
+                voice.fm_level = Some(level);
+            }
+            "--threads" => {
+                render_threads = option_value(arg, iter.next())?;
+                if render_threads == 0 {
+                    return Err("--threads must be at least 1".to_string());
+                }
+            }
+            "--format" => {
+                let value: String = option_value(arg, iter.next())?;
+                output.format = Some(parse_format(&value)?);
+            }
+            "--dither" => {
+                let value: String = option_value(arg, iter.next())?;
+                output.dither = Some(value.parse()?);
+            }
+            "--channels" => {
+                let channels: u16 = option_value(arg, iter.next())?;
+                if channels == 0 {
+                    return Err("--channels must be at least 1".to_string());
+                }
+                output.channels = Some(channels);
+            }
+            "--rate" => {
+                let rate: u32 = option_value(arg, iter.next())?;
+                if rate == 0 {
+                    return Err("--rate must be at least 1".to_string());
+                }
+                output.sample_rate = Some(rate);
+            }
+            "--oversample" => {
+                oversample = option_value(arg, iter.next())?;
+                if !(1..=16).contains(&oversample) {
+                    return Err("--oversample must be from 1 to 16".to_string());
+                }
+            }
+            "--title" => output.title = Some(option_value(arg, iter.next())?),
+            "--artist" => output.artist = Some(option_value(arg, iter.next())?),
+            "--markers" => output.markers = true,
+            "--normalize" => {
+                let value: String = option_value(arg, iter.next())?;
+                output.normalize = Some(value.parse()?);
+            }
+            "--root" => {
+                let value: String = option_value(arg, iter.next())?;
+                let note = Note::from_scientific_pitch(&value)
+                    .ok_or_else(|| format!("invalid pitch for --root: {}", value))?;
+                output.root = Some(note);
+            }
+            "--loop" => {
+                let value: String = option_value(arg, iter.next())?;
+                let (start, end) = value
+                    .split_once(':')
+                    .ok_or_else(|| format!("--loop expects <start>:<end>: {}", value))?;
+                let start: u32 = option_value("--loop start", Some(&start.to_string()))?;
+                let end: u32 = option_value("--loop end", Some(&end.to_string()))?;
+                if end < start {
+                    return Err(format!("--loop end is before its start: {}", value));
+                }
+                output.sample_loop = Some((start, end));
+            }
+            "--concert" => reference_pitch = Some((CONCERT_A, CONCERT_PITCH)),
+            "--a4" => {
+                let frequency = positive_frequency(arg, option_value(arg, iter.next())?)?;
+                reference_pitch = Some((CONCERT_A, frequency));
+            }
+            "--reference" => {
+                let value: String = option_value(arg, iter.next())?;
+                let (pitch, frequency) = value
+                    .split_once('=')
+                    .ok_or_else(|| format!("--reference expects <pitch>=<hz>: {}", value))?;
+                let note = Note::from_scientific_pitch(pitch)
+                    .ok_or_else(|| format!("invalid pitch for --reference: {}", pitch))?;
+                let frequency = option_value("--reference", Some(&frequency.to_string()))?;
+                reference_pitch = Some((note, positive_frequency(arg, frequency)?));
+            }
+            "--scl" => tuning.scl = Some(option_value(arg, iter.next())?),
+            "--kbm" => tuning.kbm = Some(option_value(arg, iter.next())?),
+            "--edo" => {
+                let value: String = option_value(arg, iter.next())?;
+                let (divisions, period) = value.split_once(':').unwrap_or((&value, "1200"));
+                let divisions: usize = option_value("--edo", Some(&divisions.to_string()))?;
+                let period: f64 = option_value("--edo period", Some(&period.to_string()))?;
+                if divisions == 0 || !(period.is_finite() && period > 0.0) {
+                    return Err(format!("invalid value for --edo: {}", value));
+                }
+                tuning.edo = Some((divisions, period));
+            }
+            "--chord" => {
+                let value: String = option_value(arg, iter.next())?;
+                let intervals = value
+                    .split(',')
+                    .map(|i| option_value("--chord", Some(&i.trim().to_string())))
+                    .collect::<Result<Vec<i32>, _>>()?;
+                transforms.chord = Some(intervals);
+            }
+            "--arp" => {
+                let value: String = option_value(arg, iter.next())?;
+                let mut parts = value.split(':');
+                let pattern: ArpPattern = parts.next().unwrap_or("").parse()?;
+                let mut config = ArpeggiatorConfig {
+                    pattern,
+                    ..Default::default()
+                };
+                if let Some(rate) = parts.next() {
+                    config.rate = option_value("--arp rate", Some(&rate.to_string()))?;
+                }
+                if let Some(octaves) = parts.next() {
+                    config.octaves = option_value("--arp octaves", Some(&octaves.to_string()))?;
+                }
+                if config.rate == 0 || config.octaves == 0 {
+                    return Err("--arp rate and octaves must be at least 1".to_string());
+                }
+                transforms.arp = Some(config);
+            }
+            "--humanize" => {
+                let value: String = option_value(arg, iter.next())?;
+                let (timing, velocity) = value
+                    .split_once(',')
+                    .ok_or_else(|| format!("--humanize expects <timing>,<vel>: {}", value))?;
+                transforms.humanize = Some(HumanizeParams {
+                    timing: option_value("--humanize timing", Some(&timing.to_string()))?,
+                    velocity: option_value("--humanize velocity", Some(&velocity.to_string()))?,
+                    seed: 0,
+                });
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown option: {}", flag)),
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    if let Some(params) = transforms.humanize.as_mut() {
+        params.seed = seed;
+    }
+    if let Some(config) = transforms.arp.as_mut() {
+        config.seed = seed;
+    }
+
+    if output.sample_loop.is_some() && output.root.is_none() {
+        return Err("--loop needs --root".to_string());
+    }
+
+    let mut positional = positional.into_iter();
+    let input_path = positional
+        .next()
+        .ok_or_else(|| "missing input file".to_string())?;
+    let output_path = positional.next();
+    if positional.next().is_some() {
+        return Err("too many arguments".to_string());
+    }
+
+    Ok(Args {
+        input_path,
+        output_path,
+        transforms,
+        voice,
+        tuning,
+        reference_pitch,
+        sample_accurate,
+        render_threads,
+        oversample,
+        output,
+    })
+}
+
+/// Apply the requested transforms to every track
+///
+/// Order: transpose, quantize (in original timesteps), stretch, chord,
+/// arpeggio, humanize.
+fn apply_transforms(tracks: &mut [Track], transforms: &Transforms) -> Result<(), String> {
+    for track in tracks.iter_mut() {
+        if let Some(semitones) = transforms.transpose {
+            track.events = transpose(&track.events, semitones)
+                .map_err(|e| format!("track {}: {}", track.name, e))?;
+        }
+        if let Some(grid) = transforms.quantize {
+            track.events = quantize(&track.events, grid).map_err(|e| e.to_string())?;
+        }
+        if let Some(ratio) = transforms.stretch {
+            track.events = scale_time(&track.events, ratio).map_err(|e| e.to_string())?;
+        }
+        if let Some(intervals) = &transforms.chord {
+            let mut chords = ChordMemory::new(intervals.clone());
+            track.events = apply_processor(&track.events, &mut chords);
+        }
+        if let Some(config) = transforms.arp {
+            track.events = apply_processor(&track.events, &mut Arpeggiator::new(config));
+        }
+        if let Some(params) = &transforms.humanize {
+            track.events = humanize(&track.events, params).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// Built-in instruments selectable from `@track` directives
+fn builtin_instruments(lead: &VoiceConfig) -> HashMap<String, VoiceConfig> {
+    let mut instruments = HashMap::new();
+
+    instruments.insert("lead".to_string(), lead.clone());
+
+    instruments.insert(
+        "bass".to_string(),
+        VoiceConfig {
+            fm_params: FmSynthParams::new(vec![1, 2], vec![1.5, 0.5], 0.1, 0.8),
+            attack_samples: 441, // 10ms at 44.1kHz
+            decay_samples: 6615, // 150ms at 44.1kHz
+            sustain_level: 0.6,
+            release_samples: 4410, // 100ms at 44.1kHz
+            ..Default::default()
+        },
+    );
+
+    instruments.insert(
+        "pad".to_string(),
+        VoiceConfig {
+            fm_params: FmSynthParams::new(vec![1, 3], vec![0.5, 0.3], 0.1, 0.5),
+            attack_samples: 22050, // 500ms at 44.1kHz
+            decay_samples: 22050,  // 500ms at 44.1kHz
+            sustain_level: 0.8,
+            release_samples: 44100, // 1s at 44.1kHz
+            ..Default::default()
+        },
+    );
+
+    instruments
+}
+
+/// Render at `factor` times the sample rate
+///
+/// Durations given in samples (timestep, envelopes, fades) are scaled so
+/// the music plays at the same speed.
+fn oversample(config: &mut PipelineConfig, factor: u32) {
+    let factor_samples = factor as usize;
+    config.sample_rate *= factor;
+    config.timestep_samples *= factor_samples;
+    for voice in std::iter::once(&mut config.voice_config).chain(config.instruments.values_mut()) {
+        voice.attack_samples *= factor_samples;
+        voice.decay_samples *= factor_samples;
+        voice.release_samples *= factor_samples;
+        voice.steal_fade_samples *= factor_samples;
+        voice.glide_samples *= factor_samples;
+    }
+}
+
+/// Apply `--oversample` to `config` and return the spec of the output file
+///
+/// The file keeps the rate `config` was set up with unless `--rate` is
+/// given, however fast the song is rendered.
+fn prepare_output(config: &mut PipelineConfig, args: &Args) -> WavSpec {
+    let spec = args.output.spec(config.sample_rate);
+    if args.oversample > 1 {
+        oversample(config, args.oversample);
+    }
+    spec
+}
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let raw_args: Vec<String> = env::args().skip(1).collect();
 
-    if args.len() < 2 {
+    if raw_args.is_empty() {
         eprintln!("{}", USAGE);
         process::exit(1);
     }
 
-    let input_path = &args[1];
+    let args = match parse_args(&raw_args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("Error: {}\n", e);
+            eprintln!("{}", USAGE);
+            process::exit(1);
+        }
+    };
+    let input_path = &args.input_path;
 
     // Determine output path
-    let output_path = if args.len() >= 3 {
-        args[2].clone()
+    let output_path = if let Some(path) = &args.output_path {
+        path.clone()
     } else {
         // Default: replace .txt with .wav or append .wav
         if input_path.ends_with(".txt") {
@@ -57,15 +740,29 @@ fn main() {
     };
 
     // Parse transcription
-    let events = match parse_transcription(&content) {
-        Ok(events) => events,
+    // Included files are resolved relative to the input file
+    let base_dir = Path::new(input_path).parent().unwrap_or(Path::new(""));
+    let mut tracks = match parse_tracks_with(&content, &FileLoader::new(base_dir)) {
+        Ok(tracks) => tracks,
         Err(e) => {
-            eprintln!("Parse error: {}", e);
+            eprint!("{}", e.render(&content, input_path));
+            eprintln!();
+            eprintln!("{} parse error(s) in {}", e.errors.len(), input_path);
             process::exit(1);
         }
     };
 
-    println!("Parsed {} event groups", events.len());
+    if let Err(e) = apply_transforms(&mut tracks, &args.transforms) {
+        eprintln!("Transform error: {}", e);
+        process::exit(1);
+    }
+
+    let event_groups: usize = tracks.iter().map(|t| t.events.len()).sum();
+    println!(
+        "Parsed {} event groups in {} track(s)",
+        event_groups,
+        tracks.len()
+    );
 
     // Configure pipeline with defaults
     let fm_params = FmSynthParams::new(
@@ -81,31 +778,131 @@ fn main() {
         decay_samples: 8820,  // 200ms at 44.1kHz
         sustain_level: 0.7,
         release_samples: 13230, // 300ms at 44.1kHz
+        ..Default::default()
     };
 
-    let config = PipelineConfig {
+    let mut config = PipelineConfig {
         sample_rate: 44100,
         frame_size: 64,
+        sample_accurate: args.sample_accurate,
         timestep_samples: 11025, // 250ms at 44.1kHz (roughly 1/4 note at 120 BPM)
+        base_frequency: 110.0,   // 1C = 110 Hz
+        tuning: None,
+        instruments: builtin_instruments(&voice_config),
         voice_config,
-        base_frequency: 110.0, // 1C = 110 Hz
+        render_threads: args.render_threads,
     };
 
+    if let Some((note, frequency)) = args.reference_pitch {
+        config = config.with_reference_pitch(note, frequency);
+    }
+    config.tuning = match args.tuning.build(config.base_frequency) {
+        Ok(tuning) => tuning,
+        Err(e) => {
+            eprintln!("Tuning error: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let spec = prepare_output(&mut config, &args);
+
+    let samples = match args.voice.load_samples() {
+        Ok(samples) => samples,
+        Err(e) => {
+            eprintln!("Error reading sample {}", e);
+            process::exit(1);
+        }
+    };
+    args.voice
+        .apply(&mut config.voice_config, config.sample_rate, &samples);
+    for instrument in config.instruments.values_mut() {
+        args.voice.apply(instrument, config.sample_rate, &samples);
+    }
+
     println!("Configuration:");
     println!("  Sample rate: {} Hz", config.sample_rate);
+    if spec.sample_rate != config.sample_rate {
+        println!("  Output rate: {} Hz", spec.sample_rate);
+    }
     println!("  Frame size: {} samples", config.frame_size);
     println!("  Timestep: {} samples", config.timestep_samples);
     println!("  Base frequency: {} Hz", config.base_frequency);
+    if let Some(tuning) = &config.tuning {
+        println!("  Tuning: {}", tuning.scale().description);
+    }
+    for zone in &samples.zones {
+        println!(
+            "  Sample: {} to {}, root {:.2} Hz, {} frames",
+            zone.low_key.scientific_pitch(),
+            zone.high_key.scientific_pitch(),
+            zone.sample.root_frequency(),
+            zone.sample.frames()
+        );
+    }
     println!();
 
+    if tracks.len() > 1 {
+        println!("Tracks:");
+        for track in &tracks {
+            let instrument = track.instrument_name();
+            let default =
+                track.instrument.is_none() && !config.instruments.contains_key(instrument);
+            println!(
+                "  {}: instrument={}{}, gain={}",
+                track.name,
+                instrument,
+                if default { " (default)" } else { "" },
+                track.gain
+            );
+        }
+        println!();
+    }
+
     // Create pipeline and generate audio
-    let mut pipeline = Pipeline::new(config, events);
+    let mut pipeline = match Pipeline::with_tracks(config, tracks) {
+        Ok(pipeline) => pipeline,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
+    if args.output.markers {
+        pipeline = pipeline.with_note_markers();
+    }
+    if let Some(target) = args.output.normalize {
+        pipeline = pipeline.with_normalization(target);
+    }
+    let metadata = match args.output.metadata(&pipeline) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
 
     println!("Generating audio...");
 
-    match pipeline.generate_wav(&output_path) {
+    match pipeline.generate_wav_with_metadata(&output_path, &spec, &metadata) {
         Ok(_) => {
             println!("✓ Generated {}", output_path);
+            let stats = pipeline.voice_stats();
+            if stats.voices_stolen > 0 {
+                println!(
+                    "  {} voice(s) stolen (peak {} voices)",
+                    stats.voices_stolen, stats.peak_voices
+                );
+            }
+            if let (Some(target), Some(loudness)) =
+                (args.output.normalize, pipeline.measured_loudness())
+            {
+                println!(
+                    "  Loudness: {:.1} LUFS, range {:.1} LU, true peak {:.1} dBTP, gain {:+.1} dB",
+                    loudness.integrated,
+                    loudness.range,
+                    loudness.true_peak_db(),
+                    to_db(target.gain(loudness))
+                );
+            }
         }
         Err(e) => {
             eprintln!("Error writing WAV file: {}", e);
@@ -113,3 +910,43 @@ fn main() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corroza::pipeline::parser::parse_transcription;
+
+    fn args(list: &[&str]) -> Args {
+        let list: Vec<String> = list.iter().map(|arg| arg.to_string()).collect();
+        parse_args(&list).unwrap()
+    }
+
+    #[test]
+    fn test_oversampled_output_keeps_default_rate() {
+        let args = args(&["song.txt", "--oversample", "4"]);
+        let mut config = PipelineConfig {
+            sample_rate: 44100,
+            timestep_samples: 1000,
+            ..Default::default()
+        };
+        let spec = prepare_output(&mut config, &args);
+        assert_eq!(config.sample_rate, 176400);
+
+        let path = "/tmp/test_oversampled_output_keeps_default_rate.wav";
+        let events = parse_transcription("+0| 4cd\n+2| 4cu").unwrap();
+        Pipeline::new(config, events)
+            .generate_wav_with(path, &spec)
+            .unwrap();
+        let audio = corroza::wav::read_wav(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(audio.sample_rate, 44100);
+    }
+
+    #[test]
+    fn test_oversampled_output_follows_rate_option() {
+        let args = args(&["song.txt", "--oversample", "2", "--rate", "48000"]);
+        let mut config = PipelineConfig::default();
+        let spec = prepare_output(&mut config, &args);
+        assert_eq!(spec.sample_rate, 48000);
+    }
+}
@@ -49,7 +49,7 @@ fn parse_args() -> Result<Args, Box<dyn std::error::Error>> {
     let output_path = args[7].clone();
 
     // Validate inputs
-    if sustain_level < 0.0 || sustain_level > 1.0 {
+    if !(0.0..=1.0).contains(&sustain_level) {
         return Err("Sustain level must be between 0.0 and 1.0".into());
     }
     if frame_size == 0 {
@@ -70,7 +70,7 @@ fn parse_args() -> Result<Args, Box<dyn std::error::Error>> {
 fn compute_expected_duration(args: &Args) -> usize {
     // Total duration is note_off_sample + release_duration
     // Release duration is rounded up to nearest frame boundary since we process full frames
-    let release_frames = (args.release_samples + args.frame_size - 1) / args.frame_size;
+    let release_frames = args.release_samples.div_ceil(args.frame_size);
     let aligned_release_samples = release_frames * args.frame_size;
     args.note_off_sample.unwrap() + aligned_release_samples
 }
@@ -1,4 +1,4 @@
-use super::{GeneratorState, SignalGenerator};
+use super::{block, GeneratorState, SignalGenerator};
 
 /// ADSR (Attack-Decay-Sustain-Release) envelope generator
 ///
@@ -24,10 +24,12 @@ pub struct AdsrGenerator {
     position: usize,
     sustain_position: usize,
     current_amplitude: f32,
+    attack_start_amplitude: f32,
     release_start_amplitude: f32,
 
     // Event queue
     pending_note_off: bool,
+    pending_retrigger: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -82,8 +84,10 @@ impl AdsrGenerator {
             position: 0,
             sustain_position: 0,
             current_amplitude: initial_amplitude.clamp(0.0, 1.0),
+            attack_start_amplitude: initial_amplitude.clamp(0.0, 1.0),
             release_start_amplitude: 0.0,
             pending_note_off: false,
+            pending_retrigger: false,
         }
     }
 
@@ -95,6 +99,17 @@ impl AdsrGenerator {
         self.pending_note_off = true;
     }
 
+    /// Queue a retrigger event
+    ///
+    /// The event will be processed at the start of the next frame.
+    /// This restarts the Attack phase from the current amplitude, so the
+    /// envelope stays continuous. A note off queued before the retrigger is
+    /// discarded; one queued after it still applies.
+    pub fn retrigger(&mut self) {
+        self.pending_retrigger = true;
+        self.pending_note_off = false;
+    }
+
     /// Get the current amplitude
     ///
     /// Useful for debugging, visualization, or chaining generators.
@@ -124,6 +139,14 @@ impl AdsrGenerator {
 
     /// Process pending events at frame boundary
     fn process_events(&mut self) {
+        if self.pending_retrigger {
+            self.pending_retrigger = false;
+            self.phase = AdsrPhase::Attack;
+            self.attack_start_amplitude = self.current_amplitude;
+            self.position = 0;
+            self.sustain_position = 0;
+        }
+
         if self.pending_note_off {
             self.pending_note_off = false;
             match self.phase {
@@ -144,26 +167,44 @@ impl AdsrGenerator {
         }
     }
 
+    /// Write the part of a ramp phase that falls in `buffer`
+    ///
+    /// Returns the number of samples written; fewer than `buffer.len()`
+    /// means the phase ends inside this buffer.
+    fn ramp(
+        &mut self,
+        buffer: &mut [f32],
+        start_amp: f32,
+        end_amp: f32,
+        total_samples: usize,
+    ) -> usize {
+        let count = buffer
+            .len()
+            .min(total_samples.saturating_sub(self.position));
+        block::line(
+            &mut buffer[..count],
+            start_amp,
+            end_amp,
+            self.position,
+            total_samples,
+        );
+        if count > 0 {
+            self.current_amplitude = buffer[count - 1];
+        }
+        count
+    }
+
     /// Generate samples for the Attack phase
     fn process_attack(&mut self, buffer: &mut [f32]) -> GeneratorState {
-        let start_amp = self.initial_amplitude;
-        let end_amp = 1.0f32;
         let total_samples = self.attack_duration;
+        let count = self.ramp(buffer, self.attack_start_amplitude, 1.0, total_samples);
 
-        for (i, sample) in buffer.iter_mut().enumerate() {
-            let global_pos = self.position + i;
-            if global_pos < total_samples {
-                let t = global_pos as f32 / (total_samples - 1).max(1) as f32;
-                self.current_amplitude = start_amp + (end_amp - start_amp) * t;
-                *sample = self.current_amplitude;
-            } else {
-                // Attack complete - transition to Decay and process remaining samples
-                self.phase = AdsrPhase::Decay;
-                self.position = 0;
-                // Process remaining buffer as Decay
-                self.process_decay(&mut buffer[i..]);
-                return GeneratorState::Running;
-            }
+        if count < buffer.len() {
+            // Attack complete - transition to Decay and process remaining samples
+            self.phase = AdsrPhase::Decay;
+            self.position = 0;
+            self.process_decay(&mut buffer[count..]);
+            return GeneratorState::Running;
         }
 
         self.position += buffer.len();
@@ -179,25 +220,16 @@ impl AdsrGenerator {
 
     /// Generate samples for the Decay phase
     fn process_decay(&mut self, buffer: &mut [f32]) -> GeneratorState {
-        let start_amp = 1.0f32;
-        let end_amp = self.sustain_level;
         let total_samples = self.decay_duration;
+        let count = self.ramp(buffer, 1.0, self.sustain_level, total_samples);
 
-        for (i, sample) in buffer.iter_mut().enumerate() {
-            let global_pos = self.position + i;
-            if global_pos < total_samples {
-                let t = global_pos as f32 / (total_samples - 1).max(1) as f32;
-                self.current_amplitude = start_amp + (end_amp - start_amp) * t;
-                *sample = self.current_amplitude;
-            } else {
-                // Decay complete - transition to Sustain and process remaining samples
-                self.phase = AdsrPhase::Sustain;
-                self.position = 0;
-                self.sustain_position = 0;
-                // Process remaining buffer as Sustain
-                self.process_sustain(&mut buffer[i..]);
-                return GeneratorState::Running;
-            }
+        if count < buffer.len() {
+            // Decay complete - transition to Sustain and process remaining samples
+            self.phase = AdsrPhase::Sustain;
+            self.position = 0;
+            self.sustain_position = 0;
+            self.process_sustain(&mut buffer[count..]);
+            return GeneratorState::Running;
         }
 
         self.position += buffer.len();
@@ -216,9 +248,7 @@ impl AdsrGenerator {
     fn process_sustain(&mut self, buffer: &mut [f32]) -> GeneratorState {
         // Hold at sustain level
         self.current_amplitude = self.sustain_level;
-        for sample in buffer.iter_mut() {
-            *sample = self.sustain_level;
-        }
+        buffer.fill(self.sustain_level);
 
         self.sustain_position += buffer.len();
 
@@ -232,25 +262,15 @@ impl AdsrGenerator {
 
     /// Generate samples for the Release phase
     fn process_release(&mut self, buffer: &mut [f32]) -> GeneratorState {
-        let start_amp = self.release_start_amplitude;
-        let end_amp = 0.0f32;
         let total_samples = self.release_duration;
+        let count = self.ramp(buffer, self.release_start_amplitude, 0.0, total_samples);
 
-        for (i, sample) in buffer.iter_mut().enumerate() {
-            let global_pos = self.position + i;
-            if global_pos < total_samples {
-                let t = global_pos as f32 / (total_samples - 1).max(1) as f32;
-                self.current_amplitude = start_amp + (end_amp - start_amp) * t;
-                *sample = self.current_amplitude;
-            } else {
-                // Release complete - transition to Complete and fill remaining with zeros
-                self.phase = AdsrPhase::Complete;
-                self.current_amplitude = end_amp;
-                for j in i..buffer.len() {
-                    buffer[j] = end_amp;
-                }
-                return GeneratorState::Complete;
-            }
+        if count < buffer.len() {
+            // Release complete - transition to Complete and fill remaining with zeros
+            self.phase = AdsrPhase::Complete;
+            self.current_amplitude = 0.0;
+            buffer[count..].fill(0.0);
+            return GeneratorState::Complete;
         }
 
         self.position += buffer.len();
@@ -278,9 +298,7 @@ impl SignalGenerator for AdsrGenerator {
             AdsrPhase::Complete => {
                 // Fill with zeros after completion
                 self.current_amplitude = 0.0;
-                for sample in buffer.iter_mut() {
-                    *sample = 0.0;
-                }
+                buffer.fill(0.0);
                 GeneratorState::Complete
             }
         }
@@ -295,8 +313,10 @@ impl SignalGenerator for AdsrGenerator {
         self.position = 0;
         self.sustain_position = 0;
         self.current_amplitude = self.initial_amplitude;
+        self.attack_start_amplitude = self.initial_amplitude;
         self.release_start_amplitude = 0.0;
         self.pending_note_off = false;
+        self.pending_retrigger = false;
     }
 }
 
@@ -586,6 +606,32 @@ mod tests {
         assert!(!adsr.is_complete());
     }
 
+    #[test]
+    fn test_retrigger_from_current_amplitude() {
+        let mut adsr = create_adsr(0.0, 100, 100, 0.5, 2000, 100);
+        let mut buffer = [0.0f32; 100];
+
+        // Reach sustain, then release partway
+        adsr.process(&mut buffer);
+        adsr.process(&mut buffer);
+        adsr.note_off();
+        adsr.process(&mut buffer[..50]);
+        let level = adsr.current_amplitude();
+        assert_eq!(adsr.phase(), AdsrPhase::Release);
+
+        // Retrigger restarts the attack without a jump
+        adsr.retrigger();
+        adsr.process(&mut buffer);
+        assert_eq!(adsr.phase(), AdsrPhase::Decay);
+        assert!((buffer[0] - level).abs() < 0.001);
+        assert_eq!(buffer[99], 1.0);
+
+        // Reset goes back to the configured initial amplitude
+        adsr.reset();
+        adsr.process(&mut buffer);
+        assert_eq!(buffer[0], 0.0);
+    }
+
     #[test]
     fn test_frame_boundary_event_processing() {
         let mut adsr = create_adsr(0.0, 100, 100, 0.5, 2000, 100);
@@ -1,6 +1,11 @@
 use super::adsr::AdsrGenerator;
-use super::{GeneratorState, SignalGenerator};
+use super::sine::fast_sin;
+use super::{GeneratorState, SignalGenerator, MAX_FRAME_SIZE};
 use std::f32::consts::PI;
+use std::f64::consts::TAU;
+
+/// Largest phase increment a note is played at, just below Nyquist
+pub const MAX_PHASE_PER_SAMPLE: f32 = PI * 0.999;
 
 /// Parameters for FM synthesis
 ///
@@ -57,6 +62,57 @@ impl FmSynthParams {
     }
 }
 
+/// Shape of a pitch glide between two frequencies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlideCurve {
+    /// Frequency moves linearly in Hz
+    Linear,
+    /// Frequency moves by a constant ratio per sample (linear in pitch)
+    Exponential,
+}
+
+/// An in-progress pitch glide
+///
+/// Slides any positive per-sample rate (a phase increment here, a
+/// playback rate in the sampler).
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Glide {
+    start: f32,
+    target: f32,
+    length: usize,
+    position: usize,
+    curve: GlideCurve,
+}
+
+impl Glide {
+    pub(super) fn new(start: f32, target: f32, length: usize, curve: GlideCurve) -> Self {
+        Self {
+            start,
+            target,
+            length,
+            position: 0,
+            curve,
+        }
+    }
+
+    /// Rate for the next sample
+    pub(super) fn next_phase_per_sample(&mut self) -> f32 {
+        self.position += 1;
+        if self.position >= self.length {
+            return self.target;
+        }
+        let t = self.position as f32 / self.length as f32;
+        match self.curve {
+            GlideCurve::Linear => self.start + (self.target - self.start) * t,
+            GlideCurve::Exponential => self.start * (self.target / self.start).powf(t),
+        }
+    }
+
+    pub(super) fn is_done(&self) -> bool {
+        self.position >= self.length
+    }
+}
+
 /// FM Synthesis generator
 ///
 /// Generates audio using frequency modulation synthesis with dual ADSR envelopes.
@@ -67,11 +123,25 @@ impl FmSynthParams {
 /// 1. Compute modulation signal: m[n] = Σ amps[i] * sin(harmonics[i] * phase_per_sample * n)
 /// 2. Get envelope values: e[n] from mod_env, E[n] from wav_env
 /// 3. Instantaneous frequency: f[n] = phase_per_sample * (1 + m[n] * mod_depth * e[n])
-/// 4. Phase accumulation: θ[n] = θ[n-1] + 2π * f[n] (wrapped to [0, 2π))
+/// 4. Phase accumulation: θ[n] = θ[n-1] + f[n] (wrapped to [0, 2π))
 /// 5. Output: y[n] = sin(θ[n]) * E[n]
+///
+/// Each harmonic keeps its own wrapped phase accumulator instead of computing
+/// harmonics[i] * phase_per_sample * n, so precision doesn't degrade on long
+/// notes. Sines use [`fast_sin`].
+///
+/// The pitch can change while the generator runs (see [`set_phase_per_sample`],
+/// [`glide_to`] and [`set_pitch_ratio`]); the modulator phases carry over
+/// so the modulation stays continuous. The pitch ratio (for pitch bend) scales
+/// phase_per_sample on top of any glide.
+///
+/// [`set_phase_per_sample`]: FmSynthGenerator::set_phase_per_sample
+/// [`glide_to`]: FmSynthGenerator::glide_to
+/// [`set_pitch_ratio`]: FmSynthGenerator::set_pitch_ratio
 pub struct FmSynthGenerator {
     // Parameters
     params: FmSynthParams,
+    initial_phase_per_sample: f32,
 
     // Envelopes
     mod_env: AdsrGenerator,
@@ -80,6 +150,23 @@ pub struct FmSynthGenerator {
     // State
     phase: f32,
     sample_count: usize,
+    /// Phase of each modulation harmonic, wrapped to [0, 2π)
+    ///
+    /// Kept in f64 so long notes don't drift.
+    mod_phases: Vec<f64>,
+    glide: Option<Glide>,
+    /// Frequency multiplier applied on top of phase_per_sample
+    pitch_ratio: f32,
+
+    // Scratch space for per-sample values (MAX_FRAME_SIZE samples each)
+    mod_env_buffer: Box<[f32]>,
+    wav_env_buffer: Box<[f32]>,
+    /// Phase increment, then instantaneous frequency
+    freq_buffer: Box<[f32]>,
+    /// Modulation signal
+    mod_buffer: Box<[f32]>,
+    /// Modulator phases, then carrier phases
+    phase_buffer: Box<[f32]>,
 }
 
 impl FmSynthGenerator {
@@ -129,32 +216,160 @@ impl FmSynthGenerator {
         }
 
         Self {
+            initial_phase_per_sample: params.phase_per_sample,
+            mod_phases: vec![0.0; params.harmonics.len()],
             params,
             mod_env,
             wav_env,
             phase: 0.0,
             sample_count: 0,
+            glide: None,
+            pitch_ratio: 1.0,
+            mod_env_buffer: vec![0.0; MAX_FRAME_SIZE].into_boxed_slice(),
+            wav_env_buffer: vec![0.0; MAX_FRAME_SIZE].into_boxed_slice(),
+            freq_buffer: vec![0.0; MAX_FRAME_SIZE].into_boxed_slice(),
+            mod_buffer: vec![0.0; MAX_FRAME_SIZE].into_boxed_slice(),
+            phase_buffer: vec![0.0; MAX_FRAME_SIZE].into_boxed_slice(),
         }
     }
 
-    /// Compute the modulation signal m[n]
+    /// Start over with new parameters and envelopes, reusing allocations
     ///
-    /// m[n] = Σ amps[i] * sin(harmonics[i] * phase_per_sample * n)
+    /// Leaves the generator in the same state as [`FmSynthGenerator::new`]
+    /// would, without allocating as long as `params` has no more harmonics
+    /// than the previous parameters.
+    pub fn restart(
+        &mut self,
+        params: &FmSynthParams,
+        mod_env: AdsrGenerator,
+        wav_env: AdsrGenerator,
+    ) {
+        // Field by field: a derived clone_from would reallocate the vectors
+        self.params.harmonics.clone_from(&params.harmonics);
+        self.params.amps.clone_from(&params.amps);
+        self.params.phase_per_sample = params.phase_per_sample;
+        self.params.mod_depth = params.mod_depth;
+        self.initial_phase_per_sample = params.phase_per_sample;
+        self.mod_env = mod_env;
+        self.wav_env = wav_env;
+        self.phase = 0.0;
+        self.sample_count = 0;
+        self.mod_phases.clear();
+        self.mod_phases.resize(params.harmonics.len(), 0.0);
+        self.glide = None;
+        self.pitch_ratio = 1.0;
+    }
+
+    /// Modulation signal m[n] at the current sample
+    ///
+    /// m[n] = Σ amps[i] * sin(φi[n]), where each harmonic's phase φi
+    /// advances by harmonics[i] * phase_per_sample every sample, so pitch
+    /// changes never jump the phase. `process_chunk` computes the same sum
+    /// for a whole chunk at once.
+    #[cfg(test)]
     fn compute_modulation(&self) -> f32 {
         let mut modulation = 0.0f32;
-        for (i, &harmonic) in self.params.harmonics.iter().enumerate() {
-            let mod_phase =
-                harmonic as f32 * self.params.phase_per_sample * self.sample_count as f32;
-            modulation += self.params.amps[i] * mod_phase.sin();
+        for (amp, &mod_phase) in self.params.amps.iter().zip(&self.mod_phases) {
+            modulation += amp * fast_sin(mod_phase as f32);
         }
         modulation
     }
 
+    /// Phase increment including the pitch ratio (kept below PI)
+    fn effective_phase_per_sample(&self) -> f32 {
+        (self.params.phase_per_sample * self.pitch_ratio).min(PI)
+    }
+
+    /// Change the phase increment
+    fn apply_phase_per_sample(&mut self, phase_per_sample: f32) {
+        self.params.phase_per_sample = phase_per_sample;
+    }
+
+    /// Get the current pitch ratio
+    pub fn pitch_ratio(&self) -> f32 {
+        self.pitch_ratio
+    }
+
+    /// Scale the pitch by a ratio (e.g. from pitch bend)
+    ///
+    /// The ratio multiplies phase_per_sample, including during a glide.
+    ///
+    /// # Panics
+    /// Panics if ratio is not positive
+    pub fn set_pitch_ratio(&mut self, ratio: f32) {
+        assert!(ratio > 0.0, "pitch ratio must be positive");
+        self.pitch_ratio = ratio;
+    }
+
+    /// Get the current modulation depth
+    pub fn mod_depth(&self) -> f32 {
+        self.params.mod_depth
+    }
+
+    /// Change the modulation depth (e.g. from a mod wheel)
+    pub fn set_mod_depth(&mut self, mod_depth: f32) {
+        self.params.mod_depth = mod_depth.max(0.0);
+    }
+
+    /// Get the current phase increment per sample
+    pub fn phase_per_sample(&self) -> f32 {
+        self.params.phase_per_sample
+    }
+
+    /// Jump to a new pitch immediately, cancelling any glide
+    ///
+    /// # Panics
+    /// Panics if phase_per_sample is not between 0 and PI
+    pub fn set_phase_per_sample(&mut self, phase_per_sample: f32) {
+        assert!(
+            phase_per_sample > 0.0 && phase_per_sample < PI,
+            "phase_per_sample must be between 0 and PI"
+        );
+        self.glide = None;
+        self.apply_phase_per_sample(phase_per_sample);
+    }
+
+    /// Slide from the current pitch to a new one
+    ///
+    /// # Arguments
+    /// * `phase_per_sample` - Target phase increment per sample
+    /// * `samples` - Glide duration in samples (0 jumps immediately)
+    /// * `curve` - Linear or exponential frequency slide
+    ///
+    /// # Panics
+    /// Panics if phase_per_sample is not between 0 and PI
+    pub fn glide_to(&mut self, phase_per_sample: f32, samples: usize, curve: GlideCurve) {
+        if samples == 0 {
+            self.set_phase_per_sample(phase_per_sample);
+            return;
+        }
+        assert!(
+            phase_per_sample > 0.0 && phase_per_sample < PI,
+            "phase_per_sample must be between 0 and PI"
+        );
+        self.glide = Some(Glide::new(
+            self.params.phase_per_sample,
+            phase_per_sample,
+            samples,
+            curve,
+        ));
+    }
+
+    /// Check if a pitch glide is in progress
+    pub fn is_gliding(&self) -> bool {
+        self.glide.is_some()
+    }
+
     /// Get the current phase
     pub fn phase(&self) -> f32 {
         self.phase
     }
 
+    /// Get the current output amplitude (waveform envelope level)
+    pub fn amplitude(&self) -> f32 {
+        self.wav_env.current_amplitude()
+    }
+
     /// Get the current sample count
     pub fn sample_count(&self) -> usize {
         self.sample_count
@@ -168,46 +383,123 @@ impl FmSynthGenerator {
         self.mod_env.note_off();
         self.wav_env.note_off();
     }
+
+    /// Restart both envelopes from their current levels
+    ///
+    /// Used when a new note reuses a sounding voice without legato.
+    pub fn retrigger(&mut self) {
+        self.mod_env.retrigger();
+        self.wav_env.retrigger();
+    }
 }
 
 impl SignalGenerator for FmSynthGenerator {
     fn process(&mut self, buffer: &mut [f32]) -> GeneratorState {
-        let two_pi = 2.0f32 * PI;
+        if buffer.len() <= MAX_FRAME_SIZE {
+            return self.process_chunk(buffer);
+        }
+        let mut state = GeneratorState::Running;
+        for chunk in buffer.chunks_mut(MAX_FRAME_SIZE) {
+            state = self.process_chunk(chunk);
+        }
+        state
+    }
 
-        // Process envelopes to get per-sample envelope values
-        let mut mod_env_buffer = vec![0.0f32; buffer.len()];
-        let mut wav_env_buffer = vec![0.0f32; buffer.len()];
-        let mod_state = self.mod_env.process(&mut mod_env_buffer);
-        let wav_state = self.wav_env.process(&mut wav_env_buffer);
+    fn is_complete(&self) -> bool {
+        self.mod_env.is_complete() && self.wav_env.is_complete()
+    }
 
-        for (i, sample) in buffer.iter_mut().enumerate() {
-            // 1. Compute modulation signal
-            let modulation = self.compute_modulation();
+    fn reset(&mut self) {
+        self.mod_env.reset();
+        self.wav_env.reset();
+        self.phase = 0.0;
+        self.sample_count = 0;
+        self.params.phase_per_sample = self.initial_phase_per_sample;
+        self.mod_phases.fill(0.0);
+        self.glide = None;
+        self.pitch_ratio = 1.0;
+    }
+}
 
-            // 2. Get envelope values for this specific sample (not cached value)
-            let mod_env_val = mod_env_buffer[i];
-            let wav_env_val = wav_env_buffer[i];
+impl FmSynthGenerator {
+    /// Render at most MAX_FRAME_SIZE samples
+    ///
+    /// Works in passes over the whole chunk rather than sample by sample:
+    /// the phase accumulators are short serial scans, and everything else
+    /// (sines, envelopes, frequency) is independent per sample, so those
+    /// loops vectorise.
+    fn process_chunk(&mut self, buffer: &mut [f32]) -> GeneratorState {
+        let len = buffer.len();
 
-            // 3. Instantaneous frequency: f[n] = g * (1 + m[n] * mod_depth * e[n])
-            let modulation_factor = modulation * self.params.mod_depth * mod_env_val;
-            let inst_freq = self.params.phase_per_sample * (1.0 + modulation_factor).max(0.0);
+        // Process envelopes to get per-sample envelope values
+        let mod_state = self.mod_env.process(&mut self.mod_env_buffer[..len]);
+        let wav_state = self.wav_env.process(&mut self.wav_env_buffer[..len]);
+
+        // Phase increment for each sample, advancing any pitch glide
+        self.fill_phase_increments(len);
+
+        // 1. Modulation signal, one harmonic at a time
+        let modulation = &mut self.mod_buffer[..len];
+        let phases = &mut self.phase_buffer[..len];
+        let increments = &self.freq_buffer[..len];
+        modulation.fill(0.0);
+        for ((&amp, &harmonic), mod_phase) in self
+            .params
+            .amps
+            .iter()
+            .zip(&self.params.harmonics)
+            .zip(self.mod_phases.iter_mut())
+        {
+            // Serial scan: φ[n] advances by harmonic * phase_per_sample[n]
+            for (phase, &increment) in phases.iter_mut().zip(increments) {
+                *phase = *mod_phase as f32;
+                *mod_phase += harmonic as f64 * increment as f64;
+                while *mod_phase >= TAU {
+                    *mod_phase -= TAU;
+                }
+            }
+            for (value, &phase) in modulation.iter_mut().zip(phases.iter()) {
+                *value += amp * fast_sin(phase);
+            }
+        }
 
-            // 4. Phase accumulation with wrapping to [0, 2π)
-            self.phase += two_pi * inst_freq;
+        // 2-3. Instantaneous frequency: f[n] = g * (1 + m[n] * mod_depth * e[n])
+        let mod_depth = self.params.mod_depth;
+        let frequencies = &mut self.freq_buffer[..len];
+        for ((frequency, &value), &mod_env_val) in frequencies
+            .iter_mut()
+            .zip(modulation.iter())
+            .zip(&self.mod_env_buffer[..len])
+        {
+            let modulation_factor = value * mod_depth * mod_env_val;
+            *frequency *= (1.0 + modulation_factor).max(0.0);
+        }
+
+        // 4. Phase accumulation (frequencies are in radians per sample)
+        // with wrapping to [0, 2π)
+        let two_pi = 2.0f32 * PI;
+        for (phase, &frequency) in phases.iter_mut().zip(frequencies.iter()) {
+            self.phase += frequency;
             while self.phase >= two_pi {
                 self.phase -= two_pi;
             }
             while self.phase < 0.0 {
                 self.phase += two_pi;
             }
+            *phase = self.phase;
+        }
 
-            // 5. Final output: y[n] = sin(θ[n]) * E[n]
-            *sample = self.phase.sin() * wav_env_val;
-
-            // Advance sample count
-            self.sample_count += 1;
+        // 5. Final output: y[n] = sin(θ[n]) * E[n]
+        for ((sample, &phase), &wav_env_val) in buffer
+            .iter_mut()
+            .zip(phases.iter())
+            .zip(&self.wav_env_buffer[..len])
+        {
+            *sample = fast_sin(phase) * wav_env_val;
         }
 
+        self.sample_count += len;
+
         // Complete when both envelopes are complete
         if mod_state == GeneratorState::Complete && wav_state == GeneratorState::Complete {
             GeneratorState::Complete
@@ -216,15 +508,23 @@ impl SignalGenerator for FmSynthGenerator {
         }
     }
 
-    fn is_complete(&self) -> bool {
-        self.mod_env.is_complete() && self.wav_env.is_complete()
-    }
-
-    fn reset(&mut self) {
-        self.mod_env.reset();
-        self.wav_env.reset();
-        self.phase = 0.0;
-        self.sample_count = 0;
+    /// Fill `freq_buffer` with the phase increment of each sample
+    fn fill_phase_increments(&mut self, len: usize) {
+        if self.glide.is_none() {
+            let phase_per_sample = self.effective_phase_per_sample();
+            self.freq_buffer[..len].fill(phase_per_sample);
+            return;
+        }
+        for i in 0..len {
+            if let Some(glide) = self.glide.as_mut() {
+                let phase_per_sample = glide.next_phase_per_sample();
+                if glide.is_done() {
+                    self.glide = None;
+                }
+                self.apply_phase_per_sample(phase_per_sample);
+            }
+            self.freq_buffer[i] = self.effective_phase_per_sample();
+        }
     }
 }
 
@@ -243,6 +543,20 @@ mod tests {
         (mod_env, wav_env)
     }
 
+    #[test]
+    fn test_sounds_at_phase_per_sample() {
+        // phase_per_sample is in radians, so 2π * 440 / 44100 is 440 Hz
+        let phase_per_sample = 2.0 * PI * 440.0 / 44100.0;
+        let params = FmSynthParams::new(vec![2], vec![1.0], phase_per_sample, 0.0);
+        let env = || AdsrGenerator::new(1.0, 0, 0, 1.0, 44100, 100);
+        let mut fm = FmSynthGenerator::new(params, env(), env());
+
+        let mut output = vec![0.0f32; 22050];
+        fm.process(&mut output);
+        let frequency = crate::pitch_check::measure_frequency(&output, 44100);
+        assert!((frequency - 440.0).abs() < 0.1, "{} Hz", frequency);
+    }
+
     #[test]
     fn test_fm_synth_params_creation() {
         let params = FmSynthParams::new(vec![2, 5, 9], vec![1.0, 2.0, 1.0], 0.1, 1.0);
@@ -279,7 +593,7 @@ mod tests {
         // Output should be bounded
         for &sample in buffer.iter() {
             assert!(
-                sample >= -1.0 && sample <= 1.0,
+                (-1.0..=1.0).contains(&sample),
                 "Sample {} out of bounds",
                 sample
             );
@@ -364,6 +678,91 @@ mod tests {
         assert!(!fm.is_complete());
     }
 
+    #[test]
+    fn test_pitch_change_keeps_modulation_continuous() {
+        let params = FmSynthParams::new(vec![1], vec![1.0], 0.1, 1.0);
+        let (mod_env, wav_env) = create_test_envs();
+        let mut fm = FmSynthGenerator::new(params, mod_env, wav_env);
+
+        let mut buffer = [0.0f32; 37];
+        fm.process(&mut buffer);
+        let before = fm.compute_modulation();
+
+        fm.set_phase_per_sample(0.2);
+        assert_eq!(fm.phase_per_sample(), 0.2);
+        assert!((fm.compute_modulation() - before).abs() < 1e-4);
+
+        // One sample later the modulator has advanced by the new increment
+        fm.process(&mut buffer[..1]);
+        let expected = (37.0f32 * 0.1 + 0.2).sin();
+        assert!((fm.compute_modulation() - expected).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_glide_reaches_target() {
+        for curve in [GlideCurve::Linear, GlideCurve::Exponential] {
+            let params = FmSynthParams::new(vec![], vec![], 0.1, 1.0);
+            let (mod_env, wav_env) = create_test_envs();
+            let mut fm = FmSynthGenerator::new(params, mod_env, wav_env);
+
+            fm.glide_to(0.4, 100, curve);
+            let mut buffer = [0.0f32; 50];
+            fm.process(&mut buffer);
+            let midway = fm.phase_per_sample();
+            assert!(fm.is_gliding());
+            assert!(midway > 0.1 && midway < 0.4);
+
+            fm.process(&mut buffer);
+            assert!(!fm.is_gliding());
+            assert_eq!(fm.phase_per_sample(), 0.4);
+
+            // Exponential glides are at the geometric midpoint half way
+            let expected = match curve {
+                GlideCurve::Linear => 0.25,
+                GlideCurve::Exponential => 0.2,
+            };
+            assert!((midway - expected).abs() < 1e-4, "{:?}: {}", curve, midway);
+        }
+    }
+
+    #[test]
+    fn test_pitch_ratio_scales_frequency() {
+        let params = FmSynthParams::new(vec![], vec![], 0.05, 1.0);
+        let mod_env = AdsrGenerator::new(1.0, 1, 1, 1.0, 10000, 1);
+        let wav_env = AdsrGenerator::new(1.0, 1, 1, 1.0, 10000, 1);
+        let mut fm = FmSynthGenerator::new(params, mod_env, wav_env);
+
+        let mut buffer = [0.0f32; 1];
+        fm.process(&mut buffer);
+        let before = fm.phase();
+        fm.process(&mut buffer);
+        let step = fm.phase() - before;
+
+        fm.set_pitch_ratio(2.0);
+        let before = fm.phase();
+        fm.process(&mut buffer);
+        assert!((fm.phase() - before - 2.0 * step).abs() < 1e-4);
+
+        // The base pitch is unchanged
+        assert_eq!(fm.phase_per_sample(), 0.05);
+        assert_eq!(fm.pitch_ratio(), 2.0);
+    }
+
+    #[test]
+    fn test_reset_restores_pitch() {
+        let params = create_test_params();
+        let (mod_env, wav_env) = create_test_envs();
+        let mut fm = FmSynthGenerator::new(params, mod_env, wav_env);
+
+        fm.glide_to(0.3, 1000, GlideCurve::Linear);
+        let mut buffer = [0.0f32; 10];
+        fm.process(&mut buffer);
+        fm.reset();
+
+        assert_eq!(fm.phase_per_sample(), 0.1);
+        assert!(!fm.is_gliding());
+    }
+
     #[test]
     fn test_is_complete() {
         // Very short envelopes
@@ -433,7 +832,7 @@ mod tests {
 
         // Check that output is bounded
         for &sample in buffer.iter() {
-            assert!(sample >= -1.0 && sample <= 1.0);
+            assert!((-1.0..=1.0).contains(&sample));
         }
     }
 
@@ -533,7 +932,7 @@ mod tests {
 
         for (i, &sample) in buffer.iter().enumerate() {
             assert!(
-                sample >= -1.0 && sample <= 1.0,
+                (-1.0..=1.0).contains(&sample),
                 "Sample {} clips: {} (outside [-1.0, 1.0])",
                 i,
                 sample
@@ -632,9 +1031,125 @@ mod tests {
 
         let final_phase = fm.phase();
         assert!(
-            final_phase >= 0.0 && final_phase < 2.0 * PI,
+            (0.0..2.0 * PI).contains(&final_phase),
             "Phase out of valid range: {}",
             final_phase
         );
     }
+
+    #[test]
+    fn test_process_does_not_allocate() {
+        let mod_env = AdsrGenerator::new(0.0, 100, 100, 0.5, 2000, 100);
+        let wav_env = AdsrGenerator::new(0.0, 100, 100, 0.5, 2000, 100);
+        let mut fm = FmSynthGenerator::new(create_test_params(), mod_env, wav_env);
+
+        let mut buffer = vec![0.0f32; MAX_FRAME_SIZE * 3 + 7];
+        let ((), allocations) = crate::alloc_check::count_allocations(|| {
+            fm.process(&mut buffer[..64]);
+            fm.process(&mut buffer);
+        });
+        assert_eq!(allocations, 0);
+    }
+
+    #[test]
+    fn test_restart_matches_new() {
+        let envelopes = || {
+            (
+                AdsrGenerator::new(0.0, 100, 100, 0.5, 2000, 100),
+                AdsrGenerator::new(0.0, 100, 100, 0.5, 2000, 100),
+            )
+        };
+        let (mod_env, wav_env) = envelopes();
+        let mut reused = FmSynthGenerator::new(create_test_params(), mod_env, wav_env);
+        reused.glide_to(0.2, 50, GlideCurve::Linear);
+        reused.process(&mut [0.0; 300]);
+
+        let mut params = create_test_params();
+        params.phase_per_sample = 0.05;
+        let (mod_env, wav_env) = envelopes();
+        let ((), allocations) = crate::alloc_check::count_allocations(|| {
+            reused.restart(&params, mod_env, wav_env);
+        });
+        assert_eq!(allocations, 0);
+
+        let (mod_env, wav_env) = envelopes();
+        let mut fresh = FmSynthGenerator::new(params, mod_env, wav_env);
+        let mut a = [0.0f32; 500];
+        let mut b = [0.0f32; 500];
+        reused.process(&mut a);
+        fresh.process(&mut b);
+        assert_eq!(a, b);
+    }
+
+    /// The previous algorithm: sin() of harmonic * phase_per_sample * n
+    fn reference_render(params: &FmSynthParams, mod_env: &[f32], wav_env: &[f32]) -> Vec<f32> {
+        let mut phase = 0.0f32;
+        (0..mod_env.len())
+            .map(|n| {
+                let modulation: f32 = params
+                    .harmonics
+                    .iter()
+                    .zip(&params.amps)
+                    .map(|(&h, amp)| amp * (h as f32 * params.phase_per_sample * n as f32).sin())
+                    .sum();
+                let factor = modulation * params.mod_depth * mod_env[n];
+                phase += params.phase_per_sample * (1.0 + factor).max(0.0);
+                phase = phase.rem_euclid(2.0 * PI);
+                phase.sin() * wav_env[n]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_previous_algorithm() {
+        let params = FmSynthParams::new(vec![2, 5, 9], vec![1.0, 2.0, 1.0], 0.02, 1.0);
+        let envelopes = || {
+            (
+                AdsrGenerator::new(0.0, 100, 300, 0.5, 5000, 100),
+                AdsrGenerator::new(0.0, 200, 200, 0.6, 5000, 100),
+            )
+        };
+        let (mod_env, wav_env) = envelopes();
+        let mut fm = FmSynthGenerator::new(params.clone(), mod_env, wav_env);
+
+        let mut output = vec![0.0f32; 2000];
+        fm.process(&mut output);
+
+        let (mut mod_env, mut wav_env) = envelopes();
+        let mut mod_values = vec![0.0f32; 2000];
+        let mut wav_values = vec![0.0f32; 2000];
+        mod_env.process(&mut mod_values);
+        wav_env.process(&mut wav_values);
+        let reference = reference_render(&params, &mod_values, &wav_values);
+
+        let max_error = output
+            .iter()
+            .zip(&reference)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0f32, f32::max);
+        assert!(max_error < 1e-3, "max error {}", max_error);
+    }
+
+    #[test]
+    fn test_modulator_phase_does_not_drift() {
+        let params = FmSynthParams::new(vec![3, 7], vec![1.0, 1.0], 0.0371, 1.0);
+        let (mod_env, wav_env) = create_test_envs();
+        let mut fm = FmSynthGenerator::new(params, mod_env, wav_env);
+
+        // About 23 seconds at 44.1kHz
+        let samples = 1_000_000;
+        let mut buffer = vec![0.0f32; MAX_FRAME_SIZE];
+        for _ in 0..samples / MAX_FRAME_SIZE {
+            fm.process(&mut buffer);
+        }
+        let n = (samples / MAX_FRAME_SIZE * MAX_FRAME_SIZE) as f64;
+
+        for (&harmonic, &mod_phase) in [3.0, 7.0].iter().zip(&fm.mod_phases) {
+            let exact = (harmonic * 0.0371f32 as f64 * n).rem_euclid(TAU);
+            let error = (mod_phase - exact)
+                .abs()
+                .min(TAU - (mod_phase - exact).abs());
+            assert!(error < 1e-6, "harmonic {}: error {}", harmonic, error);
+        }
+    }
 }
@@ -1,10 +1,20 @@
 pub mod adsr;
+pub mod block;
 pub mod fm_synth;
 pub mod ramp;
+pub mod sampler;
+pub mod sine;
 
 pub use adsr::{AdsrGenerator, AdsrPhase};
-pub use fm_synth::{FmSynthGenerator, FmSynthParams};
+pub use fm_synth::{FmSynthGenerator, FmSynthParams, GlideCurve};
 pub use ramp::RampGenerator;
+pub use sampler::{Sample, SamplerGenerator, SustainLoop};
+
+/// Largest buffer a generator renders in one pass
+///
+/// Generators that need scratch space allocate it once for this many
+/// samples; longer buffers are rendered in pieces.
+pub const MAX_FRAME_SIZE: usize = 512;
 
 /// Represents the current state of a signal generator
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
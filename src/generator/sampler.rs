@@ -0,0 +1,717 @@
+//! Sample playback
+//!
+//! [`SamplerGenerator`] plays a recorded [`Sample`] at any pitch:
+//! - Band-limited sinc interpolation from a polyphase table, with a lower
+//!   cutoff when playing more than an octave above the recording
+//! - An optional sustain loop, repeated while the note is held, with a
+//!   crossfade baked into the loop end so the seam does not click
+//! - On release the loop is left at the next point where the looped and
+//!   unlooped audio agree, and playback continues into the rest of the
+//!   recording while the release envelope fades it out
+
+use super::adsr::AdsrGenerator;
+use super::fm_synth::{Glide, GlideCurve};
+use super::{GeneratorState, SignalGenerator};
+use crate::resample::sinc_row;
+use crate::wav::{read_wav, SamplerInfo, WavAudio, WavError};
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+/// Interpolation filter length in input samples
+const SINC_TAPS: usize = 32;
+
+/// Taps on each side of the output position
+const HALF_TAPS: usize = SINC_TAPS / 2;
+
+/// Precomputed filter phases; positions in between blend neighbouring rows
+const SINC_PHASES: usize = 256;
+
+/// Filter tables for playback rates up to 1, 2 and 4 times the recording
+/// rate; faster playback uses the last one
+const SINC_BANDS: usize = 3;
+
+/// Samples rendered per envelope pass (kept on the stack)
+const ENVELOPE_CHUNK: usize = 64;
+
+/// Sustain length of the envelope: the note is held until `note_off`
+const HOLD_SAMPLES: usize = u32::MAX as usize;
+
+/// MIDI note assumed for recordings without a `smpl` chunk (middle C)
+const DEFAULT_ROOT_NOTE: u8 = 60;
+
+/// Interpolation filters: `SINC_BANDS` tables of `SINC_PHASES + 1` rows
+fn sinc_tables() -> &'static [f32] {
+    static TABLES: OnceLock<Vec<f32>> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let mut tables = Vec::with_capacity(SINC_BANDS * (SINC_PHASES + 1) * SINC_TAPS);
+        for band in 0..SINC_BANDS {
+            let scale = 1.0 / (1 << band) as f64;
+            for phase in 0..=SINC_PHASES {
+                let fraction = phase as f64 / SINC_PHASES as f64;
+                tables.extend(sinc_row(fraction, SINC_TAPS, scale));
+            }
+        }
+        tables
+    })
+}
+
+/// Filter table for a playback rate
+fn sinc_band(tables: &[f32], rate: f32) -> &[f32] {
+    let band = if rate <= 1.0 {
+        0
+    } else if rate <= 2.0 {
+        1
+    } else {
+        SINC_BANDS - 1
+    };
+    let len = (SINC_PHASES + 1) * SINC_TAPS;
+    &tables[band * len..(band + 1) * len]
+}
+
+/// Interpolated value at `position`
+///
+/// `buffer` holds the audio preceded by `HALF_TAPS` samples of padding, so
+/// the taps for position `p` start at `buffer[floor(p) + 1]`.
+fn interpolate(buffer: &[f32], position: f64, band: &[f32]) -> f32 {
+    let index = position as usize;
+    let phase = (position - index as f64) * SINC_PHASES as f64;
+    let row = (phase as usize).min(SINC_PHASES - 1);
+    let blend = (phase - row as f64) as f32;
+
+    let input = &buffer[index + 1..index + 1 + SINC_TAPS];
+    let before = &band[row * SINC_TAPS..(row + 1) * SINC_TAPS];
+    let after = &band[(row + 1) * SINC_TAPS..(row + 2) * SINC_TAPS];
+    let mut sum_before = 0.0f32;
+    let mut sum_after = 0.0f32;
+    for ((&x, &a), &b) in input.iter().zip(before).zip(after) {
+        sum_before += x * a;
+        sum_after += x * b;
+    }
+    sum_before + (sum_after - sum_before) * blend
+}
+
+/// A loop repeated while the note is held
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SustainLoop {
+    /// First frame of the loop
+    pub start: usize,
+    /// Frame after the last frame of the loop
+    pub end: usize,
+    /// Frames before `end` blended with the frames before `start`
+    pub crossfade: usize,
+}
+
+/// A mono recording ready for playback
+///
+/// Shared between voices through an [`Arc`]. Building a sample prepares
+/// everything playback needs, so starting and rendering notes does not
+/// allocate.
+pub struct Sample {
+    /// The recording with `HALF_TAPS` zeros on each side
+    data: Vec<f32>,
+    /// The recording up to the loop end with the crossfade applied, then
+    /// the start of the loop again (empty without a loop)
+    looped: Vec<f32>,
+    frames: usize,
+    sample_rate: u32,
+    root_frequency: f64,
+    sustain_loop: Option<SustainLoop>,
+    /// Positions below this read the same from `data` and `looped`
+    loop_exit: f64,
+}
+
+impl std::fmt::Debug for Sample {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sample")
+            .field("frames", &self.frames)
+            .field("sample_rate", &self.sample_rate)
+            .field("root_frequency", &self.root_frequency)
+            .field("sustain_loop", &self.sustain_loop)
+            .finish()
+    }
+}
+
+impl Sample {
+    /// A recording without a loop
+    ///
+    /// # Arguments
+    /// * `samples` - Mono audio
+    /// * `sample_rate` - Rate the audio was recorded at
+    /// * `root_frequency` - Pitch of the recording in Hz
+    ///
+    /// # Panics
+    /// Panics if `sample_rate` is 0 or `root_frequency` is not positive
+    pub fn new(samples: &[f32], sample_rate: u32, root_frequency: f64) -> Self {
+        assert!(sample_rate > 0, "sample_rate must be positive");
+        assert!(
+            root_frequency.is_finite() && root_frequency > 0.0,
+            "root_frequency must be positive"
+        );
+        // Build the filters now rather than on the first note
+        sinc_tables();
+
+        let mut data = Vec::with_capacity(samples.len() + SINC_TAPS);
+        data.resize(HALF_TAPS, 0.0);
+        data.extend_from_slice(samples);
+        data.resize(samples.len() + SINC_TAPS, 0.0);
+        Self {
+            data,
+            looped: Vec::new(),
+            frames: samples.len(),
+            sample_rate,
+            root_frequency,
+            sustain_loop: None,
+            loop_exit: f64::INFINITY,
+        }
+    }
+
+    /// Build a sample from a decoded WAV file
+    ///
+    /// Channels are mixed to mono. The root note and the first loop come
+    /// from the file's `smpl` chunk; without one the root is middle C.
+    /// Every loop plays forwards, whatever its kind in the file.
+    pub fn from_wav(audio: &WavAudio) -> Self {
+        let info = audio.metadata.sampler.as_ref();
+        let root_frequency = info.map_or(SamplerInfo::new(DEFAULT_ROOT_NOTE).frequency(), |info| {
+            info.frequency()
+        });
+        let sample = Sample::new(&audio.to_mono(), audio.sample_rate, root_frequency);
+        let Some(sample_loop) = info.and_then(|info| info.loops.first()) else {
+            return sample;
+        };
+        let start = sample_loop.start as usize;
+        let end = (sample_loop.end as usize + 1).min(sample.frames);
+        if start < end {
+            sample.with_loop(start, end, 0)
+        } else {
+            sample
+        }
+    }
+
+    /// Read a WAV file (see [`Sample::from_wav`])
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, WavError> {
+        Ok(Sample::from_wav(&read_wav(path)?))
+    }
+
+    /// Loop frames `start..end` while the note is held
+    ///
+    /// The last `crossfade` frames of the loop fade into the frames before
+    /// `start`, so the jump back is seamless for material that is not
+    /// perfectly periodic. The crossfade is shortened to fit before
+    /// `start` and within the loop.
+    ///
+    /// # Panics
+    /// Panics if the loop is empty or extends past the end of the sample
+    pub fn with_loop(mut self, start: usize, end: usize, crossfade: usize) -> Self {
+        assert!(
+            start < end && end <= self.frames,
+            "Invalid sample loop: {}..{}",
+            start,
+            end
+        );
+        let length = end - start;
+        let crossfade = crossfade.min(start).min(length);
+
+        let mut looped = Vec::with_capacity(HALF_TAPS + end + SINC_TAPS);
+        looped.extend_from_slice(&self.data[..HALF_TAPS + end]);
+        for i in 0..crossfade {
+            let t = (i + 1) as f32 / (crossfade + 1) as f32;
+            let fading_in = self.data[HALF_TAPS + start - crossfade + i];
+            let sample = &mut looped[HALF_TAPS + end - crossfade + i];
+            *sample += (fading_in - *sample) * t;
+        }
+        // The loop again, far enough for the taps around a wrapped position
+        for i in 0..SINC_TAPS {
+            looped.push(looped[HALF_TAPS + start + i]);
+        }
+
+        self.looped = looped;
+        self.sustain_loop = Some(SustainLoop {
+            start,
+            end,
+            crossfade,
+        });
+        // Past this, some taps would read the crossfade or the loop again.
+        // Loops too short to have such a point are left straight away.
+        let exit = (end - crossfade).saturating_sub(HALF_TAPS);
+        self.loop_exit = if exit > start + HALF_TAPS {
+            exit as f64
+        } else {
+            f64::INFINITY
+        };
+        self
+    }
+
+    /// Change the crossfade of the sustain loop, if there is one
+    pub fn with_crossfade(self, crossfade: usize) -> Self {
+        match self.sustain_loop {
+            Some(sustain_loop) => self.with_loop(sustain_loop.start, sustain_loop.end, crossfade),
+            None => self,
+        }
+    }
+
+    /// Use a different root pitch
+    ///
+    /// # Panics
+    /// Panics if `root_frequency` is not positive
+    pub fn with_root_frequency(mut self, root_frequency: f64) -> Self {
+        assert!(
+            root_frequency.is_finite() && root_frequency > 0.0,
+            "root_frequency must be positive"
+        );
+        self.root_frequency = root_frequency;
+        self
+    }
+
+    /// Length in frames
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Rate the sample was recorded at
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Pitch of the recording in Hz
+    pub fn root_frequency(&self) -> f64 {
+        self.root_frequency
+    }
+
+    /// The sustain loop, if any
+    pub fn sustain_loop(&self) -> Option<SustainLoop> {
+        self.sustain_loop
+    }
+
+    /// Sample frames to advance per output sample to play `frequency`
+    pub fn playback_rate(&self, frequency: f64, output_rate: u32) -> f64 {
+        frequency / self.root_frequency * self.sample_rate as f64 / output_rate as f64
+    }
+}
+
+/// Sample playback generator
+///
+/// Plays a [`Sample`] from its start, advancing `rate` sample frames per
+/// output sample, scaled by the pitch ratio (for pitch bend). The sample
+/// plays at full level until [`note_off`], then fades out over the release
+/// time. Without a loop, the generator completes when the recording ends.
+///
+/// # Example
+/// ```
+/// use corroza::generator::sampler::{Sample, SamplerGenerator};
+/// use corroza::generator::SignalGenerator;
+/// use std::sync::Arc;
+///
+/// let tone: Vec<f32> = (0..4410).map(|i| (i as f32 * 0.0627).sin()).collect();
+/// let sample = Arc::new(Sample::new(&tone, 44100, 440.0).with_loop(1000, 4000, 200));
+///
+/// // An octave up
+/// let rate = sample.playback_rate(880.0, 44100) as f32;
+/// let mut sampler = SamplerGenerator::new(sample, rate, 2205);
+/// let mut buffer = [0.0f32; 512];
+/// sampler.process(&mut buffer);
+/// sampler.note_off();
+/// ```
+///
+/// [`note_off`]: SamplerGenerator::note_off
+pub struct SamplerGenerator {
+    sample: Arc<Sample>,
+    envelope: AdsrGenerator,
+    /// Read position in sample frames
+    position: f64,
+    initial_rate: f32,
+    rate: f32,
+    /// Rate multiplier applied on top of `rate`
+    pitch_ratio: f32,
+    glide: Option<Glide>,
+    /// Reading from the looped copy of the sample
+    looping: bool,
+    /// Released: leave the loop at the next chance
+    leaving_loop: bool,
+    /// Played past the end of the sample
+    finished: bool,
+}
+
+impl SamplerGenerator {
+    /// Start playing a sample
+    ///
+    /// # Arguments
+    /// * `sample` - The recording to play
+    /// * `rate` - Sample frames per output sample (see [`Sample::playback_rate`])
+    /// * `release_samples` - Fade-out length after note off
+    ///
+    /// # Panics
+    /// Panics if rate is not positive
+    pub fn new(sample: Arc<Sample>, rate: f32, release_samples: usize) -> Self {
+        assert!(rate > 0.0, "rate must be positive");
+        let looping = sample.sustain_loop.is_some();
+        Self {
+            sample,
+            envelope: AdsrGenerator::new(1.0, 1, 1, 1.0, HOLD_SAMPLES, release_samples),
+            position: 0.0,
+            initial_rate: rate,
+            rate,
+            pitch_ratio: 1.0,
+            glide: None,
+            looping,
+            leaving_loop: false,
+            finished: false,
+        }
+    }
+
+    /// The sample being played
+    pub fn sample(&self) -> &Arc<Sample> {
+        &self.sample
+    }
+
+    /// Current read position in sample frames
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    /// Check if playback is inside the sustain loop
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    /// Get the current playback rate, before the pitch ratio
+    pub fn rate(&self) -> f32 {
+        self.rate
+    }
+
+    /// Jump to a new playback rate immediately, cancelling any glide
+    ///
+    /// # Panics
+    /// Panics if rate is not positive
+    pub fn set_rate(&mut self, rate: f32) {
+        assert!(rate > 0.0, "rate must be positive");
+        self.glide = None;
+        self.rate = rate;
+    }
+
+    /// Slide from the current playback rate to a new one
+    ///
+    /// # Arguments
+    /// * `rate` - Target sample frames per output sample
+    /// * `samples` - Glide duration in samples (0 jumps immediately)
+    /// * `curve` - Linear or exponential slide
+    ///
+    /// # Panics
+    /// Panics if rate is not positive
+    pub fn glide_to(&mut self, rate: f32, samples: usize, curve: GlideCurve) {
+        if samples == 0 {
+            self.set_rate(rate);
+            return;
+        }
+        assert!(rate > 0.0, "rate must be positive");
+        self.glide = Some(Glide::new(self.rate, rate, samples, curve));
+    }
+
+    /// Check if a pitch glide is in progress
+    pub fn is_gliding(&self) -> bool {
+        self.glide.is_some()
+    }
+
+    /// Get the current pitch ratio
+    pub fn pitch_ratio(&self) -> f32 {
+        self.pitch_ratio
+    }
+
+    /// Scale the pitch by a ratio (e.g. from pitch bend)
+    ///
+    /// # Panics
+    /// Panics if ratio is not positive
+    pub fn set_pitch_ratio(&mut self, ratio: f32) {
+        assert!(ratio > 0.0, "pitch ratio must be positive");
+        self.pitch_ratio = ratio;
+    }
+
+    /// Get the current envelope level (0 once the sample has ended)
+    pub fn amplitude(&self) -> f32 {
+        if self.finished {
+            0.0
+        } else {
+            self.envelope.current_amplitude()
+        }
+    }
+
+    /// Start the release: fade out and let the loop run out
+    pub fn note_off(&mut self) {
+        self.envelope.note_off();
+        self.leaving_loop = true;
+    }
+
+    /// Play the sample again from the start
+    pub fn retrigger(&mut self) {
+        self.envelope.retrigger();
+        self.position = 0.0;
+        self.looping = self.sample.sustain_loop.is_some();
+        self.leaving_loop = false;
+        self.finished = false;
+    }
+
+    /// Add the next `buffer.len()` samples to `buffer`
+    ///
+    /// Lets the sampler layer onto other output without a scratch buffer.
+    pub fn process_add(&mut self, buffer: &mut [f32]) -> GeneratorState {
+        let tables = sinc_tables();
+        let sample = &*self.sample;
+        let frames = sample.frames as f64;
+        let mut envelope = [0.0f32; ENVELOPE_CHUNK];
+
+        for chunk in buffer.chunks_mut(ENVELOPE_CHUNK) {
+            if self.finished {
+                break;
+            }
+            self.envelope.process(&mut envelope[..chunk.len()]);
+
+            for (output, &gain) in chunk.iter_mut().zip(&envelope) {
+                if self.leaving_loop && self.looping && self.position < sample.loop_exit {
+                    self.looping = false;
+                }
+                if !self.looping && self.position >= frames {
+                    self.finished = true;
+                    break;
+                }
+
+                let source = if self.looping {
+                    &sample.looped
+                } else {
+                    &sample.data
+                };
+                if let Some(glide) = self.glide.as_mut() {
+                    self.rate = glide.next_phase_per_sample();
+                    if glide.is_done() {
+                        self.glide = None;
+                    }
+                }
+                let rate = self.rate * self.pitch_ratio;
+                let band = sinc_band(tables, rate);
+                *output += interpolate(source, self.position, band) * gain;
+
+                self.position += rate as f64;
+                if let (true, Some(sustain_loop)) = (self.looping, sample.sustain_loop) {
+                    // Wrap once the taps around the position fit in the loop
+                    let length = (sustain_loop.end - sustain_loop.start) as f64;
+                    let wrap = (sustain_loop.end + HALF_TAPS) as f64;
+                    while self.position >= wrap {
+                        self.position -= length;
+                    }
+                }
+            }
+        }
+
+        if self.is_complete() {
+            GeneratorState::Complete
+        } else {
+            GeneratorState::Running
+        }
+    }
+}
+
+impl SignalGenerator for SamplerGenerator {
+    fn process(&mut self, buffer: &mut [f32]) -> GeneratorState {
+        buffer.fill(0.0);
+        self.process_add(buffer)
+    }
+
+    fn is_complete(&self) -> bool {
+        self.finished || self.envelope.is_complete()
+    }
+
+    fn reset(&mut self) {
+        self.envelope.reset();
+        self.position = 0.0;
+        self.rate = self.initial_rate;
+        self.pitch_ratio = 1.0;
+        self.glide = None;
+        self.looping = self.sample.sustain_loop.is_some();
+        self.leaving_loop = false;
+        self.finished = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wav::{SampleLoop, WavMetadata};
+    use std::f64::consts::TAU;
+
+    fn sine(cycles_per_sample: f64, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (TAU * cycles_per_sample * i as f64).sin() as f32)
+            .collect()
+    }
+
+    fn render(sampler: &mut SamplerGenerator, len: usize) -> Vec<f32> {
+        let mut output = vec![0.0f32; len];
+        for chunk in output.chunks_mut(100) {
+            sampler.process(chunk);
+        }
+        output
+    }
+
+    #[test]
+    fn test_plays_at_root_pitch() {
+        let tone = sine(0.01, 2000);
+        let sample = Arc::new(Sample::new(&tone, 44100, 441.0));
+        assert_eq!(sample.playback_rate(441.0, 44100), 1.0);
+
+        let mut sampler = SamplerGenerator::new(sample, 1.0, 100);
+        let output = render(&mut sampler, 2100);
+        for (a, b) in output[100..1900].iter().zip(&tone[100..1900]) {
+            assert!((a - b).abs() < 1e-3);
+        }
+        assert!(sampler.is_complete());
+    }
+
+    #[test]
+    fn test_pitch_shift_matches_ideal_sine() {
+        let tone = sine(0.01, 20000);
+        let sample = Arc::new(Sample::new(&tone, 48000, 480.0));
+
+        // A fifth up at a different output rate
+        let frequency = 480.0 * 1.5;
+        let rate = sample.playback_rate(frequency, 44100);
+        let mut sampler = SamplerGenerator::new(sample, rate as f32, 100);
+        let output = render(&mut sampler, 4000);
+
+        let expected = sine(frequency / 44100.0, 4000);
+        // Skip the start, where the filter reaches before the sample
+        let error = output[100..]
+            .iter()
+            .zip(&expected[100..])
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0f32, f32::max);
+        assert!(error < 1e-3, "error {}", error);
+    }
+
+    #[test]
+    fn test_pitch_ratio_and_glide() {
+        let sample = Arc::new(Sample::new(&sine(0.01, 1000), 44100, 441.0));
+        let mut sampler = SamplerGenerator::new(sample, 1.0, 100);
+        sampler.set_pitch_ratio(2.0);
+        render(&mut sampler, 10);
+        assert!((sampler.position() - 20.0).abs() < 1e-9);
+
+        sampler.glide_to(0.5, 10, GlideCurve::Linear);
+        assert!(sampler.is_gliding());
+        render(&mut sampler, 20);
+        assert!(!sampler.is_gliding());
+        assert_eq!(sampler.rate(), 0.5);
+    }
+
+    #[test]
+    fn test_loop_sustains_until_release() {
+        let tone = sine(0.01, 3000);
+        let sample = Sample::new(&tone, 44100, 441.0).with_loop(1000, 2000, 100);
+        let mut sampler = SamplerGenerator::new(Arc::new(sample), 1.3, 500);
+
+        // Held far longer than the sample
+        let held = render(&mut sampler, 20000);
+        assert!(sampler.is_looping());
+        assert!(!sampler.is_complete());
+        assert!(held[15000..].iter().any(|s| s.abs() > 0.9));
+
+        // Release plays on past the loop end and fades out
+        sampler.note_off();
+        let mut released = Vec::new();
+        while !sampler.is_complete() {
+            released.extend(render(&mut sampler, 100));
+        }
+        assert!(!sampler.is_looping());
+        assert!(released.len() >= 500 && released.len() < 700);
+        assert!(released.last().unwrap().abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_release_continues_into_tail() {
+        let mut recording = vec![0.0f32; 4000];
+        recording[3000..].fill(0.5);
+        let sample = Sample::new(&recording, 44100, 441.0).with_loop(100, 2000, 0);
+        let mut sampler = SamplerGenerator::new(Arc::new(sample), 1.0, 1_000_000);
+
+        render(&mut sampler, 10000);
+        assert!(sampler.position() < 2000.0 + HALF_TAPS as f64);
+        sampler.note_off();
+        let tail = render(&mut sampler, 4000);
+        assert!(tail.iter().any(|&s| s > 0.4));
+        assert!(sampler.is_complete());
+    }
+
+    #[test]
+    fn test_crossfade_hides_loop_seam() {
+        // A loop that is not a whole number of cycles
+        let tone = sine(0.0123, 6000);
+        let seam = |crossfade| {
+            let sample = Sample::new(&tone, 44100, 441.0).with_loop(2000, 2900, crossfade);
+            let mut sampler = SamplerGenerator::new(Arc::new(sample), 1.0, 100);
+            let output = render(&mut sampler, 5000);
+            output
+                .windows(2)
+                .map(|w| (w[1] - w[0]).abs())
+                .fold(0.0f32, f32::max)
+        };
+        // The steepest step of the sine itself is 2π * 0.0123 ≈ 0.077
+        assert!(seam(0) > 0.2);
+        assert!(seam(400) < 0.1);
+    }
+
+    #[test]
+    fn test_from_wav_reads_root_and_loop() {
+        let metadata = WavMetadata {
+            sampler: Some(SamplerInfo::new(69).with_loop(SampleLoop::forward(10, 99))),
+            ..Default::default()
+        };
+        let audio = WavAudio {
+            sample_rate: 22050,
+            format: crate::wav::SampleFormat::Pcm,
+            bits_per_sample: 16,
+            channels: vec![vec![0.5; 200], vec![0.0; 200]],
+            metadata,
+        };
+        let sample = Sample::from_wav(&audio);
+        assert_eq!(sample.frames(), 200);
+        assert!((sample.root_frequency() - 440.0).abs() < 1e-9);
+        assert_eq!(
+            sample.sustain_loop(),
+            Some(SustainLoop {
+                start: 10,
+                end: 100,
+                crossfade: 0
+            })
+        );
+        assert_eq!(sample.data[HALF_TAPS], 0.25);
+        assert_eq!(sample.playback_rate(440.0, 44100), 0.5);
+
+        // Without a smpl chunk the root is middle C
+        let audio = WavAudio {
+            metadata: WavMetadata::default(),
+            ..audio
+        };
+        let sample = Sample::from_wav(&audio);
+        assert!((sample.root_frequency() - 261.6256).abs() < 1e-3);
+        assert_eq!(sample.sustain_loop(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid sample loop")]
+    fn test_loop_past_end_panics() {
+        Sample::new(&[0.0; 100], 44100, 440.0).with_loop(50, 101, 0);
+    }
+
+    #[test]
+    fn test_process_does_not_allocate() {
+        let sample =
+            Arc::new(Sample::new(&sine(0.01, 3000), 44100, 441.0).with_loop(500, 2500, 300));
+        let mut buffer = vec![0.0f32; 1024];
+        let ((), allocations) = crate::alloc_check::count_allocations(|| {
+            let mut sampler = SamplerGenerator::new(Arc::clone(&sample), 1.7, 300);
+            sampler.set_pitch_ratio(1.1);
+            sampler.process(&mut buffer);
+            sampler.note_off();
+            while sampler.process(&mut buffer) == GeneratorState::Running {}
+        });
+        assert_eq!(allocations, 0);
+    }
+}
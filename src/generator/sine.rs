@@ -0,0 +1,57 @@
+//! Fast sine for oscillators
+//!
+//! Oscillators keep their phase wrapped to [0, 2π), so the sine only needs
+//! a cheap range reduction followed by a polynomial:
+//! - Shift into [-π, π] and fold into [-π/2, π/2] using sin(π - x) = sin(x)
+//! - Evaluate the Taylor series up to x^11 (error below 1e-7, about the
+//!   precision of an f32)
+
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+// Taylor coefficients of sin(x) / x in powers of x²
+const C3: f32 = -1.0 / 6.0;
+const C5: f32 = 1.0 / 120.0;
+const C7: f32 = -1.0 / 5040.0;
+const C9: f32 = 1.0 / 362_880.0;
+const C11: f32 = -1.0 / 39_916_800.0;
+
+/// Sine of a phase in [-π, 2π)
+///
+/// Phases outside that range give inaccurate results; wrap them first.
+#[inline]
+pub fn fast_sin(phase: f32) -> f32 {
+    let mut x = if phase > PI { phase - TAU } else { phase };
+    if x > FRAC_PI_2 {
+        x = PI - x;
+    } else if x < -FRAC_PI_2 {
+        x = -PI - x;
+    }
+    let x2 = x * x;
+    x * (1.0 + x2 * (C3 + x2 * (C5 + x2 * (C7 + x2 * (C9 + x2 * C11)))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fast_sin_accuracy() {
+        let steps = 100_000;
+        let mut max_error = 0.0f32;
+        for i in 0..steps {
+            let phase = -PI + 3.0 * PI * i as f32 / steps as f32;
+            let error = (fast_sin(phase) - phase.sin()).abs();
+            max_error = max_error.max(error);
+        }
+        assert!(max_error < 2e-7, "max error {}", max_error);
+    }
+
+    #[test]
+    fn test_fast_sin_key_points() {
+        assert_eq!(fast_sin(0.0), 0.0);
+        assert!((fast_sin(FRAC_PI_2) - 1.0).abs() < 2e-7);
+        assert!(fast_sin(PI).abs() < 1e-6);
+        assert!((fast_sin(3.0 * FRAC_PI_2) + 1.0).abs() < 2e-7);
+        assert!(fast_sin(TAU - 1e-6).abs() < 1e-5);
+    }
+}
@@ -1,5 +1,12 @@
+#[cfg(test)]
+mod alloc_check;
+pub mod analysis;
 pub mod generator;
 pub mod pipeline;
+#[cfg(test)]
+mod pitch_check;
+pub mod realtime;
+pub mod resample;
 pub mod wav;
 
 pub use generator::{GeneratorState, SignalGenerator};
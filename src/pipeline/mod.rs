@@ -2,13 +2,35 @@
 //!
 //! Provides a complete event-driven audio synthesis pipeline:
 //! - Parser: Parse musical transcription format
-//! - VoiceManager: Polyphonic voice management
-//! - Scheduler: Frame-based event scheduling and audio generation
+//! - Preprocess: Expand sections, repeats and includes
+//! - VoiceManager: Voice allocation, stealing, mono/legato and pedals
+//! - Processor: Arpeggiator and chord memory applied to key events
+//! - Transform: Transpose, time-stretch, quantize and humanize event streams
+//! - Tuning: Scala scales and keyboard mappings, EDOs and just intonation
+//! - Scheduler: Frame-based event scheduling, track mixing and audio generation
 
 pub mod parser;
+pub mod preprocess;
+pub mod processor;
 pub mod scheduler;
+pub mod transform;
+pub mod tuning;
 pub mod voicemgr;
+mod workers;
 
-pub use parser::{parse_transcription, Event, KeyDirection, Note, ParseError, TimedEvents};
-pub use scheduler::{Pipeline, PipelineConfig};
-pub use voicemgr::{VoiceConfig, VoiceManager};
+pub use parser::{
+    parse_tracks, parse_tracks_with, parse_transcription, parse_transcription_with, Control, Event,
+    KeyDirection, Note, ParseError, ParseErrorKind, ParseErrors, Pedal, Span, TimedEvents, Track,
+    CONCERT_A,
+};
+pub use preprocess::{FileLoader, SourceLoader};
+pub use processor::{
+    apply_processor, ArpPattern, Arpeggiator, ArpeggiatorConfig, ChordMemory, EventProcessor,
+};
+pub use scheduler::{Pipeline, PipelineConfig, PipelineError, CONCERT_PITCH};
+pub use transform::{humanize, quantize, scale_time, transpose, HumanizeParams, TransformError};
+pub use tuning::{KeyboardMapping, Scale, Tuning, TuningError};
+pub use voicemgr::{
+    ControlTarget, ControllerMapping, NotePriority, RetriggerPolicy, SampleMap, SampleZone,
+    StealPolicy, VoiceConfig, VoiceManager, VoiceMode, VoiceStats,
+};
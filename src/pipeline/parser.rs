@@ -6,21 +6,156 @@
 //! Events:
 //! - Key down: <octave><note><accidental>d  (e.g., 4c#d, 4ad)
 //! - Key up:   <octave><note><accidental>u  (e.g., 4c#u, 4au)
+//! - Sustain pedal:    ped+ (press), ped- (lift)
+//! - Sostenuto pedal:  sos+ (press), sos- (lift)
+//! - Pitch bend:       bend=<-1.0..1.0>
+//! - Controllers:      cc<0-127>=<0.0..1.0>, with aliases mod= (cc1) and
+//!   expr= (cc11)
 //!
 //! Notes:
 //! - White keys: c, d, e, f, g, a, b
 //! - Black keys: c#, d#, f#, g#, a#
 //! - Octaves: 0-9
+//!
+//! Comments start at any `#` that is not the sharp of a note
+//! (a sharp directly follows `<octave><note>`, as in `4c#d`).
+//!
+//! Tracks (optional):
+//! @track <name> [instrument=<name>] [gain=<value>]
+//!
+//! Lines following a track directive belong to that track, and each track
+//! has its own timeline starting at 0. Lines before the first directive
+//! belong to an implicit track named "main".
+//!
+//! Sections, repeats and includes are expanded before parsing (see the
+//! `preprocess` module).
+//!
+//! Errors carry the line and column they occurred at. Whole-file parsing
+//! keeps going after an error and reports every error it finds.
 
+use std::ops::Range;
 use std::str::FromStr;
 
+use crate::pipeline::preprocess::{expand, FileLoader, SourceLine, SourceLoader};
+
+/// Name of the implicit track used for lines before any `@track` directive
+pub const DEFAULT_TRACK_NAME: &str = "main";
+
 /// Represents a musical note (pitch class and octave)
+///
+/// Octaves are numbered as in scientific pitch notation, so `4a` is A4
+/// (MIDI note 69). How a note sounds depends on the pipeline's pitch
+/// reference: by default 1C sounds at the base frequency rather than at
+/// concert pitch.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Note {
     pub octave: u8,
     pub pitch_class: PitchClass,
 }
 
+/// Highest octave a note can be written in
+pub const MAX_OCTAVE: u8 = 9;
+
+/// MIDI note number of 0C (C0 in scientific pitch notation)
+pub const MIDI_OFFSET: i32 = 12;
+
+/// Concert pitch reference note (4a = A4)
+pub const CONCERT_A: Note = Note {
+    octave: 4,
+    pitch_class: PitchClass::A,
+};
+
+impl Note {
+    /// Semitones above 0C (octave * 12 + pitch class semitone)
+    pub fn semitone_index(&self) -> i32 {
+        self.octave as i32 * 12 + self.pitch_class.semitone() as i32
+    }
+
+    /// Build a note from its semitone index above 0C
+    ///
+    /// Returns `None` if the note falls outside octaves 0 to [`MAX_OCTAVE`].
+    pub fn from_semitone_index(index: i32) -> Option<Note> {
+        if index < 0 || index / 12 > MAX_OCTAVE as i32 {
+            return None;
+        }
+        Some(Note {
+            octave: (index / 12) as u8,
+            pitch_class: PitchClass::from_semitone((index % 12) as u8),
+        })
+    }
+
+    /// MIDI note number (4a = 69), or `None` above 127
+    pub fn midi_number(&self) -> Option<u8> {
+        u8::try_from(self.semitone_index() + MIDI_OFFSET)
+            .ok()
+            .filter(|n| *n <= 127)
+    }
+
+    /// Build a note from a MIDI note number
+    ///
+    /// Returns `None` below 0C (MIDI 12) or above 127.
+    pub fn from_midi_number(number: u8) -> Option<Note> {
+        if number > 127 {
+            return None;
+        }
+        Note::from_semitone_index(number as i32 - MIDI_OFFSET)
+    }
+
+    /// Format in scientific pitch notation (e.g. "C#4")
+    pub fn scientific_pitch(&self) -> String {
+        format!(
+            "{}{}",
+            self.pitch_class.to_string().to_uppercase(),
+            self.octave
+        )
+    }
+
+    /// Parse scientific pitch notation (e.g. "A4", "C#4", "Bb3")
+    ///
+    /// The letter may be upper or lower case and is followed by an optional
+    /// `#` or `b` and an octave from 0 to [`MAX_OCTAVE`]. Returns `None` for
+    /// anything else, including flats or sharps that leave that range.
+    pub fn from_scientific_pitch(s: &str) -> Option<Note> {
+        let mut chars = s.chars();
+        let letter = chars.next()?.to_ascii_lowercase();
+        let natural = PitchClass::from_str(&letter.to_string()).ok()?;
+        let rest = chars.as_str();
+        let (accidental, octave) = match rest.strip_prefix('#') {
+            Some(octave) => (1, octave),
+            None => match rest.strip_prefix('b') {
+                Some(octave) => (-1, octave),
+                None => (0, rest),
+            },
+        };
+        if octave.len() != 1 {
+            return None;
+        }
+        let octave = octave.parse::<u8>().ok()?;
+        if octave > MAX_OCTAVE {
+            return None;
+        }
+        Note {
+            octave,
+            pitch_class: natural,
+        }
+        .transposed(accidental)
+    }
+
+    /// Shift the note by a number of semitones (negative is down)
+    ///
+    /// Returns `None` if the result falls outside the supported octaves.
+    pub fn transposed(&self, semitones: i32) -> Option<Note> {
+        Note::from_semitone_index(self.semitone_index().checked_add(semitones)?)
+    }
+}
+
+/// Formats as in the transcription format (e.g. "4c#")
+impl std::fmt::Display for Note {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.octave, self.pitch_class)
+    }
+}
+
 /// Pitch classes with support for black keys (sharps only)
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum PitchClass {
@@ -56,6 +191,44 @@ impl PitchClass {
             PitchClass::B => 11,
         }
     }
+
+    /// Convert a semitone number (taken modulo 12) to a pitch class
+    pub fn from_semitone(semitone: u8) -> PitchClass {
+        match semitone % 12 {
+            0 => PitchClass::C,
+            1 => PitchClass::CSharp,
+            2 => PitchClass::D,
+            3 => PitchClass::DSharp,
+            4 => PitchClass::E,
+            5 => PitchClass::F,
+            6 => PitchClass::FSharp,
+            7 => PitchClass::G,
+            8 => PitchClass::GSharp,
+            9 => PitchClass::A,
+            10 => PitchClass::ASharp,
+            _ => PitchClass::B,
+        }
+    }
+}
+
+impl std::fmt::Display for PitchClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            PitchClass::C => "c",
+            PitchClass::CSharp => "c#",
+            PitchClass::D => "d",
+            PitchClass::DSharp => "d#",
+            PitchClass::E => "e",
+            PitchClass::F => "f",
+            PitchClass::FSharp => "f#",
+            PitchClass::G => "g",
+            PitchClass::GSharp => "g#",
+            PitchClass::A => "a",
+            PitchClass::ASharp => "a#",
+            PitchClass::B => "b",
+        };
+        f.write_str(name)
+    }
 }
 
 impl FromStr for PitchClass {
@@ -75,7 +248,10 @@ impl FromStr for PitchClass {
             "a" => Ok(PitchClass::A),
             "a#" | "A#" => Ok(PitchClass::ASharp),
             "b" => Ok(PitchClass::B),
-            _ => Err(ParseError::InvalidPitchClass(s.to_string())),
+            _ => Err(ParseError::new(
+                ParseErrorKind::InvalidPitchClass(s.to_string()),
+                Span::new(1, 1, s.chars().count()),
+            )),
         }
     }
 }
@@ -88,10 +264,67 @@ pub enum KeyDirection {
 }
 
 /// A single musical event
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Event {
     pub note: Note,
     pub direction: KeyDirection,
+    /// Key-down loudness (0.0 to 1.0); ignored for key-up events
+    pub velocity: f32,
+}
+
+impl Event {
+    /// Create an event at full velocity
+    pub fn new(note: Note, direction: KeyDirection) -> Self {
+        Self {
+            note,
+            direction,
+            velocity: 1.0,
+        }
+    }
+}
+
+/// A pedal that keeps notes sounding after their key-up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pedal {
+    /// Holds every note released while it is down (like MIDI CC64)
+    Sustain,
+    /// Holds only the notes that were down when it was pressed (like CC66)
+    Sostenuto,
+}
+
+/// Controller number of the modulation wheel
+pub const CC_MOD_WHEEL: u8 = 1;
+/// Controller number of channel volume
+pub const CC_VOLUME: u8 = 7;
+/// Controller number of expression
+pub const CC_EXPRESSION: u8 = 11;
+/// Controller number of the sustain pedal
+pub const CC_SUSTAIN: u8 = 64;
+/// Controller number of the sostenuto pedal
+pub const CC_SOSTENUTO: u8 = 66;
+
+/// A non-note event
+///
+/// On a line, pedal lifts and controller changes apply before the line's
+/// key events and pedal presses after them, so `ped-, 4cd, ped+` changes
+/// the pedal cleanly, `4cd, sos+` holds the C and `bend=0.5, 4cd` starts
+/// the C bent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Control {
+    /// Pedal pressed (`Down`) or lifted (`Up`)
+    Pedal(Pedal, KeyDirection),
+    /// Pitch bend from -1.0 (down) to 1.0 (up); the range in semitones
+    /// is set per instrument
+    PitchBend(f32),
+    /// Continuous controller number (0-127) with a value from 0.0 to 1.0
+    ControlChange(u8, f32),
+}
+
+impl Control {
+    /// Check if the control applies after the key events on its line
+    pub fn applies_after_keys(&self) -> bool {
+        matches!(self, Control::Pedal(_, KeyDirection::Down))
+    }
 }
 
 /// A line from the transcription with its timestep delta
@@ -101,12 +334,74 @@ pub struct TimedEvents {
     pub delta: usize,
     /// Events occurring at this timestep
     pub events: Vec<Event>,
+    /// Pedal and controller events occurring at this timestep
+    pub controls: Vec<Control>,
+}
+
+/// A named track of timed events
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    /// Track name (unique within a transcription)
+    pub name: String,
+    /// Instrument to play this track with (defaults to the track name)
+    pub instrument: Option<String>,
+    /// Linear gain applied to the track's mixed output
+    pub gain: f32,
+    /// Timed events in chronological order (deltas relative to track start)
+    pub events: Vec<TimedEvents>,
+}
+
+impl Track {
+    /// Create an empty track with unity gain and no explicit instrument
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            instrument: None,
+            gain: 1.0,
+            events: Vec::new(),
+        }
+    }
+
+    /// Name of the instrument used to render this track
+    pub fn instrument_name(&self) -> &str {
+        self.instrument.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// Location of an error in the source text
+///
+/// Lines and columns are 1-based; columns count characters, not bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    /// Number of characters covered (at least 1 when rendered)
+    pub len: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, column: usize, len: usize) -> Self {
+        Self { line, column, len }
+    }
+
+    /// Build a span from a byte range within a line of text
+    fn from_byte_range(line_number: usize, line: &str, range: Range<usize>) -> Self {
+        let start = range.start.min(line.len());
+        let end = range.end.clamp(start, line.len());
+        Self {
+            line: line_number,
+            column: line[..start].chars().count() + 1,
+            len: line[start..end].chars().count(),
+        }
+    }
 }
 
-/// Parse errors
+/// Kinds of parse errors
 #[derive(Debug, Clone, PartialEq)]
-pub enum ParseError {
+pub enum ParseErrorKind {
     InvalidLine(String),
+    InvalidDirective(String),
+    InvalidTrack(String),
     InvalidTimestep(String),
     InvalidEvent(String),
     InvalidNote(String),
@@ -115,138 +410,597 @@ pub enum ParseError {
     InvalidDirection(String),
 }
 
-impl std::fmt::Display for ParseError {
+impl std::fmt::Display for ParseErrorKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ParseError::InvalidLine(s) => write!(f, "Invalid line: {}", s),
-            ParseError::InvalidTimestep(s) => write!(f, "Invalid timestep: {}", s),
-            ParseError::InvalidEvent(s) => write!(f, "Invalid event: {}", s),
-            ParseError::InvalidNote(s) => write!(f, "Invalid note: {}", s),
-            ParseError::InvalidPitchClass(s) => write!(f, "Invalid pitch class: {}", s),
-            ParseError::InvalidOctave(s) => write!(f, "Invalid octave: {}", s),
-            ParseError::InvalidDirection(s) => write!(f, "Invalid direction: {}", s),
+            ParseErrorKind::InvalidLine(s) => write!(f, "Invalid line: {}", s),
+            ParseErrorKind::InvalidDirective(s) => write!(f, "Invalid directive: {}", s),
+            ParseErrorKind::InvalidTrack(s) => write!(f, "Invalid track: {}", s),
+            ParseErrorKind::InvalidTimestep(s) => write!(f, "Invalid timestep: {}", s),
+            ParseErrorKind::InvalidEvent(s) => write!(f, "Invalid event: {}", s),
+            ParseErrorKind::InvalidNote(s) => write!(f, "Invalid note: {}", s),
+            ParseErrorKind::InvalidPitchClass(s) => write!(f, "Invalid pitch class: {}", s),
+            ParseErrorKind::InvalidOctave(s) => write!(f, "Invalid octave: {}", s),
+            ParseErrorKind::InvalidDirection(s) => write!(f, "Invalid direction: {}", s),
+        }
+    }
+}
+
+/// A parse error with its location
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub span: Span,
+    /// Included file the error is in (`None` for the top-level text)
+    pub file: Option<String>,
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, span: Span) -> Self {
+        Self {
+            kind,
+            span,
+            file: None,
+        }
+    }
+
+    /// Render a caret-style diagnostic pointing at the error in `source`
+    ///
+    /// # Arguments
+    /// * `source` - Full text of the file the error is in
+    /// * `file_name` - Name shown in the location line
+    pub fn render(&self, source: &str, file_name: &str) -> String {
+        let Span { line, column, len } = self.span;
+        let line_text = source.lines().nth(line.saturating_sub(1)).unwrap_or("");
+        let gutter = " ".repeat(line.to_string().len());
+
+        // Keep tabs so the caret lines up with the source line
+        let padding: String = line_text
+            .chars()
+            .take(column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        format!(
+            "error: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}\n",
+            self.kind,
+            gutter,
+            file_name,
+            line,
+            column,
+            gutter,
+            line,
+            line_text,
+            gutter,
+            padding,
+            "^".repeat(len.max(1))
+        )
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}: ", file)?;
         }
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.span.line, self.span.column, self.kind
+        )
     }
 }
 
 impl std::error::Error for ParseError {}
 
+/// All errors found while parsing a transcription, in source order
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseErrors {
+    pub errors: Vec<ParseError>,
+    /// Text of included files as `(path, text)`, for rendering
+    pub sources: Vec<(String, String)>,
+}
+
+impl ParseErrors {
+    /// Sort errors by file and position, dropping duplicates
+    ///
+    /// Lines inside repeats and sections are parsed once per repetition,
+    /// so the same error can be reported several times.
+    fn new(mut errors: Vec<ParseError>, sources: Vec<(String, String)>) -> Self {
+        errors.sort_by(|a, b| {
+            (&a.file, a.span.line, a.span.column).cmp(&(&b.file, b.span.line, b.span.column))
+        });
+        errors.dedup();
+        Self { errors, sources }
+    }
+
+    /// Render caret-style diagnostics for every error
+    ///
+    /// # Arguments
+    /// * `source` - Top-level text that was parsed
+    /// * `file_name` - Name of the top-level file
+    pub fn render(&self, source: &str, file_name: &str) -> String {
+        self.errors
+            .iter()
+            .map(|e| match &e.file {
+                Some(file) => {
+                    let text = self
+                        .sources
+                        .iter()
+                        .find(|(name, _)| name == file)
+                        .map_or("", |(_, text)| text.as_str());
+                    e.render(text, file)
+                }
+                None => e.render(source, file_name),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl std::fmt::Display for ParseErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, error) in self.errors.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseErrors {}
+
+/// An error kind with the byte range it covers within the text being parsed
+type Spanned = (ParseErrorKind, Range<usize>);
+
+/// Split on a delimiter, yielding trimmed pieces with their byte offsets
+fn split_with_offsets(s: &str, delimiter: char) -> impl Iterator<Item = (usize, &str)> {
+    let mut offset = 0;
+    s.split(delimiter).map(move |piece| {
+        let start = offset;
+        offset += piece.len() + delimiter.len_utf8();
+        let trimmed = piece.trim_start();
+        (start + piece.len() - trimmed.len(), trimmed.trim_end())
+    })
+}
+
+/// Split on whitespace, yielding words with their byte offsets
+fn words_with_offsets(s: &str) -> impl Iterator<Item = (usize, &str)> {
+    s.split_whitespace()
+        .map(move |word| (word.as_ptr() as usize - s.as_ptr() as usize, word))
+}
+
+/// Find the byte offset where a comment starts, if any
+///
+/// A `#` is a sharp (not a comment) only when it follows `<octave><note>`.
+pub(crate) fn comment_start(line: &str) -> Option<usize> {
+    let bytes = line.as_bytes();
+    (0..bytes.len()).find(|&i| {
+        bytes[i] == b'#'
+            && !(i >= 2
+                && matches!(bytes[i - 1], b'a'..=b'g' | b'A'..=b'G')
+                && bytes[i - 2].is_ascii_digit())
+    })
+}
+
+/// Parse a controller assignment (`bend=0.5`, `cc7=0.8`, `mod=1`, `expr=0.3`)
+fn parse_controller(s: &str, name: &str, value: &str) -> Result<Control, Spanned> {
+    let value_start = name.len() + 1;
+    let invalid_value = |expected: &str| {
+        (
+            ParseErrorKind::InvalidEvent(format!("{} (expected {} value)", s, expected)),
+            value_start..s.len().max(value_start + 1),
+        )
+    };
+    let parsed = value.parse::<f32>().ok().filter(|v| v.is_finite());
+
+    let number = match name {
+        "bend" => {
+            return match parsed {
+                Some(v) if (-1.0..=1.0).contains(&v) => Ok(Control::PitchBend(v)),
+                _ => Err(invalid_value("a -1.0 to 1.0")),
+            };
+        }
+        "mod" => CC_MOD_WHEEL,
+        "expr" => CC_EXPRESSION,
+        _ => name
+            .strip_prefix("cc")
+            .and_then(|n| n.parse::<u8>().ok())
+            .filter(|&n| n < 128)
+            .ok_or_else(|| {
+                (
+                    ParseErrorKind::InvalidEvent(format!(
+                        "{} (expected bend, mod, expr or cc0-cc127)",
+                        name
+                    )),
+                    0..name.len(),
+                )
+            })?,
+    };
+
+    match parsed {
+        Some(v) if (0.0..=1.0).contains(&v) => Ok(Control::ControlChange(number, v)),
+        _ => Err(invalid_value("a 0.0 to 1.0")),
+    }
+}
+
+/// Parse a pedal or controller event string
+///
+/// Pedals are `ped+`, `ped-`, `sos+` and `sos-`; controllers are
+/// `<name>=<value>`. Returns `None` if `s` is neither.
+fn parse_control(s: &str) -> Option<Result<Control, Spanned>> {
+    if let Some((name, value)) = s.split_once('=') {
+        return Some(parse_controller(s, name, value));
+    }
+
+    let (pedal, rest) = if let Some(rest) = s.strip_prefix("ped") {
+        (Pedal::Sustain, rest)
+    } else if let Some(rest) = s.strip_prefix("sos") {
+        (Pedal::Sostenuto, rest)
+    } else {
+        return None;
+    };
+
+    Some(match rest {
+        "+" => Ok(Control::Pedal(pedal, KeyDirection::Down)),
+        "-" => Ok(Control::Pedal(pedal, KeyDirection::Up)),
+        _ => Err((
+            ParseErrorKind::InvalidEvent(format!("{} (expected {}+ or {}-)", s, &s[..3], &s[..3])),
+            0..s.len(),
+        )),
+    })
+}
+
 /// Parse a single event string
 /// Format: <octave><note><accidental><direction>
 /// Examples: 4c#d, 4au, 3f#u
-fn parse_event(s: &str) -> Result<Event, ParseError> {
-    let s = s.trim();
+///
+/// Error ranges are byte offsets into `s`, which must already be trimmed.
+fn parse_event(s: &str) -> Result<Event, Spanned> {
     if s.is_empty() {
-        return Err(ParseError::InvalidEvent("empty event".to_string()));
+        return Err((
+            ParseErrorKind::InvalidEvent("empty event".to_string()),
+            0..0,
+        ));
     }
 
     // Last character must be 'd' (down) or 'u' (up)
-    let (note_part, direction_char) = s.split_at(s.len() - 1);
+    let (direction_start, direction_char) = s.char_indices().last().expect("non-empty");
+    let note_part = &s[..direction_start];
     let direction = match direction_char {
-        "d" => KeyDirection::Down,
-        "u" => KeyDirection::Up,
-        _ => return Err(ParseError::InvalidDirection(direction_char.to_string())),
+        'd' => KeyDirection::Down,
+        'u' => KeyDirection::Up,
+        _ => {
+            return Err((
+                ParseErrorKind::InvalidDirection(direction_char.to_string()),
+                direction_start..s.len(),
+            ))
+        }
     };
 
     // Parse note part: <octave><note><accidental>
     // First character must be octave digit
     if note_part.is_empty() {
-        return Err(ParseError::InvalidNote("missing note".to_string()));
+        return Err((
+            ParseErrorKind::InvalidNote("missing note".to_string()),
+            0..s.len(),
+        ));
     }
 
-    let mut chars = note_part.chars();
-    let octave_char = chars
-        .next()
-        .ok_or_else(|| ParseError::InvalidOctave("missing".to_string()))?;
-    let octave = octave_char
-        .to_digit(10)
-        .ok_or_else(|| ParseError::InvalidOctave(octave_char.to_string()))? as u8;
+    let octave_char = note_part.chars().next().expect("non-empty");
+    let octave_len = octave_char.len_utf8();
+    let octave = octave_char.to_digit(10).ok_or_else(|| {
+        (
+            ParseErrorKind::InvalidOctave(octave_char.to_string()),
+            0..octave_len,
+        )
+    })? as u8;
 
     // Remaining is pitch class (could be "c", "c#", "d", etc.)
-    let pitch_str: String = chars.collect();
+    let pitch_str = &note_part[octave_len..];
     if pitch_str.is_empty() {
-        return Err(ParseError::InvalidPitchClass("missing".to_string()));
+        return Err((
+            ParseErrorKind::InvalidPitchClass("missing".to_string()),
+            0..s.len(),
+        ));
     }
 
-    let pitch_class = PitchClass::from_str(&pitch_str)?;
+    let pitch_class =
+        PitchClass::from_str(pitch_str).map_err(|e| (e.kind, octave_len..note_part.len()))?;
 
-    Ok(Event {
-        note: Note {
+    Ok(Event::new(
+        Note {
             octave,
             pitch_class,
         },
         direction,
-    })
+    ))
 }
 
-/// Parse a line of the transcription format
-/// Format: +<delta>| event1, event2, ...  # comment
-pub fn parse_line(line: &str) -> Result<TimedEvents, ParseError> {
-    // Remove comments (split on " #" to preserve sharp signs in notes like "c#")
-    let line = line.split(" #").next().unwrap_or(line).trim();
+/// Parse a line, pushing every error found onto `errors`
+///
+/// Returns `None` if the line had errors.
+fn parse_line_at(
+    line: &str,
+    line_number: usize,
+    errors: &mut Vec<ParseError>,
+) -> Option<TimedEvents> {
+    let error_count = errors.len();
+    let mut report = |kind: ParseErrorKind, range: Range<usize>| {
+        errors.push(ParseError::new(
+            kind,
+            Span::from_byte_range(line_number, line, range),
+        ));
+    };
 
-    if line.is_empty() {
-        return Ok(TimedEvents {
+    // Remove comments (a '#' right after "<octave><note>" is a sharp sign)
+    let content = &line[..comment_start(line).unwrap_or(line.len())];
+    let start = content.len() - content.trim_start().len();
+    let content = content.trim_end();
+
+    if content.len() <= start {
+        return Some(TimedEvents {
             delta: 0,
             events: vec![],
+            controls: vec![],
         });
     }
 
     // Split by | to get timestep and events
-    let parts: Vec<&str> = line.splitn(2, '|').collect();
-    if parts.len() != 2 {
-        return Err(ParseError::InvalidLine(
-            "expected format: +<delta>| events".to_string(),
-        ));
-    }
+    let Some(bar) = content.find('|') else {
+        report(
+            ParseErrorKind::InvalidLine("expected format: +<delta>| events".to_string()),
+            start..content.len(),
+        );
+        return None;
+    };
 
     // Parse timestep delta (starts with +)
-    let timestep_part = parts[0].trim();
+    let timestep_part = content[start..bar].trim_end();
+    let timestep_range = start..start + timestep_part.len().max(1);
+
+    let mut delta = 0;
     if !timestep_part.starts_with('+') {
-        return Err(ParseError::InvalidTimestep(
-            "timestep must start with +".to_string(),
-        ));
+        report(
+            ParseErrorKind::InvalidTimestep("timestep must start with +".to_string()),
+            timestep_range,
+        );
+    } else {
+        match timestep_part[1..].parse::<usize>() {
+            Ok(value) => delta = value,
+            Err(_) => report(
+                ParseErrorKind::InvalidTimestep(timestep_part.to_string()),
+                timestep_range,
+            ),
+        }
     }
 
-    let delta_str = &timestep_part[1..];
-    let delta = delta_str
-        .parse::<usize>()
-        .map_err(|_| ParseError::InvalidTimestep(timestep_part.to_string()))?;
-
     // Parse events (comma-separated)
-    let events_part = parts[1].trim();
-    let events: Vec<Event> = if events_part.is_empty() {
-        vec![]
+    let events_start = bar + 1;
+    let mut events = Vec::new();
+    let mut controls = Vec::new();
+    for (offset, event_str) in split_with_offsets(&content[events_start..], ',') {
+        if event_str.is_empty() {
+            continue;
+        }
+        let offset = offset + events_start;
+        let parsed = match parse_control(event_str) {
+            Some(control) => control.map(|c| controls.push(c)),
+            None => parse_event(event_str).map(|e| events.push(e)),
+        };
+        if let Err((kind, range)) = parsed {
+            report(kind, range.start + offset..range.end + offset);
+        }
+    }
+
+    if errors.len() > error_count {
+        None
     } else {
-        events_part
-            .split(',')
-            .map(str::trim)
-            .filter(|s| !s.is_empty())
-            .map(parse_event)
-            .collect::<Result<Vec<_>, _>>()?
-    };
+        Some(TimedEvents {
+            delta,
+            events,
+            controls,
+        })
+    }
+}
+
+/// Parse a line of the transcription format
+/// Format: +<delta>| event1, event2, ...  # comment
+///
+/// Returns the first error on the line (reported as line 1).
+pub fn parse_line(line: &str) -> Result<TimedEvents, ParseError> {
+    let mut errors = Vec::new();
+    parse_line_at(line, 1, &mut errors).ok_or_else(|| errors.remove(0))
+}
+
+/// Append a parsed line to an event list, dropping empty lines after the first
+fn push_timed(result: &mut Vec<TimedEvents>, timed: TimedEvents) {
+    if !timed.events.is_empty() || !timed.controls.is_empty() || result.is_empty() {
+        result.push(timed);
+    }
+}
 
-    Ok(TimedEvents { delta, events })
+/// Check whether a line holds no content besides whitespace and comments
+fn is_blank(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+/// Parse an expanded source line, tagging any errors with its file
+fn parse_source_line(line: &SourceLine, errors: &mut Vec<ParseError>) -> Option<TimedEvents> {
+    let error_count = errors.len();
+    let timed = parse_line_at(&line.text, line.line, errors);
+    for error in errors[error_count..].iter_mut() {
+        error.file = line.file.clone();
+    }
+    timed
 }
 
 /// Parse full transcription text
-/// Returns a list of timed events in chronological order
-pub fn parse_transcription(text: &str) -> Result<Vec<TimedEvents>, ParseError> {
+/// Returns a list of timed events in chronological order,
+/// or every error found in the text
+///
+/// Included files are resolved against the current directory.
+pub fn parse_transcription(text: &str) -> Result<Vec<TimedEvents>, ParseErrors> {
+    parse_transcription_with(text, &FileLoader::default())
+}
+
+/// Parse full transcription text, loading included files with `loader`
+pub fn parse_transcription_with(
+    text: &str,
+    loader: &dyn SourceLoader,
+) -> Result<Vec<TimedEvents>, ParseErrors> {
+    let expanded = expand(text, loader);
     let mut result = Vec::new();
+    let mut errors = expanded.errors;
+
+    for line in &expanded.lines {
+        if is_blank(&line.text) {
+            continue;
+        }
+
+        if let Some(timed) = parse_source_line(line, &mut errors) {
+            push_timed(&mut result, timed);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(result)
+    } else {
+        Err(ParseErrors::new(errors, expanded.sources))
+    }
+}
+
+/// Parse a track directive, returning the track and the byte range of its name
+/// Format: @track <name> [instrument=<name>] [gain=<value>]
+fn parse_track_directive(line: &str) -> Result<(Track, Range<usize>), Spanned> {
+    let content = &line[..comment_start(line).unwrap_or(line.len())];
+    let mut words = words_with_offsets(content);
+    let whole = |content: &str| {
+        let start = content.len() - content.trim_start().len();
+        start..content.trim_end().len()
+    };
+
+    match words.next() {
+        Some((_, "@track")) => {}
+        Some((offset, word)) => {
+            return Err((
+                ParseErrorKind::InvalidTrack(format!("unknown directive: {}", word)),
+                offset..offset + word.len(),
+            ))
+        }
+        None => unreachable!("directive lines are not blank"),
+    }
+
+    let (name_offset, name) = words.next().ok_or_else(|| {
+        (
+            ParseErrorKind::InvalidTrack("missing track name".to_string()),
+            whole(content),
+        )
+    })?;
+    if name.contains('=') {
+        return Err((
+            ParseErrorKind::InvalidTrack(format!("expected track name, got {}", name)),
+            name_offset..name_offset + name.len(),
+        ));
+    }
+
+    let mut track = Track::new(name);
+    for (offset, option) in words {
+        let range = offset..offset + option.len();
+        match option.split_once('=') {
+            Some(("instrument", value)) if !value.is_empty() => {
+                track.instrument = Some(value.to_string());
+            }
+            Some(("gain", value)) => {
+                track.gain = value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|g| g.is_finite() && *g >= 0.0)
+                    .ok_or_else(|| {
+                        (
+                            ParseErrorKind::InvalidTrack(format!("invalid gain: {}", value)),
+                            range.clone(),
+                        )
+                    })?;
+            }
+            _ => {
+                return Err((
+                    ParseErrorKind::InvalidTrack(format!("unknown option: {}", option)),
+                    range,
+                ))
+            }
+        }
+    }
+
+    Ok((track, name_offset..name_offset + name.len()))
+}
+
+/// Parse a transcription that may contain multiple `@track` sections
+///
+/// Returns the tracks in declaration order. Lines before the first directive
+/// form an implicit track named [`DEFAULT_TRACK_NAME`], which is omitted if empty.
+///
+/// Included files are resolved against the current directory.
+pub fn parse_tracks(text: &str) -> Result<Vec<Track>, ParseErrors> {
+    parse_tracks_with(text, &FileLoader::default())
+}
+
+/// Parse a multi-track transcription, loading included files with `loader`
+pub fn parse_tracks_with(text: &str, loader: &dyn SourceLoader) -> Result<Vec<Track>, ParseErrors> {
+    let expanded = expand(text, loader);
+    let mut tracks = vec![Track::new(DEFAULT_TRACK_NAME)];
+    let mut errors = expanded.errors;
 
-    for line in text.lines() {
-        let line = line.trim();
-        if line.is_empty() || line.starts_with('#') {
+    for source_line in &expanded.lines {
+        let line = source_line.text.as_str();
+        let line_number = source_line.line;
+        if is_blank(line) {
             continue;
         }
 
-        let timed = parse_line(line)?;
-        if !timed.events.is_empty() || result.is_empty() {
-            result.push(timed);
+        if line.trim_start().starts_with('@') {
+            match parse_track_directive(line) {
+                Ok((track, name_range)) if tracks.iter().any(|t| t.name == track.name) => {
+                    let mut error = ParseError::new(
+                        ParseErrorKind::InvalidTrack(format!(
+                            "duplicate track name: {}",
+                            track.name
+                        )),
+                        Span::from_byte_range(line_number, line, name_range),
+                    );
+                    error.file = source_line.file.clone();
+                    errors.push(error);
+                }
+                Ok((track, _)) => tracks.push(track),
+                Err((kind, range)) => {
+                    let mut error =
+                        ParseError::new(kind, Span::from_byte_range(line_number, line, range));
+                    error.file = source_line.file.clone();
+                    errors.push(error);
+                }
+            }
+            continue;
+        }
+
+        if let Some(timed) = parse_source_line(source_line, &mut errors) {
+            let current = tracks.last_mut().expect("at least one track");
+            push_timed(&mut current.events, timed);
         }
     }
 
-    Ok(result)
+    if !errors.is_empty() {
+        return Err(ParseErrors::new(errors, expanded.sources));
+    }
+
+    // Drop the implicit track if everything was declared explicitly
+    if tracks.len() > 1 && tracks[0].events.is_empty() {
+        tracks.remove(0);
+    }
+
+    Ok(tracks)
 }
 
 #[cfg(test)]
@@ -262,6 +1016,61 @@ mod tests {
         assert!(PitchClass::from_str("h").is_err());
     }
 
+    #[test]
+    fn test_note_transpose() {
+        let c4 = Note {
+            octave: 4,
+            pitch_class: PitchClass::C,
+        };
+        let b3 = c4.transposed(-1).unwrap();
+        assert_eq!(b3.octave, 3);
+        assert_eq!(b3.pitch_class, PitchClass::B);
+
+        let g5 = c4.transposed(19).unwrap();
+        assert_eq!((g5.octave, g5.pitch_class), (5, PitchClass::G));
+
+        for index in 0..120 {
+            assert_eq!(
+                Note::from_semitone_index(index).unwrap().semitone_index(),
+                index
+            );
+        }
+
+        // Bounded to octaves 0-9
+        assert!(c4.transposed(-49).is_none());
+        assert!(c4.transposed(71).is_some());
+        assert!(c4.transposed(72).is_none());
+
+        assert_eq!(c4.transposed(1).unwrap().to_string(), "4c#");
+    }
+
+    #[test]
+    fn test_note_midi_and_scientific_pitch() {
+        assert_eq!(CONCERT_A.midi_number(), Some(69));
+        assert_eq!(Note::from_midi_number(60).unwrap().to_string(), "4c");
+        assert_eq!(Note::from_midi_number(11), None);
+        assert_eq!(Note::from_midi_number(128), None);
+        for number in 12..=127 {
+            let note = Note::from_midi_number(number).unwrap();
+            assert_eq!(note.midi_number(), Some(number));
+        }
+        // 9b is above the MIDI range
+        assert_eq!(Note::from_semitone_index(119).unwrap().midi_number(), None);
+
+        let cs4 = Note::from_scientific_pitch("C#4").unwrap();
+        assert_eq!(cs4.to_string(), "4c#");
+        assert_eq!(cs4.scientific_pitch(), "C#4");
+        assert_eq!(Note::from_scientific_pitch("a4"), Some(CONCERT_A));
+        assert_eq!(
+            Note::from_scientific_pitch("Bb3"),
+            Note::from_scientific_pitch("A#3")
+        );
+        assert_eq!(Note::from_scientific_pitch("Cb0"), None);
+        assert_eq!(Note::from_scientific_pitch("H4"), None);
+        assert_eq!(Note::from_scientific_pitch("A10"), None);
+        assert_eq!(Note::from_scientific_pitch("A"), None);
+    }
+
     #[test]
     fn test_parse_event() {
         let event = parse_event("4c#d").unwrap();
@@ -334,6 +1143,183 @@ mod tests {
         assert!(parse_event("4xd").is_err()); // invalid note
     }
 
+    #[test]
+    fn test_parse_tracks() {
+        let text = r#"
+@track melody gain=0.8
++0| 5cd
++2| 5cu
+
+@track bass instrument=pluck   # low end
++0| 2cd
++4| 2cu
+        "#;
+
+        let tracks = parse_tracks(text).unwrap();
+        assert_eq!(tracks.len(), 2);
+
+        assert_eq!(tracks[0].name, "melody");
+        assert_eq!(tracks[0].instrument_name(), "melody");
+        assert!((tracks[0].gain - 0.8).abs() < 1e-6);
+        assert_eq!(tracks[0].events.len(), 2);
+
+        assert_eq!(tracks[1].name, "bass");
+        assert_eq!(tracks[1].instrument_name(), "pluck");
+        assert_eq!(tracks[1].gain, 1.0);
+        assert_eq!(tracks[1].events[1].delta, 4);
+    }
+
+    #[test]
+    fn test_parse_tracks_tab_separated() {
+        let tracks = parse_tracks("@track\tbass\t instrument=pluck\tgain=0.5\n+0| 2cd\n").unwrap();
+        assert_eq!(tracks[0].name, "bass");
+        assert_eq!(tracks[0].instrument_name(), "pluck");
+        assert_eq!(tracks[0].gain, 0.5);
+    }
+
+    #[test]
+    fn test_parse_tracks_implicit_main() {
+        let text = "+0| 4cd\n+1| 4cu\n";
+        let tracks = parse_tracks(text).unwrap();
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].name, DEFAULT_TRACK_NAME);
+        assert_eq!(tracks[0].events, parse_transcription(text).unwrap());
+    }
+
+    #[test]
+    fn test_parse_tracks_invalid() {
+        assert!(parse_tracks("@track").is_err()); // missing name
+        assert!(parse_tracks("@track a gain=loud").is_err());
+        assert!(parse_tracks("@track a volume=1").is_err());
+        assert!(parse_tracks("@tempo 120").is_err());
+        assert!(parse_tracks("@track a\n@track a").is_err()); // duplicate
+    }
+
+    #[test]
+    fn test_comment_without_space() {
+        let timed = parse_line("+1| 4cd# tight comment").unwrap();
+        assert_eq!(timed.events.len(), 1);
+        assert_eq!(timed.events[0].note.pitch_class, PitchClass::C);
+
+        let timed = parse_line("+1| 4c#d, 4f#u#comment").unwrap();
+        assert_eq!(timed.events.len(), 2);
+        assert_eq!(timed.events[1].note.pitch_class, PitchClass::FSharp);
+    }
+
+    #[test]
+    fn test_error_span() {
+        let err = parse_line("+1| 4cd, 4xd").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidPitchClass("x".to_string()));
+        assert_eq!(err.span, Span::new(1, 11, 1));
+
+        let err = parse_line("  +abc| 4cd").unwrap_err();
+        assert!(matches!(err.kind, ParseErrorKind::InvalidTimestep(_)));
+        assert_eq!(err.span, Span::new(1, 3, 4));
+
+        let err = parse_line("+1| 4c#x").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidDirection("x".to_string()));
+        assert_eq!(err.span, Span::new(1, 8, 1));
+    }
+
+    #[test]
+    fn test_multiple_errors_collected() {
+        let text = "+0| 4cd\n+1| 4xd, 4cu\n4du\n+1| 9gz\n";
+        let errors = parse_transcription(text).unwrap_err().errors;
+
+        let positions: Vec<(usize, usize)> = errors
+            .iter()
+            .map(|e| (e.span.line, e.span.column))
+            .collect();
+        assert_eq!(positions, vec![(2, 6), (3, 1), (4, 7)]);
+        assert!(matches!(errors[1].kind, ParseErrorKind::InvalidLine(_)));
+    }
+
+    #[test]
+    fn test_track_directive_error_span() {
+        let errors = parse_tracks("+0| 4cd\n@track bass gain=-1\n")
+            .unwrap_err()
+            .errors;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span, Span::new(2, 13, 7));
+
+        // The name also appears inside "@track"
+        let errors = parse_tracks("@track t\n+0| 4cd\n@track  t gain=1\n")
+            .unwrap_err()
+            .errors;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].kind,
+            ParseErrorKind::InvalidTrack("duplicate track name: t".to_string())
+        );
+        assert_eq!(errors[0].span, Span::new(3, 9, 1));
+    }
+
+    #[test]
+    fn test_render_diagnostic() {
+        let source = "+0| 4cd\n+1| 4cd, 4hd\n";
+        let errors = parse_transcription(source).unwrap_err();
+        let rendered = errors.render(source, "song.txt");
+
+        let expected = "error: Invalid pitch class: h\n \
+                        --> song.txt:2:11\n  \
+                        |\n\
+                        2 | +1| 4cd, 4hd\n  \
+                        |           ^\n";
+        assert_eq!(rendered, expected);
+    }
+
+    #[test]
+    fn test_parse_sections_and_repeats() {
+        let text = r#"
+:motif
++1| 5ed
++1| 5eu
+:end
+
++0| 4cd
+|:
+*motif
+:| x2
++1| 4cu
+        "#;
+
+        let result = parse_transcription(text).unwrap();
+        let deltas: Vec<usize> = result.iter().map(|t| t.delta).collect();
+        assert_eq!(deltas, vec![0, 1, 1, 1, 1, 1]);
+        assert_eq!(result[1].events[0].note.octave, 5);
+    }
+
+    #[test]
+    fn test_sections_inside_tracks() {
+        let text = ":riff\n+0| 2cd\n+2| 2cu\n:end\n@track bass\n*riff x2\n@track lead\n+1| 5cd";
+        let tracks = parse_tracks(text).unwrap();
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[0].events.len(), 4);
+        assert_eq!(tracks[1].events.len(), 1);
+    }
+
+    #[test]
+    fn test_repeated_error_reported_once() {
+        let text = "|:\n+1| 4hd\n:| x4";
+        let errors = parse_transcription(text).unwrap_err().errors;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span.line, 2);
+    }
+
+    #[test]
+    fn test_included_error_render() {
+        let mut files = std::collections::HashMap::new();
+        files.insert("part.txt".to_string(), "+0| 4cd\n+1| 4zd".to_string());
+
+        let source = "@include part.txt";
+        let errors = parse_transcription_with(source, &files).unwrap_err();
+        assert_eq!(errors.errors[0].file.as_deref(), Some("part.txt"));
+
+        let rendered = errors.render(source, "song.txt");
+        assert!(rendered.contains("--> part.txt:2:6"), "{}", rendered);
+        assert!(rendered.contains("2 | +1| 4zd"), "{}", rendered);
+    }
+
     #[test]
     fn test_large_timestep() {
         let result = parse_line("+1000000| 4c#d");
@@ -341,4 +1327,51 @@ mod tests {
         let timed = result.unwrap();
         assert_eq!(timed.delta, 1000000);
     }
+
+    #[test]
+    fn test_parse_pedals() {
+        let timed = parse_line("+2| ped-, 4cd, ped+, sos+").unwrap();
+        assert_eq!(timed.events.len(), 1);
+        assert_eq!(
+            timed.controls,
+            vec![
+                Control::Pedal(Pedal::Sustain, KeyDirection::Up),
+                Control::Pedal(Pedal::Sustain, KeyDirection::Down),
+                Control::Pedal(Pedal::Sostenuto, KeyDirection::Down),
+            ]
+        );
+        assert!(!timed.controls[0].applies_after_keys());
+        assert!(timed.controls[1].applies_after_keys());
+
+        // Pedal-only lines are kept
+        let events = parse_transcription("+0| 4cd\n+1| ped+\n+1| 4cu, ped-").unwrap();
+        assert_eq!(events.len(), 3);
+        assert!(events[1].events.is_empty());
+
+        let err = parse_line("+0| ped*").unwrap_err();
+        assert!(matches!(err.kind, ParseErrorKind::InvalidEvent(_)));
+        assert_eq!(err.span, Span::new(1, 5, 4));
+    }
+
+    #[test]
+    fn test_parse_controllers() {
+        let timed = parse_line("+0| bend=-0.5, mod=1, expr=0.25, cc74=0.5, 4cd").unwrap();
+        assert_eq!(
+            timed.controls,
+            vec![
+                Control::PitchBend(-0.5),
+                Control::ControlChange(CC_MOD_WHEEL, 1.0),
+                Control::ControlChange(CC_EXPRESSION, 0.25),
+                Control::ControlChange(74, 0.5),
+            ]
+        );
+        assert!(timed.controls.iter().all(|c| !c.applies_after_keys()));
+
+        let err = parse_line("+0| bend=2").unwrap_err();
+        assert_eq!(err.span, Span::new(1, 10, 1));
+        let err = parse_line("+0| cc200=0.5").unwrap_err();
+        assert_eq!(err.span, Span::new(1, 5, 5));
+        assert!(parse_line("+0| mod=-0.1").is_err());
+        assert!(parse_line("+0| expr=").is_err());
+    }
 }
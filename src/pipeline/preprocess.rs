@@ -0,0 +1,578 @@
+//! Preprocessor for the transcription format
+//!
+//! Expands structural constructs into plain transcription lines before
+//! they are parsed:
+//!
+//! ```text
+//! :verse              # define a named section (not played here)
+//! +1| 4cd
+//! +1| 4cu
+//! :end
+//!
+//! *verse              # play a section
+//! *verse x2           # play a section twice
+//!
+//! |:                  # start of a repeated block
+//! +1| 4ed
+//! +1| 4eu
+//! :| x3               # end of block, played 3 times (default 2)
+//!
+//! @include intro.txt  # insert another file (relative to this one)
+//! ```
+//!
+//! Sections must be defined before they are played. Sections and repeats
+//! may nest; a section may not play itself, directly or indirectly. The
+//! expansion may be at most [`MAX_EXPANDED_LINES`] lines long.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::pipeline::parser::{comment_start, ParseError, ParseErrorKind, Span};
+
+/// Most lines a transcription may expand to
+pub const MAX_EXPANDED_LINES: usize = 1_000_000;
+
+/// Loads the text of included files
+pub trait SourceLoader {
+    /// Read the file at `path` (already resolved against the including file)
+    fn load(&self, path: &str) -> io::Result<String>;
+}
+
+/// Loads included files from the filesystem
+///
+/// Paths of top-level includes are resolved against `base_dir`.
+#[derive(Debug, Clone)]
+pub struct FileLoader {
+    base_dir: PathBuf,
+}
+
+impl FileLoader {
+    pub fn new(base_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            base_dir: base_dir.into(),
+        }
+    }
+}
+
+impl Default for FileLoader {
+    fn default() -> Self {
+        Self::new(".")
+    }
+}
+
+impl SourceLoader for FileLoader {
+    fn load(&self, path: &str) -> io::Result<String> {
+        std::fs::read_to_string(self.base_dir.join(path))
+    }
+}
+
+/// In-memory sources keyed by path (useful for tests and embedded songs)
+impl SourceLoader for HashMap<String, String> {
+    fn load(&self, path: &str) -> io::Result<String> {
+        self.get(path)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{}: not found", path)))
+    }
+}
+
+/// A line of transcription text together with where it came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLine {
+    pub text: String,
+    /// 1-based line number within its file
+    pub line: usize,
+    /// Included file the line came from (`None` for the top-level text)
+    pub file: Option<String>,
+}
+
+impl SourceLine {
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        let start = self.text.len() - self.text.trim_start().len();
+        let content = &self.text[..comment_start(&self.text).unwrap_or(self.text.len())];
+        let len = content.trim().chars().count();
+        let column = self.text[..start].chars().count() + 1;
+        let mut error = ParseError::new(kind, Span::new(self.line, column, len));
+        error.file = self.file.clone();
+        error
+    }
+}
+
+/// Structural directive found on a line
+#[derive(Debug, Clone, PartialEq)]
+enum Directive<'a> {
+    DefineSection(&'a str),
+    EndSection,
+    PlaySection(&'a str, usize),
+    RepeatStart,
+    RepeatEnd(usize),
+    Include(&'a str),
+}
+
+/// Parse an optional `x<count>` suffix
+fn parse_count(word: Option<&str>) -> Result<Option<usize>, String> {
+    match word {
+        None => Ok(None),
+        Some(word) => word
+            .strip_prefix('x')
+            .and_then(|n| n.parse::<usize>().ok())
+            .filter(|&n| n > 0)
+            .map(Some)
+            .ok_or_else(|| format!("invalid repeat count: {}", word)),
+    }
+}
+
+fn is_section_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Recognise a structural directive, if the line holds one
+fn parse_directive(text: &str) -> Option<Result<Directive<'_>, String>> {
+    let content = text[..comment_start(text).unwrap_or(text.len())].trim();
+    let mut words = content.split_whitespace();
+    let first = words.next()?;
+    let second = words.next();
+    let extra = words.next();
+
+    let directive = if first == "|:" {
+        second.map_or(Ok(Directive::RepeatStart), |w| {
+            Err(format!("unexpected text after |: {}", w))
+        })
+    } else if first == ":|" {
+        parse_count(second).map(|count| Directive::RepeatEnd(count.unwrap_or(2)))
+    } else if first == ":end" {
+        second.map_or(Ok(Directive::EndSection), |w| {
+            Err(format!("unexpected text after :end {}", w))
+        })
+    } else if let Some(name) = first.strip_prefix(':') {
+        if !is_section_name(name) {
+            Err(format!("invalid section name: {}", name))
+        } else if let Some(w) = second {
+            Err(format!("unexpected text after section name: {}", w))
+        } else {
+            Ok(Directive::DefineSection(name))
+        }
+    } else if let Some(name) = first.strip_prefix('*') {
+        if !is_section_name(name) {
+            Err(format!("invalid section name: {}", name))
+        } else {
+            parse_count(second).map(|count| Directive::PlaySection(name, count.unwrap_or(1)))
+        }
+    } else if first == "@include" {
+        match second.map(|p| p.trim_matches('"')) {
+            Some(path) if !path.is_empty() => Ok(Directive::Include(path)),
+            _ => Err("missing include path".to_string()),
+        }
+    } else {
+        return None;
+    };
+
+    Some(match (directive, extra) {
+        (Ok(_), Some(w)) => Err(format!("unexpected text: {}", w)),
+        (result, _) => result,
+    })
+}
+
+/// State shared while expanding a file and everything it includes
+struct Expander<'a> {
+    loader: &'a dyn SourceLoader,
+    sections: HashMap<String, Vec<SourceLine>>,
+    /// Sections currently being played (for recursion detection)
+    playing: Vec<String>,
+    /// Files currently being included (for recursion detection)
+    including: Vec<String>,
+    /// Text of every included file, for rendering diagnostics
+    sources: Vec<(String, String)>,
+    errors: Vec<ParseError>,
+    /// Repeat and play directives being expanded, outermost first
+    repeating: Vec<SourceLine>,
+    /// Whether the expansion reached [`MAX_EXPANDED_LINES`]
+    overflowed: bool,
+}
+
+impl Expander<'_> {
+    /// Find the `:|` closing the repeat opened just before `lines[start]`
+    fn find_repeat_end(lines: &[SourceLine], start: usize) -> Option<usize> {
+        let mut depth = 0;
+        for (i, line) in lines.iter().enumerate().skip(start) {
+            match parse_directive(&line.text) {
+                Some(Ok(Directive::RepeatStart)) => depth += 1,
+                Some(Ok(Directive::RepeatEnd(_))) if depth == 0 => return Some(i),
+                Some(Ok(Directive::RepeatEnd(_))) => depth -= 1,
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Find the `:end` closing the section defined just before `lines[start]`
+    fn find_section_end(lines: &[SourceLine], start: usize) -> Result<Option<usize>, usize> {
+        for (i, line) in lines.iter().enumerate().skip(start) {
+            match parse_directive(&line.text) {
+                Some(Ok(Directive::EndSection)) => return Ok(Some(i)),
+                Some(Ok(Directive::DefineSection(_))) => return Err(i),
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    /// Expand `body` `count` times on behalf of the directive on `line`
+    ///
+    /// Stops early once the expansion overflows, or if a pass adds no lines
+    /// (every further pass would add nothing either).
+    fn repeat(
+        &mut self,
+        line: &SourceLine,
+        body: &[SourceLine],
+        count: usize,
+        out: &mut Vec<SourceLine>,
+    ) {
+        self.repeating.push(line.clone());
+        for _ in 0..count {
+            let before = out.len();
+            self.expand(body, out);
+            if self.overflowed || out.len() == before {
+                break;
+            }
+        }
+        self.repeating.pop();
+    }
+
+    /// Expand `lines` into plain transcription lines appended to `out`
+    fn expand(&mut self, lines: &[SourceLine], out: &mut Vec<SourceLine>) {
+        let mut i = 0;
+        while i < lines.len() && !self.overflowed {
+            let line = &lines[i];
+            i += 1;
+
+            let directive = match parse_directive(&line.text) {
+                None if out.len() == MAX_EXPANDED_LINES => {
+                    // Blame the outermost repeat, which is what multiplied
+                    // the lines
+                    let cause = self.repeating.first().unwrap_or(line);
+                    self.errors
+                        .push(cause.error(ParseErrorKind::InvalidDirective(format!(
+                            "expands to more than {} lines",
+                            MAX_EXPANDED_LINES
+                        ))));
+                    self.overflowed = true;
+                    return;
+                }
+                None => {
+                    out.push(line.clone());
+                    continue;
+                }
+                Some(Err(message)) => {
+                    self.errors
+                        .push(line.error(ParseErrorKind::InvalidDirective(message)));
+                    continue;
+                }
+                Some(Ok(directive)) => directive,
+            };
+
+            match directive {
+                Directive::DefineSection(name) => match Self::find_section_end(lines, i) {
+                    Ok(Some(end)) => {
+                        self.sections
+                            .insert(name.to_string(), lines[i..end].to_vec());
+                        i = end + 1;
+                    }
+                    Ok(None) => {
+                        self.errors
+                            .push(line.error(ParseErrorKind::InvalidDirective(format!(
+                                "section {} is missing :end",
+                                name
+                            ))));
+                        i = lines.len();
+                    }
+                    Err(nested) => {
+                        self.errors
+                            .push(
+                                lines[nested].error(ParseErrorKind::InvalidDirective(format!(
+                                    "section definitions cannot be nested inside {}",
+                                    name
+                                ))),
+                            );
+                        i = nested + 1;
+                    }
+                },
+                Directive::EndSection => {
+                    self.errors
+                        .push(line.error(ParseErrorKind::InvalidDirective(
+                            ":end without a section".to_string(),
+                        )));
+                }
+                Directive::PlaySection(name, count) => {
+                    if self.playing.iter().any(|n| n == name) {
+                        self.errors
+                            .push(line.error(ParseErrorKind::InvalidDirective(format!(
+                                "section {} plays itself",
+                                name
+                            ))));
+                        continue;
+                    }
+                    let Some(body) = self.sections.get(name).cloned() else {
+                        self.errors
+                            .push(line.error(ParseErrorKind::InvalidDirective(format!(
+                                "undefined section: {}",
+                                name
+                            ))));
+                        continue;
+                    };
+                    self.playing.push(name.to_string());
+                    self.repeat(line, &body, count, out);
+                    self.playing.pop();
+                }
+                Directive::RepeatStart => match Self::find_repeat_end(lines, i) {
+                    Some(end) => {
+                        let count = match parse_directive(&lines[end].text) {
+                            Some(Ok(Directive::RepeatEnd(count))) => count,
+                            _ => unreachable!("find_repeat_end returns a repeat end"),
+                        };
+                        self.repeat(line, &lines[i..end], count, out);
+                        i = end + 1;
+                    }
+                    None => {
+                        self.errors
+                            .push(line.error(ParseErrorKind::InvalidDirective(
+                                "|: is missing :|".to_string(),
+                            )));
+                    }
+                },
+                Directive::RepeatEnd(_) => {
+                    self.errors
+                        .push(line.error(ParseErrorKind::InvalidDirective(
+                            ":| without |:".to_string(),
+                        )));
+                }
+                Directive::Include(path) => self.include(line, path, out),
+            }
+        }
+    }
+
+    /// Expand an included file in place
+    fn include(&mut self, line: &SourceLine, path: &str, out: &mut Vec<SourceLine>) {
+        // Resolve relative to the directory of the including file
+        let resolved = match &line.file {
+            Some(parent) => Path::new(parent)
+                .parent()
+                .unwrap_or(Path::new(""))
+                .join(path)
+                .to_string_lossy()
+                .into_owned(),
+            None => path.to_string(),
+        };
+
+        if self.including.contains(&resolved) {
+            self.errors
+                .push(line.error(ParseErrorKind::InvalidDirective(format!(
+                    "{} includes itself",
+                    resolved
+                ))));
+            return;
+        }
+
+        let text = match self.loader.load(&resolved) {
+            Ok(text) => text,
+            Err(e) => {
+                self.errors
+                    .push(line.error(ParseErrorKind::InvalidDirective(format!(
+                        "cannot include {}: {}",
+                        resolved, e
+                    ))));
+                return;
+            }
+        };
+
+        let lines = source_lines(&text, Some(&resolved));
+        if !self.sources.iter().any(|(name, _)| *name == resolved) {
+            self.sources.push((resolved.clone(), text));
+        }
+
+        self.including.push(resolved);
+        self.expand(&lines, out);
+        self.including.pop();
+    }
+}
+
+/// Split text into source lines tagged with their origin
+fn source_lines(text: &str, file: Option<&str>) -> Vec<SourceLine> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| SourceLine {
+            text: line.to_string(),
+            line: index + 1,
+            file: file.map(str::to_string),
+        })
+        .collect()
+}
+
+/// Result of expanding a transcription
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expanded {
+    /// Plain transcription lines in playing order
+    pub lines: Vec<SourceLine>,
+    /// Text of every included file as `(path, text)`
+    pub sources: Vec<(String, String)>,
+    /// Errors found while expanding
+    pub errors: Vec<ParseError>,
+}
+
+/// Expand sections, repeats and includes into plain transcription lines
+///
+/// # Arguments
+/// * `text` - Top-level transcription text
+/// * `loader` - Source of included files
+pub fn expand(text: &str, loader: &dyn SourceLoader) -> Expanded {
+    let mut expander = Expander {
+        loader,
+        sections: HashMap::new(),
+        playing: Vec::new(),
+        including: Vec::new(),
+        sources: Vec::new(),
+        errors: Vec::new(),
+        repeating: Vec::new(),
+        overflowed: false,
+    };
+
+    let mut lines = Vec::new();
+    expander.expand(&source_lines(text, None), &mut lines);
+
+    Expanded {
+        lines,
+        sources: expander.sources,
+        errors: expander.errors,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_text(text: &str) -> Expanded {
+        expand(text, &HashMap::new())
+    }
+
+    fn texts(expanded: &Expanded) -> Vec<&str> {
+        expanded.lines.iter().map(|l| l.text.trim()).collect()
+    }
+
+    #[test]
+    fn test_plain_lines_pass_through() {
+        let expanded = expand_text("+0| 4cd\n# comment\n+1| 4cu");
+        assert!(expanded.errors.is_empty());
+        assert_eq!(texts(&expanded), vec!["+0| 4cd", "# comment", "+1| 4cu"]);
+        assert_eq!(expanded.lines[2].line, 3);
+    }
+
+    #[test]
+    fn test_section_define_and_play() {
+        let text = ":motif\n+1| 4cd\n+1| 4cu\n:end\n+0| 3cd\n*motif x2\n*motif";
+        let expanded = expand_text(text);
+        assert!(expanded.errors.is_empty(), "{:?}", expanded.errors);
+        assert_eq!(
+            texts(&expanded),
+            vec!["+0| 3cd", "+1| 4cd", "+1| 4cu", "+1| 4cd", "+1| 4cu", "+1| 4cd", "+1| 4cu"]
+        );
+        // Expanded lines keep their original line numbers
+        assert_eq!(expanded.lines[1].line, 2);
+    }
+
+    #[test]
+    fn test_nested_repeats() {
+        let text = "|:\n+1| 4cd\n|:\n+1| 4ed\n:| x3\n:|";
+        let expanded = expand_text(text);
+        assert!(expanded.errors.is_empty(), "{:?}", expanded.errors);
+
+        let count = |s: &str| texts(&expanded).iter().filter(|t| **t == s).count();
+        assert_eq!(count("+1| 4cd"), 2);
+        assert_eq!(count("+1| 4ed"), 6);
+    }
+
+    #[test]
+    fn test_section_with_repeat() {
+        let text = ":a\n|:\n+1| 4cd\n:| x2\n:end\n*a x2 # comment";
+        let expanded = expand_text(text);
+        assert!(expanded.errors.is_empty(), "{:?}", expanded.errors);
+        assert_eq!(expanded.lines.len(), 4);
+    }
+
+    #[test]
+    fn test_structure_errors() {
+        let cases = [
+            "*missing",
+            ":end",
+            ":|",
+            "|:\n+1| 4cd",
+            ":a\n+1| 4cd",
+            ":a\n:b\n:end",
+            ":a\n*a\n:end\n*a",
+            "|: x2",
+            "*a x0",
+            "@include",
+        ];
+        for text in cases {
+            assert!(!expand_text(text).errors.is_empty(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_expansion_limit() {
+        // A billion lines
+        let text = "+0| 4cd\n|:\n|:\n|:\n+1| 4cd\n:| x1000\n:| x1000\n:| x1000";
+        let expanded = expand_text(text);
+        assert_eq!(expanded.lines.len(), MAX_EXPANDED_LINES);
+        assert_eq!(expanded.errors.len(), 1);
+        assert_eq!(expanded.errors[0].span, Span::new(2, 1, 2));
+
+        // Repeating nothing finishes at once however large the count
+        let expanded = expand_text(":a\n:end\n*a x18446744073709551615\n|:\n:| x99999999999");
+        assert!(expanded.errors.is_empty(), "{:?}", expanded.errors);
+        assert!(expanded.lines.is_empty());
+    }
+
+    #[test]
+    fn test_error_location() {
+        let expanded = expand_text("+0| 4cd\n  *nope x2  # no such section");
+        assert_eq!(expanded.errors.len(), 1);
+        assert_eq!(expanded.errors[0].span, Span::new(2, 3, 8));
+    }
+
+    #[test]
+    fn test_include() {
+        let mut files = HashMap::new();
+        files.insert(
+            "parts/intro.txt".to_string(),
+            ":hook\n+1| 5cd\n:end\n+0| 4cd\n@include tail.txt".to_string(),
+        );
+        files.insert("parts/tail.txt".to_string(), "+1| 4cu".to_string());
+
+        let expanded = expand("@include \"parts/intro.txt\"\n*hook", &files);
+        assert!(expanded.errors.is_empty(), "{:?}", expanded.errors);
+        assert_eq!(texts(&expanded), vec!["+0| 4cd", "+1| 4cu", "+1| 5cd"]);
+        assert_eq!(expanded.lines[1].file.as_deref(), Some("parts/tail.txt"));
+        assert_eq!(expanded.sources.len(), 2);
+    }
+
+    #[test]
+    fn test_include_errors() {
+        let mut files = HashMap::new();
+        files.insert("loop.txt".to_string(), "@include loop.txt".to_string());
+        files.insert("bad.txt".to_string(), "+0| 4cd\n:|".to_string());
+
+        let expanded = expand("@include loop.txt", &files);
+        assert_eq!(expanded.errors.len(), 1);
+        assert_eq!(expanded.errors[0].file.as_deref(), Some("loop.txt"));
+
+        let expanded = expand("@include bad.txt", &files);
+        assert_eq!(expanded.errors.len(), 1);
+        assert_eq!(expanded.errors[0].span.line, 2);
+        assert_eq!(expanded.errors[0].file.as_deref(), Some("bad.txt"));
+
+        assert_eq!(expand("@include missing.txt", &files).errors.len(), 1);
+    }
+}
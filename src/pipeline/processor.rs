@@ -0,0 +1,556 @@
+//! Event processors between parsed events and the voice manager
+//!
+//! An event processor receives the key events of each timestep and emits
+//! the events that should actually be played. Processors are stateful and
+//! advance one timestep at a time, so the same processor can be driven by
+//! live input or applied to a parsed stream with [`apply_processor`].
+//!
+//! Provided processors:
+//! - ChordMemory: play a stored chord for every key
+//! - Arpeggiator: turn held notes into a repeating note pattern
+
+use std::collections::HashMap;
+
+use crate::pipeline::parser::{Control, Event, KeyDirection, Note, TimedEvents};
+use crate::pipeline::transform::SplitMix64;
+
+/// A stage that rewrites key events one timestep at a time
+pub trait EventProcessor {
+    /// Process the events arriving at the current timestep
+    ///
+    /// Called once per timestep (with an empty `input` when nothing
+    /// arrives) while [`is_active`](Self::is_active) is true.
+    fn process_timestep(&mut self, input: &[Event], output: &mut Vec<Event>);
+
+    /// Check if the processor needs timesteps even without input
+    fn is_active(&self) -> bool;
+
+    /// Release everything the processor is sounding
+    fn all_notes_off(&mut self, output: &mut Vec<Event>);
+}
+
+/// Run a parsed event stream through a processor
+///
+/// Timesteps without input are only visited while the processor is active.
+/// Pedal events pass through unchanged at their original timestep.
+/// Notes still held when the input ends are released one timestep after the
+/// last input line.
+pub fn apply_processor(
+    events: &[TimedEvents],
+    processor: &mut dyn EventProcessor,
+) -> Vec<TimedEvents> {
+    let mut result = Vec::new();
+    let mut output = Vec::new();
+    let mut input = Vec::new();
+    let mut controls = Vec::new();
+    let mut last_output_time = 0;
+    let mut index = 0;
+    let mut time = 0;
+    let mut next_input_time = events.first().map(|t| t.delta);
+
+    let mut emit = |time: usize,
+                    output: &mut Vec<Event>,
+                    controls: &mut Vec<Control>,
+                    result: &mut Vec<TimedEvents>| {
+        if !output.is_empty() || !controls.is_empty() {
+            result.push(TimedEvents {
+                delta: time - last_output_time,
+                events: std::mem::take(output),
+                controls: std::mem::take(controls),
+            });
+            last_output_time = time;
+        }
+    };
+
+    while let Some(input_time) = next_input_time {
+        if !processor.is_active() {
+            time = input_time;
+        }
+
+        // Gather every line at this timestep
+        input.clear();
+        while index < events.len() && next_input_time == Some(time) {
+            input.extend(events[index].events.iter().cloned());
+            controls.extend(events[index].controls.iter().copied());
+            index += 1;
+            next_input_time = events.get(index).map(|t| time + t.delta);
+        }
+
+        processor.process_timestep(&input, &mut output);
+        emit(time, &mut output, &mut controls, &mut result);
+        time += 1;
+    }
+
+    processor.all_notes_off(&mut output);
+    emit(time, &mut output, &mut controls, &mut result);
+
+    result
+}
+
+/// Plays a stored chord for every key
+///
+/// Chords are semitone intervals relative to the played key. A default
+/// chord applies to every key unless a key has its own chord.
+#[derive(Debug, Clone)]
+pub struct ChordMemory {
+    default_chord: Vec<i32>,
+    chords: HashMap<Note, Vec<i32>>,
+    /// Notes sounded for each held key, so key-up releases exactly those
+    sounding: Vec<(Note, Vec<Note>)>,
+}
+
+impl ChordMemory {
+    /// Create a chord memory playing `intervals` for every key
+    ///
+    /// # Example
+    /// ```
+    /// use corroza::pipeline::processor::ChordMemory;
+    ///
+    /// let major_triad = ChordMemory::new(vec![0, 4, 7]);
+    /// ```
+    pub fn new(intervals: Vec<i32>) -> Self {
+        Self {
+            default_chord: intervals,
+            chords: HashMap::new(),
+            sounding: Vec::new(),
+        }
+    }
+
+    /// Store a chord for one specific key
+    pub fn with_chord(mut self, key: Note, intervals: Vec<i32>) -> Self {
+        self.chords.insert(key, intervals);
+        self
+    }
+
+    /// Notes played for a key (notes outside the supported range are dropped)
+    fn chord_notes(&self, key: &Note) -> Vec<Note> {
+        let intervals = self.chords.get(key).unwrap_or(&self.default_chord);
+        let mut notes: Vec<Note> = Vec::with_capacity(intervals.len());
+        for note in intervals.iter().filter_map(|&i| key.transposed(i)) {
+            if !notes.contains(&note) {
+                notes.push(note);
+            }
+        }
+        notes
+    }
+}
+
+impl EventProcessor for ChordMemory {
+    fn process_timestep(&mut self, input: &[Event], output: &mut Vec<Event>) {
+        for event in input {
+            match event.direction {
+                KeyDirection::Down => {
+                    if self.sounding.iter().any(|(key, _)| *key == event.note) {
+                        continue;
+                    }
+                    let notes = self.chord_notes(&event.note);
+                    output.extend(notes.iter().map(|&note| Event {
+                        note,
+                        ..event.clone()
+                    }));
+                    self.sounding.push((event.note, notes));
+                }
+                KeyDirection::Up => {
+                    if let Some(i) = self.sounding.iter().position(|(k, _)| *k == event.note) {
+                        let (_, notes) = self.sounding.remove(i);
+                        output.extend(
+                            notes
+                                .into_iter()
+                                .map(|note| Event::new(note, KeyDirection::Up)),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        false
+    }
+
+    fn all_notes_off(&mut self, output: &mut Vec<Event>) {
+        for (_, notes) in self.sounding.drain(..) {
+            output.extend(
+                notes
+                    .into_iter()
+                    .map(|note| Event::new(note, KeyDirection::Up)),
+            );
+        }
+    }
+}
+
+/// Order in which the arpeggiator plays held notes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArpPattern {
+    /// Lowest to highest
+    Up,
+    /// Highest to lowest
+    Down,
+    /// Lowest to highest and back, without repeating the ends
+    UpDown,
+    /// Random held note each step (seeded)
+    Random,
+    /// In the order the keys were pressed
+    AsPlayed,
+}
+
+impl std::str::FromStr for ArpPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "up" => Ok(ArpPattern::Up),
+            "down" => Ok(ArpPattern::Down),
+            "updown" | "up-down" => Ok(ArpPattern::UpDown),
+            "random" => Ok(ArpPattern::Random),
+            "played" | "as-played" => Ok(ArpPattern::AsPlayed),
+            _ => Err(format!("unknown arpeggio pattern: {}", s)),
+        }
+    }
+}
+
+/// Arpeggiator settings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArpeggiatorConfig {
+    pub pattern: ArpPattern,
+    /// Number of octaves the pattern spans (1 = held notes only)
+    pub octaves: usize,
+    /// Timesteps between arpeggio notes
+    pub rate: usize,
+    /// Seed for the random pattern
+    pub seed: u64,
+}
+
+impl Default for ArpeggiatorConfig {
+    fn default() -> Self {
+        Self {
+            pattern: ArpPattern::Up,
+            octaves: 1,
+            rate: 1,
+            seed: 0,
+        }
+    }
+}
+
+/// Turns held notes into a repeating pattern of single notes
+///
+/// The first step plays as soon as a key is pressed from idle. Each arpeggio
+/// note lasts until the next step; releasing every key stops the pattern.
+pub struct Arpeggiator {
+    config: ArpeggiatorConfig,
+    /// Held keys with their velocities, in the order they were pressed
+    held: Vec<(Note, f32)>,
+    /// Note currently sounding
+    current: Option<Note>,
+    /// Index of the next step in the pattern
+    position: usize,
+    /// Timesteps until the next step
+    countdown: usize,
+    rng: SplitMix64,
+}
+
+impl Arpeggiator {
+    /// Create a new arpeggiator
+    ///
+    /// # Panics
+    /// Panics if `rate` or `octaves` is zero
+    pub fn new(config: ArpeggiatorConfig) -> Self {
+        assert!(config.rate > 0, "rate must be at least one timestep");
+        assert!(config.octaves > 0, "octaves must be at least 1");
+        Self {
+            config,
+            held: Vec::new(),
+            current: None,
+            position: 0,
+            countdown: 0,
+            rng: SplitMix64(config.seed),
+        }
+    }
+
+    /// Notes the pattern cycles through, with velocities
+    fn sequence(&self) -> Vec<(Note, f32)> {
+        let mut base = self.held.clone();
+        match self.config.pattern {
+            ArpPattern::AsPlayed | ArpPattern::Random => {}
+            _ => base.sort_by_key(|(note, _)| note.semitone_index()),
+        }
+
+        let mut notes: Vec<(Note, f32)> = (0..self.config.octaves)
+            .flat_map(|octave| {
+                base.iter().filter_map(move |&(note, velocity)| {
+                    note.transposed(12 * octave as i32).map(|n| (n, velocity))
+                })
+            })
+            .collect();
+
+        match self.config.pattern {
+            ArpPattern::Down => notes.reverse(),
+            ArpPattern::UpDown if notes.len() > 2 => {
+                let descending: Vec<_> = notes[1..notes.len() - 1].iter().rev().copied().collect();
+                notes.extend(descending);
+            }
+            _ => {}
+        }
+        notes
+    }
+
+    /// Play the next step of the pattern
+    fn step(&mut self, output: &mut Vec<Event>) {
+        if let Some(note) = self.current.take() {
+            output.push(Event::new(note, KeyDirection::Up));
+        }
+
+        let sequence = self.sequence();
+        if sequence.is_empty() {
+            return;
+        }
+        let index = match self.config.pattern {
+            ArpPattern::Random => (self.rng.next_u64() % sequence.len() as u64) as usize,
+            _ => self.position % sequence.len(),
+        };
+        self.position = index + 1;
+
+        let (note, velocity) = sequence[index];
+        output.push(Event {
+            note,
+            direction: KeyDirection::Down,
+            velocity,
+        });
+        self.current = Some(note);
+    }
+}
+
+impl EventProcessor for Arpeggiator {
+    fn process_timestep(&mut self, input: &[Event], output: &mut Vec<Event>) {
+        for event in input {
+            match event.direction {
+                KeyDirection::Down => {
+                    if !self.held.iter().any(|(n, _)| *n == event.note) {
+                        self.held.push((event.note, event.velocity));
+                    }
+                }
+                KeyDirection::Up => self.held.retain(|(n, _)| *n != event.note),
+            }
+        }
+
+        if self.held.is_empty() {
+            // Stop and restart from the beginning on the next key
+            self.all_notes_off(output);
+            return;
+        }
+
+        if self.countdown == 0 {
+            self.step(output);
+            self.countdown = self.config.rate;
+        }
+        self.countdown -= 1;
+    }
+
+    fn is_active(&self) -> bool {
+        !self.held.is_empty() || self.current.is_some()
+    }
+
+    fn all_notes_off(&mut self, output: &mut Vec<Event>) {
+        if let Some(note) = self.current.take() {
+            output.push(Event::new(note, KeyDirection::Up));
+        }
+        self.held.clear();
+        self.position = 0;
+        self.countdown = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::parser::{parse_transcription, Pedal};
+
+    fn note(s: &str) -> Note {
+        parse_transcription(&format!("+0| {}d", s)).unwrap()[0].events[0].note
+    }
+
+    /// Flatten output into (absolute time, "4cd"-style event) pairs
+    fn flatten(events: &[TimedEvents]) -> Vec<(usize, String)> {
+        let mut time = 0;
+        let mut result = Vec::new();
+        for timed in events {
+            time += timed.delta;
+            for event in &timed.events {
+                let dir = if event.direction == KeyDirection::Down {
+                    "d"
+                } else {
+                    "u"
+                };
+                result.push((time, format!("{}{}", event.note, dir)));
+            }
+        }
+        result
+    }
+
+    fn downs(events: &[TimedEvents]) -> Vec<String> {
+        flatten(events)
+            .into_iter()
+            .filter(|(_, e)| e.ends_with('d'))
+            .map(|(_, e)| e.trim_end_matches('d').to_string())
+            .collect()
+    }
+
+    fn arp(pattern: ArpPattern, octaves: usize, rate: usize) -> Arpeggiator {
+        Arpeggiator::new(ArpeggiatorConfig {
+            pattern,
+            octaves,
+            rate,
+            seed: 1,
+        })
+    }
+
+    #[test]
+    fn test_chord_memory() {
+        let events = parse_transcription("+0| 4cd\n+2| 4cu\n+1| 4ad\n+1| 4au").unwrap();
+        let mut chords = ChordMemory::new(vec![0, 4, 7]).with_chord(note("4a"), vec![0, 3]);
+        let result = apply_processor(&events, &mut chords);
+
+        assert_eq!(
+            flatten(&result),
+            vec![
+                (0, "4cd".to_string()),
+                (0, "4ed".to_string()),
+                (0, "4gd".to_string()),
+                (2, "4cu".to_string()),
+                (2, "4eu".to_string()),
+                (2, "4gu".to_string()),
+                (3, "4ad".to_string()),
+                (3, "5cd".to_string()),
+                (4, "4au".to_string()),
+                (4, "5cu".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chord_memory_drops_out_of_range() {
+        let events = parse_transcription("+0| 9bd\n+1| 9bu").unwrap();
+        let result = apply_processor(&events, &mut ChordMemory::new(vec![0, 4, 7]));
+        assert_eq!(downs(&result), vec!["9b"]);
+    }
+
+    #[test]
+    fn test_arp_up() {
+        // Chord held for 6 timesteps, pressed out of order
+        let events = parse_transcription("+0| 4gd, 4cd, 4ed\n+6| 4gu, 4cu, 4eu").unwrap();
+        let result = apply_processor(&events, &mut arp(ArpPattern::Up, 1, 1));
+
+        assert_eq!(downs(&result), vec!["4c", "4e", "4g", "4c", "4e", "4g"]);
+
+        // Each note is released when the next one starts, the last on key-up
+        let flat = flatten(&result);
+        assert_eq!(flat[1], (1, "4cu".to_string()));
+        assert_eq!(flat.last().unwrap(), &(6, "4gu".to_string()));
+    }
+
+    #[test]
+    fn test_arp_patterns() {
+        let events = parse_transcription("+0| 4ed, 4cd, 4gd\n+8| 4eu, 4cu, 4gu").unwrap();
+        let run = |pattern| downs(&apply_processor(&events, &mut arp(pattern, 1, 1)));
+
+        assert_eq!(
+            run(ArpPattern::Down),
+            vec!["4g", "4e", "4c", "4g", "4e", "4c", "4g", "4e"]
+        );
+        assert_eq!(
+            run(ArpPattern::UpDown),
+            vec!["4c", "4e", "4g", "4e", "4c", "4e", "4g", "4e"]
+        );
+        assert_eq!(
+            run(ArpPattern::AsPlayed),
+            vec!["4e", "4c", "4g", "4e", "4c", "4g", "4e", "4c"]
+        );
+
+        let random = run(ArpPattern::Random);
+        assert_eq!(random.len(), 8);
+        assert!(random
+            .iter()
+            .all(|n| ["4c", "4e", "4g"].contains(&n.as_str())));
+        assert_eq!(random, run(ArpPattern::Random)); // seeded
+    }
+
+    #[test]
+    fn test_arp_octaves_and_rate() {
+        let events = parse_transcription("+0| 4cd, 4gd\n+8| 4cu, 4gu").unwrap();
+        let result = apply_processor(&events, &mut arp(ArpPattern::Up, 2, 2));
+
+        assert_eq!(downs(&result), vec!["4c", "4g", "5c", "5g"]);
+        let times: Vec<usize> = flatten(&result)
+            .into_iter()
+            .filter(|(_, e)| e.ends_with('d'))
+            .map(|(t, _)| t)
+            .collect();
+        assert_eq!(times, vec![0, 2, 4, 6]);
+    }
+
+    #[test]
+    fn test_arp_follows_held_notes() {
+        // E joins at t=2, C leaves at t=4
+        let events = parse_transcription("+0| 4cd\n+2| 4ed\n+2| 4cu\n+2| 4eu").unwrap();
+        let result = apply_processor(&events, &mut arp(ArpPattern::Up, 1, 1));
+        assert_eq!(downs(&result), vec!["4c", "4c", "4e", "4c", "4e", "4e"]);
+    }
+
+    #[test]
+    fn test_arp_restarts_after_silence() {
+        let events =
+            parse_transcription("+0| 4cd, 4ed\n+3| 4cu, 4eu\n+100| 4cd, 4ed\n+1| 4cu, 4eu")
+                .unwrap();
+        let result = apply_processor(&events, &mut arp(ArpPattern::Up, 1, 1));
+        let flat = flatten(&result);
+
+        assert_eq!(downs(&result), vec!["4c", "4e", "4c", "4c"]);
+        assert_eq!(flat[flat.len() - 2], (103, "4cd".to_string()));
+    }
+
+    #[test]
+    fn test_arp_releases_stuck_notes() {
+        let events = parse_transcription("+0| 4cd\n+2| 4ed").unwrap();
+        let result = apply_processor(&events, &mut arp(ArpPattern::Up, 1, 1));
+        let flat = flatten(&result);
+        assert_eq!(flat.last().unwrap(), &(3, "4eu".to_string()));
+    }
+
+    #[test]
+    fn test_arp_keeps_velocity() {
+        let mut events = parse_transcription("+0| 4cd\n+2| 4cu").unwrap();
+        events[0].events[0].velocity = 0.5;
+        let result = apply_processor(&events, &mut arp(ArpPattern::Up, 1, 1));
+        assert_eq!(result[0].events[0].velocity, 0.5);
+    }
+
+    #[test]
+    fn test_chord_then_arp() {
+        let events = parse_transcription("+0| 4cd\n+3| 4cu").unwrap();
+        let chords = apply_processor(&events, &mut ChordMemory::new(vec![0, 4, 7]));
+        let result = apply_processor(&chords, &mut arp(ArpPattern::Up, 1, 1));
+        assert_eq!(downs(&result), vec!["4c", "4e", "4g"]);
+    }
+
+    #[test]
+    fn test_pedals_pass_through() {
+        let events = parse_transcription("+0| 4cd, ped+\n+3| 4cu\n+5| ped-").unwrap();
+        let result = apply_processor(&events, &mut arp(ArpPattern::Up, 1, 1));
+
+        let pedals: Vec<(usize, Control)> = result
+            .iter()
+            .scan(0, |time, timed| {
+                *time += timed.delta;
+                Some((*time, timed.controls.clone()))
+            })
+            .flat_map(|(time, controls)| controls.into_iter().map(move |c| (time, c)))
+            .collect();
+        assert_eq!(
+            pedals,
+            vec![
+                (0, Control::Pedal(Pedal::Sustain, KeyDirection::Down)),
+                (8, Control::Pedal(Pedal::Sustain, KeyDirection::Up)),
+            ]
+        );
+    }
+}
@@ -1,25 +1,61 @@
 //! Scheduler and Pipeline orchestrator
 //!
 //! Coordinates event scheduling, frame-based processing, and audio generation.
-//! The pipeline processes events at frame boundaries and generates audio samples.
+//! By default the pipeline processes events at frame boundaries, so an event
+//! due mid-frame is delayed to the next frame. In sample-accurate mode frames
+//! are split at event offsets instead, making timing independent of the
+//! frame size.
+//!
+//! Multiple tracks are merged into a single time-ordered event stream. Each
+//! track is rendered by its own voice manager and mixed with its own gain.
+//!
+//! The written output can be normalized to a loudness or peak level (see
+//! [`Pipeline::with_normalization`]), so songs come out at the same level
+//! however many voices they play.
+
+use std::collections::HashMap;
 
-use crate::pipeline::parser::TimedEvents;
-use crate::pipeline::voicemgr::{VoiceConfig, VoiceManager};
-use crate::wav::write_wav_16bit;
+use crate::analysis::{Loudness, LoudnessMeter, NormalizeTarget};
+use crate::pipeline::parser::{
+    Control, Event, KeyDirection, Note, TimedEvents, Track, DEFAULT_TRACK_NAME,
+};
+use crate::pipeline::tuning::Tuning;
+use crate::pipeline::voicemgr::{VoiceConfig, VoiceManager, VoiceStats};
+use crate::resample::{ResampleQuality, Resampler};
+use crate::wav::{CuePoint, WavMetadata, WavSpec, WavWriter};
+
+/// Standard concert pitch for 4a (A4) in Hz
+pub const CONCERT_PITCH: f32 = 440.0;
 
 /// Configuration for the audio pipeline
+///
+/// By default 1C sounds at `base_frequency` (110 Hz, so 4a is about
+/// 1480 Hz). Use [`PipelineConfig::with_reference_pitch`] to tune by a
+/// reference note instead, such as 4a at [`CONCERT_PITCH`].
 #[derive(Debug, Clone)]
 pub struct PipelineConfig {
     /// Sample rate in Hz
     pub sample_rate: u32,
     /// Number of samples per frame
     pub frame_size: usize,
+    /// Fire events at their exact sample instead of the next frame boundary
+    pub sample_accurate: bool,
     /// Number of samples per timestep
     pub timestep_samples: usize,
     /// Voice configuration (FM params, ADSR)
     pub voice_config: VoiceConfig,
     /// Base frequency for 1C (Hz)
     pub base_frequency: f32,
+    /// Tuning for all tracks; 12-TET from `base_frequency` when `None`
+    pub tuning: Option<Tuning>,
+    /// Named instruments available to tracks
+    ///
+    /// A track without an explicit instrument uses the one named after the
+    /// track, or `voice_config` if there is none.
+    pub instruments: HashMap<String, VoiceConfig>,
+    /// Threads rendering each track's voices (see
+    /// [`VoiceManager::with_render_threads`]); 1 renders on the calling thread
+    pub render_threads: usize,
 }
 
 impl Default for PipelineConfig {
@@ -27,18 +63,144 @@ impl Default for PipelineConfig {
         Self {
             sample_rate: 44100,
             frame_size: 64,
+            sample_accurate: false,
             timestep_samples: 1000, // ≈22.7ms at 44.1kHz
             voice_config: VoiceConfig::default(),
             base_frequency: 110.0, // 1C = 110 Hz
+            tuning: None,
+            instruments: HashMap::new(),
+            render_threads: 1,
+        }
+    }
+}
+
+impl PipelineConfig {
+    /// Look up the voice configuration for an instrument name
+    pub fn instrument(&self, name: &str) -> Result<&VoiceConfig, PipelineError> {
+        self.instruments
+            .get(name)
+            .ok_or_else(|| PipelineError::UnknownInstrument(name.to_string()))
+    }
+
+    /// Voice configuration a track is played with
+    ///
+    /// An explicit instrument must be listed in `instruments`; otherwise the
+    /// instrument named after the track is used if there is one, and
+    /// `voice_config` if not.
+    pub fn track_instrument(&self, track: &Track) -> Result<&VoiceConfig, PipelineError> {
+        match &track.instrument {
+            Some(name) => self.instrument(name),
+            None => Ok(self
+                .instruments
+                .get(&track.name)
+                .unwrap_or(&self.voice_config)),
+        }
+    }
+
+    /// Tune so that `note` sounds at `frequency` Hz in 12-TET
+    ///
+    /// Sets `base_frequency` (the frequency of 1C) accordingly, which also
+    /// places a tuning's default keyboard mapping.
+    ///
+    /// # Arguments
+    /// * `note` - Reference note (e.g. [`CONCERT_A`](crate::pipeline::parser::CONCERT_A))
+    /// * `frequency` - Frequency of the reference note in Hz
+    ///
+    /// # Panics
+    /// Panics if `frequency` is not positive
+    pub fn with_reference_pitch(mut self, note: Note, frequency: f32) -> Self {
+        assert!(
+            frequency > 0.0,
+            "Reference frequency must be positive: {}",
+            frequency
+        );
+        let semitones_above_c1 = (note.semitone_index() - 12) as f32;
+        self.base_frequency = frequency * 2f32.powf(-semitones_above_c1 / 12.0);
+        self
+    }
+}
+
+/// Errors from setting up a pipeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PipelineError {
+    /// A track names an instrument that isn't configured
+    UnknownInstrument(String),
+}
+
+impl std::fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PipelineError::UnknownInstrument(name) => write!(f, "Unknown instrument: {}", name),
         }
     }
 }
 
+impl std::error::Error for PipelineError {}
+
+/// Events from all tracks occurring at the same timestep
+#[derive(Debug, Clone, PartialEq)]
+struct MergedEvents {
+    /// Timesteps since the previous entry (absolute timestep for the first)
+    delta: usize,
+    /// Events tagged with the index of the track they belong to
+    events: Vec<(usize, Event)>,
+    /// Pedal events tagged with the index of the track they belong to
+    controls: Vec<(usize, Control)>,
+}
+
+/// Merge per-track event lists into a single time-ordered stream
+///
+/// Events at the same timestep keep track order, then line order.
+fn merge_tracks(tracks: &[Track]) -> Vec<MergedEvents> {
+    let mut absolute: Vec<(usize, usize, &TimedEvents)> = Vec::new();
+    for (track_index, track) in tracks.iter().enumerate() {
+        let mut timestep = 0;
+        for timed in &track.events {
+            timestep += timed.delta;
+            if !timed.events.is_empty() || !timed.controls.is_empty() {
+                absolute.push((timestep, track_index, timed));
+            }
+        }
+    }
+    // Stable sort preserves line order within a track
+    absolute.sort_by_key(|&(timestep, track_index, _)| (timestep, track_index));
+
+    let mut merged: Vec<MergedEvents> = Vec::new();
+    let mut last_timestep = 0;
+    for (timestep, track_index, timed) in absolute {
+        if merged.is_empty() || timestep != last_timestep {
+            merged.push(MergedEvents {
+                delta: timestep - last_timestep,
+                events: Vec::new(),
+                controls: Vec::new(),
+            });
+            last_timestep = timestep;
+        }
+        let last = merged.last_mut().expect("just pushed");
+        let tagged_events = timed.events.iter().map(|e| (track_index, e.clone()));
+        last.events.extend(tagged_events);
+        let tagged_controls = timed.controls.iter().map(|c| (track_index, *c));
+        last.controls.extend(tagged_controls);
+    }
+    merged
+}
+
+/// A track being rendered by the pipeline
+struct TrackVoices {
+    name: String,
+    voice_config: VoiceConfig,
+    voice_manager: VoiceManager,
+    gain: f32,
+    release_samples: usize,
+}
+
 /// Pipeline for processing musical events and generating audio
 pub struct Pipeline {
     config: PipelineConfig,
-    voice_manager: VoiceManager,
-    events: Vec<TimedEvents>,
+    tracks: Vec<TrackVoices>,
+    events: Vec<MergedEvents>,
+    /// Scratch buffer for rendering a single track
+    track_buffer: Vec<f32>,
     /// Current sample position
     current_sample: usize,
     /// Current event index
@@ -47,6 +209,12 @@ pub struct Pipeline {
     samples_to_next_event: usize,
     /// Whether there are more events to process
     has_more_events: bool,
+    /// A marker for each note-on so far, when enabled
+    note_markers: Option<Vec<CuePoint>>,
+    /// Level to bring the written output to
+    normalization: Option<NormalizeTarget>,
+    /// Levels of the last normalized output, before the gain
+    measured: Option<Loudness>,
 }
 
 impl Pipeline {
@@ -56,43 +224,207 @@ impl Pipeline {
     /// * `config` - Pipeline configuration
     /// * `events` - Parsed events in chronological order
     pub fn new(config: PipelineConfig, events: Vec<TimedEvents>) -> Self {
-        let voice_manager = VoiceManager::new(
-            config.voice_config.clone(),
-            config.base_frequency,
-            config.sample_rate,
-        );
+        let mut track = Track::new(DEFAULT_TRACK_NAME);
+        track.events = events;
+        let voice_config = config.voice_config.clone();
+        Self::with_voice_configs(config, vec![track], vec![voice_config])
+    }
 
-        let has_more_events = !events.is_empty();
-        let samples_to_next_event = if has_more_events {
-            events[0].delta * config.timestep_samples
-        } else {
-            0
-        };
+    /// Create a new pipeline rendering several tracks
+    ///
+    /// Each track is played with the instrument named by the track (see
+    /// [`PipelineConfig::instrument`]) and mixed with the track's gain.
+    ///
+    /// # Arguments
+    /// * `config` - Pipeline configuration
+    /// * `tracks` - Parsed tracks, each with its own timeline
+    ///
+    /// # Errors
+    /// Returns [`PipelineError::UnknownInstrument`] if a track names an
+    /// instrument that `config` doesn't have.
+    pub fn with_tracks(config: PipelineConfig, tracks: Vec<Track>) -> Result<Self, PipelineError> {
+        let voice_configs = tracks
+            .iter()
+            .map(|track| config.track_instrument(track).cloned())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::with_voice_configs(config, tracks, voice_configs))
+    }
 
-        Self {
+    /// Create a pipeline playing each track with the matching voice config
+    fn with_voice_configs(
+        config: PipelineConfig,
+        tracks: Vec<Track>,
+        voice_configs: Vec<VoiceConfig>,
+    ) -> Self {
+        let events = merge_tracks(&tracks);
+        let tracks = tracks
+            .iter()
+            .zip(voice_configs)
+            .map(|(track, voice_config)| TrackVoices {
+                name: track.name.clone(),
+                release_samples: voice_config.release_samples,
+                voice_manager: Self::voice_manager(&config, &voice_config),
+                voice_config,
+                gain: track.gain,
+            })
+            .collect();
+
+        let mut pipeline = Self {
+            track_buffer: vec![0.0; config.frame_size],
             config,
-            voice_manager,
+            tracks,
             events,
             current_sample: 0,
             event_index: 0,
-            samples_to_next_event,
-            has_more_events,
+            samples_to_next_event: 0,
+            has_more_events: false,
+            note_markers: None,
+            normalization: None,
+            measured: None,
+        };
+        pipeline.rewind();
+        pipeline
+    }
+
+    /// Create a voice manager for one track
+    fn voice_manager(config: &PipelineConfig, voice_config: &VoiceConfig) -> VoiceManager {
+        let voice_manager = VoiceManager::new(
+            voice_config.clone(),
+            config.base_frequency,
+            config.sample_rate,
+        )
+        .with_render_threads(config.render_threads);
+        match &config.tuning {
+            Some(tuning) => voice_manager.with_tuning(tuning.clone()),
+            None => voice_manager,
+        }
+    }
+
+    /// Go back to the start of the song with fresh voices
+    fn rewind(&mut self) {
+        for track in self.tracks.iter_mut() {
+            track.voice_manager = Self::voice_manager(&self.config, &track.voice_config);
+        }
+        self.current_sample = 0;
+        self.event_index = 0;
+        self.has_more_events = !self.events.is_empty();
+        self.samples_to_next_event = match self.events.first() {
+            Some(merged) => merged.delta * self.config.timestep_samples,
+            None => 0,
+        };
+        if let Some(markers) = self.note_markers.as_mut() {
+            markers.clear();
         }
     }
 
+    /// Record a marker at every note-on
+    ///
+    /// The markers are written as cue points by
+    /// [`Pipeline::generate_wav_with_metadata`], labelled with the note
+    /// (e.g. "4c#"), prefixed by the track name when there are several
+    /// tracks.
+    pub fn with_note_markers(mut self) -> Self {
+        self.note_markers = Some(Vec::new());
+        self
+    }
+
+    /// Normalize the output written by [`Pipeline::generate_wav_with_metadata`]
+    ///
+    /// The song is rendered twice: once to measure it, then again to write
+    /// it with the gain applied, so it is never held in memory.
+    pub fn with_normalization(mut self, target: NormalizeTarget) -> Self {
+        self.normalization = Some(target);
+        self
+    }
+
+    /// Levels of the last normalized output, measured before the gain was
+    /// applied
+    ///
+    /// `None` until a WAV file has been written with normalization.
+    pub fn measured_loudness(&self) -> Option<&Loudness> {
+        self.measured.as_ref()
+    }
+
+    /// Frequency a note plays at, or `None` if the tuning leaves it unmapped
+    pub fn note_frequency(&self, note: &Note) -> Option<f32> {
+        self.tracks.first()?.voice_manager.note_frequency(note)
+    }
+
     /// Check if there are more events or active voices
     pub fn is_active(&self) -> bool {
-        self.has_more_events || self.voice_manager.has_active_voices()
+        self.has_more_events || self.has_active_voices()
+    }
+
+    /// Check if any track has active voices
+    fn has_active_voices(&self) -> bool {
+        self.tracks
+            .iter()
+            .any(|t| t.voice_manager.has_active_voices())
+    }
+
+    /// Get the number of active voices across all tracks
+    pub fn voice_count(&self) -> usize {
+        self.tracks
+            .iter()
+            .map(|t| t.voice_manager.voice_count())
+            .sum()
+    }
+
+    /// Get voice diagnostics summed over all tracks
+    ///
+    /// `peak_voices` is the sum of per-track peaks, an upper bound on the
+    /// overall peak.
+    pub fn voice_stats(&self) -> VoiceStats {
+        self.tracks.iter().fold(VoiceStats::default(), |acc, t| {
+            acc.merge(&t.voice_manager.stats())
+        })
+    }
+
+    /// Get the number of tracks
+    pub fn track_count(&self) -> usize {
+        self.tracks.len()
     }
 
     /// Process pending events at the current frame boundary
     fn process_events(&mut self) {
         while self.has_more_events && self.samples_to_next_event == 0 {
             // Process all events at this timestep
-            let timed = &self.events[self.event_index];
-            for event in &timed.events {
-                self.voice_manager
-                    .handle_event(&event.note, event.direction);
+            // Pedal lifts, then key events, then pedal presses
+            let merged = &self.events[self.event_index];
+            let early = merged
+                .controls
+                .iter()
+                .filter(|(_, c)| !c.applies_after_keys());
+            for (track_index, control) in early {
+                self.tracks[*track_index]
+                    .voice_manager
+                    .handle_control(control);
+            }
+            for (track_index, event) in &merged.events {
+                if let Some(markers) = self.note_markers.as_mut() {
+                    // Cue points hold 32-bit positions
+                    let position = u32::try_from(self.current_sample);
+                    if let (KeyDirection::Down, Ok(position)) = (event.direction, position) {
+                        let label = if self.tracks.len() > 1 {
+                            format!("{}: {}", self.tracks[*track_index].name, event.note)
+                        } else {
+                            event.note.to_string()
+                        };
+                        markers.push(CuePoint::new(position, &label));
+                    }
+                }
+                self.tracks[*track_index]
+                    .voice_manager
+                    .handle_event_with_velocity(&event.note, event.direction, event.velocity);
+            }
+            let late = merged
+                .controls
+                .iter()
+                .filter(|(_, c)| c.applies_after_keys());
+            for (track_index, control) in late {
+                self.tracks[*track_index]
+                    .voice_manager
+                    .handle_control(control);
             }
 
             // Move to next event
@@ -119,36 +451,195 @@ impl Pipeline {
 
     /// Process one frame of audio
     ///
-    /// Returns the samples for this frame.
+    /// Returns the samples for this frame. In sample-accurate mode the frame
+    /// is rendered in pieces split at event offsets.
     pub fn process_frame(&mut self, buffer: &mut [f32]) {
-        // Process events at frame boundary (start of frame)
-        self.process_events();
+        if !self.config.sample_accurate {
+            // Process events at frame boundary (start of frame)
+            self.process_events();
+
+            // Generate audio
+            self.mix_tracks(buffer);
+
+            // Advance time
+            self.advance_time(buffer.len());
+            return;
+        }
+
+        let mut start = 0;
+        while start < buffer.len() {
+            // Leaves samples_to_next_event > 0 while events remain
+            self.process_events();
+
+            let mut length = buffer.len() - start;
+            if self.has_more_events {
+                length = length.min(self.samples_to_next_event);
+            }
+            self.mix_tracks(&mut buffer[start..start + length]);
+            self.advance_time(length);
+            start += length;
+        }
+    }
 
-        // Generate audio
-        self.voice_manager.process_frame(buffer);
+    /// Render every track and mix them into the buffer using track gains
+    fn mix_tracks(&mut self, buffer: &mut [f32]) {
+        if self.tracks.len() == 1 && self.tracks[0].gain == 1.0 {
+            self.tracks[0].voice_manager.process_frame(buffer);
+            return;
+        }
+
+        for sample in buffer.iter_mut() {
+            *sample = 0.0;
+        }
+
+        if self.track_buffer.len() < buffer.len() {
+            self.track_buffer.resize(buffer.len(), 0.0);
+        }
+        let track_buffer = &mut self.track_buffer[..buffer.len()];
 
-        // Advance time
-        self.advance_time(buffer.len());
+        for track in self.tracks.iter_mut() {
+            track.voice_manager.process_frame(track_buffer);
+            for (sample, &track_sample) in buffer.iter_mut().zip(track_buffer.iter()) {
+                *sample += track_sample * track.gain;
+            }
+        }
     }
 
     /// Generate complete audio and write to WAV file
     ///
+    /// Writes mono 16-bit PCM at the configured sample rate.
+    ///
     /// # Arguments
     /// * `output_path` - Path for output WAV file
     pub fn generate_wav(&mut self, output_path: &str) -> std::io::Result<()> {
-        let mut samples = Vec::new();
+        let spec = WavSpec::pcm(self.config.sample_rate, 1, 16);
+        self.generate_wav_with(output_path, &spec)
+    }
+
+    /// Generate complete audio and write it in a chosen WAV format
+    ///
+    /// Frames are streamed to the file as they are rendered, so memory use
+    /// does not grow with the length of the song. The mono mix is written to
+    /// every channel of `spec`. When the spec's sample rate differs from the
+    /// pipeline's, the mix is converted with a sinc resampler, e.g. to render
+    /// at a high rate (less FM aliasing) and deliver at 44.1 kHz.
+    ///
+    /// # Arguments
+    /// * `output_path` - Path for output WAV file
+    /// * `spec` - Output format (bit depth, channels, dither)
+    pub fn generate_wav_with(&mut self, output_path: &str, spec: &WavSpec) -> std::io::Result<()> {
+        self.generate_wav_with_metadata(output_path, spec, &WavMetadata::default())
+    }
+
+    /// Generate complete audio and write it with tags, markers or sampler
+    /// data
+    ///
+    /// Like [`Pipeline::generate_wav_with`]; note-on markers (see
+    /// [`Pipeline::with_note_markers`]) are added after the cue points in
+    /// `metadata`. Positions in `metadata` are at the spec's sample rate.
+    ///
+    /// # Arguments
+    /// * `output_path` - Path for output WAV file
+    /// * `spec` - Output format (bit depth, channels, dither)
+    /// * `metadata` - Metadata to write after the audio
+    pub fn generate_wav_with_metadata(
+        &mut self,
+        output_path: &str,
+        spec: &WavSpec,
+        metadata: &WavMetadata,
+    ) -> std::io::Result<()> {
+        let mut writer = WavWriter::create(output_path, *spec)?;
+        writer.set_metadata(metadata.clone());
+        let render_rate = self.config.sample_rate;
+
+        // Measure the whole song first, then render it again at that gain
+        let gain = match self.normalization {
+            Some(target) => {
+                let mut meter = LoudnessMeter::new(spec.sample_rate, 1);
+                self.render_at(spec.sample_rate, |samples| {
+                    meter.process(samples);
+                    Ok::<_, std::io::Error>(())
+                })?;
+                let loudness = meter.finish();
+                self.measured = Some(loudness);
+                self.rewind();
+                Some(target.gain(&loudness) as f32)
+            }
+            None => None,
+        };
+
+        let mut scaled = Vec::new();
+        self.render_at(spec.sample_rate, |samples| match gain {
+            Some(gain) => {
+                scaled.clear();
+                scaled.extend(samples.iter().map(|sample| sample * gain));
+                writer.write_mono(&scaled)
+            }
+            None => writer.write_mono(samples),
+        })?;
+        if let Some(markers) = &self.note_markers {
+            let mut markers = WavMetadata {
+                cue_points: markers.clone(),
+                ..Default::default()
+            };
+            markers.convert_positions(render_rate, spec.sample_rate);
+            writer
+                .metadata_mut()
+                .cue_points
+                .append(&mut markers.cue_points);
+        }
+        writer.finalize()
+    }
+
+    /// Render until all events have played and all voices are done,
+    /// resampled to `sample_rate`
+    ///
+    /// The resampled output is passed to `sink`; rendering stops at the first
+    /// error it returns.
+    fn render_at<E>(
+        &mut self,
+        sample_rate: u32,
+        mut sink: impl FnMut(&[f32]) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let render_rate = self.config.sample_rate;
+        if sample_rate == render_rate {
+            return self.render_into(sink);
+        }
+        let mut resampler = Resampler::new(render_rate, sample_rate, ResampleQuality::Sinc);
+        let mut resampled = Vec::new();
+        self.render_into(|frame| {
+            resampled.clear();
+            resampler.process(frame, &mut resampled);
+            sink(&resampled)
+        })?;
+        resampled.clear();
+        resampler.finish(&mut resampled);
+        sink(&resampled)
+    }
+
+    /// Render until all events have played and all voices are done
+    ///
+    /// Each rendered frame is passed to `sink`; rendering stops at the first
+    /// error it returns.
+    fn render_into<E>(&mut self, mut sink: impl FnMut(&[f32]) -> Result<(), E>) -> Result<(), E> {
         let mut frame_buffer = vec![0.0f32; self.config.frame_size];
 
         // Process until all events and voices complete
+        let max_release = self
+            .tracks
+            .iter()
+            .map(|t| t.release_samples)
+            .max()
+            .unwrap_or(0);
         let max_samples = self.events.iter().map(|e| e.delta).sum::<usize>()
             * self.config.timestep_samples
-            + self.config.voice_config.release_samples * 2;
+            + max_release * 2;
         let mut safety_counter = 0;
         let max_iterations = max_samples / self.config.frame_size + 1000;
 
         while self.is_active() && safety_counter < max_iterations {
             self.process_frame(&mut frame_buffer);
-            samples.extend_from_slice(&frame_buffer);
+            sink(&frame_buffer)?;
             safety_counter += 1;
         }
 
@@ -156,26 +647,28 @@ impl Pipeline {
         // (VoiceManager should handle this, but let's be safe)
         let trailing_frames = 10;
         for _ in 0..trailing_frames {
-            if !self.voice_manager.has_active_voices() {
+            if !self.has_active_voices() {
                 break;
             }
             self.process_frame(&mut frame_buffer);
-            samples.extend_from_slice(&frame_buffer);
+            sink(&frame_buffer)?;
         }
 
-        write_wav_16bit(output_path, &samples, self.config.sample_rate)
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::pipeline::parser::{KeyDirection, Note, PitchClass};
+    use crate::pipeline::parser::{KeyDirection, Note, PitchClass, CONCERT_A};
+    use crate::resample::resample;
 
     fn create_simple_event(delta: usize, note: Note, direction: KeyDirection) -> TimedEvents {
         TimedEvents {
             delta,
-            events: vec![super::super::parser::Event { note, direction }],
+            events: vec![super::super::parser::Event::new(note, direction)],
+            controls: vec![],
         }
     }
 
@@ -186,6 +679,47 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_reference_pitch() {
+        // Legacy default: 1C at 110 Hz
+        assert_eq!(PipelineConfig::default().base_frequency, 110.0);
+
+        // A4 = 440 Hz puts 1C (C1) at about 32.70 Hz
+        let config = PipelineConfig::default().with_reference_pitch(CONCERT_A, CONCERT_PITCH);
+        assert!((config.base_frequency - 32.703).abs() < 0.001);
+
+        let config = PipelineConfig::default().with_reference_pitch(c4(), 256.0);
+        assert!((config.base_frequency - 32.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_legacy_1c_sounds_at_base_frequency() {
+        use crate::pipeline::parser::parse_transcription;
+
+        // The carrier once advanced 2π times too fast, putting 1C near 691 Hz
+        let mut config = PipelineConfig::default();
+        config.voice_config.fm_params.mod_depth = 0.0;
+        let events = parse_transcription("+0| 1cd\n+20| 1cu").unwrap();
+        let rendered = render(config, events);
+
+        let frequency = crate::pitch_check::measure_frequency(&rendered[4410..17640], 44100);
+        assert!((frequency - 110.0).abs() < 0.1, "{} Hz", frequency);
+    }
+
+    #[test]
+    fn test_concert_pitch_sounds_at_440() {
+        use crate::pipeline::parser::parse_transcription;
+
+        // Pure carrier (no modulation) so zero crossings give the pitch
+        let mut config = PipelineConfig::default().with_reference_pitch(CONCERT_A, CONCERT_PITCH);
+        config.voice_config.fm_params.mod_depth = 0.0;
+        let events = parse_transcription("+0| 4ad\n+20| 4au").unwrap();
+        let rendered = render(config, events);
+
+        let frequency = crate::pitch_check::measure_frequency(&rendered[4410..17640], 44100);
+        assert!((frequency - 440.0).abs() < 0.1, "{} Hz", frequency);
+    }
+
     #[test]
     fn test_pipeline_single_note() {
         let config = PipelineConfig {
@@ -212,7 +746,7 @@ mod tests {
         }
 
         // Should still have active voice
-        assert!(pipeline.voice_manager.voice_count() > 0);
+        assert!(pipeline.voice_count() > 0);
     }
 
     #[test]
@@ -240,4 +774,462 @@ mod tests {
         // Should have decremented
         assert_eq!(pipeline.samples_to_next_event, 68);
     }
+
+    fn track(name: &str, events: Vec<TimedEvents>) -> Track {
+        let mut track = Track::new(name);
+        track.events = events;
+        track
+    }
+
+    fn e4() -> Note {
+        Note {
+            octave: 4,
+            pitch_class: PitchClass::E,
+        }
+    }
+
+    /// Render `events` to completion, frame by frame
+    fn render(config: PipelineConfig, events: Vec<TimedEvents>) -> Vec<f32> {
+        let mut buffer = vec![0.0f32; config.frame_size];
+        let mut pipeline = Pipeline::new(config, events);
+        let mut samples = Vec::new();
+        while pipeline.is_active() {
+            pipeline.process_frame(&mut buffer);
+            samples.extend_from_slice(&buffer);
+        }
+        samples
+    }
+
+    fn staggered_events() -> Vec<TimedEvents> {
+        let e4 = c4().transposed(4).unwrap();
+        vec![
+            create_simple_event(1, c4(), KeyDirection::Down),
+            create_simple_event(1, e4, KeyDirection::Down),
+            create_simple_event(3, c4(), KeyDirection::Up),
+            create_simple_event(1, e4, KeyDirection::Up),
+        ]
+    }
+
+    #[test]
+    fn test_sample_accurate_event_offset() {
+        let first_sound = |sample_accurate: bool| {
+            let config = PipelineConfig {
+                timestep_samples: 100,
+                frame_size: 64,
+                sample_accurate,
+                ..Default::default()
+            };
+            let samples = render(config, staggered_events());
+            samples.iter().position(|&s| s != 0.0).unwrap()
+        };
+
+        // Frame boundaries delay the first note from sample 100 to 128
+        assert!(first_sound(false) >= 128);
+        let first = first_sound(true);
+        assert!((100..102).contains(&first), "first sound at {}", first);
+    }
+
+    #[test]
+    fn test_generate_wav_matches_render() {
+        let config = PipelineConfig {
+            timestep_samples: 250,
+            voice_config: VoiceConfig {
+                release_samples: 200,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let expected = render(config.clone(), staggered_events());
+
+        let path = "/tmp/test_generate_wav_matches_render.wav";
+        Pipeline::new(config, staggered_events())
+            .generate_wav(path)
+            .unwrap();
+        let audio = crate::wav::read_wav(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(audio.sample_rate, 44100);
+        assert_eq!(audio.channels[0].len(), expected.len());
+        for (read, rendered) in audio.channels[0].iter().zip(&expected) {
+            assert!((read - rendered).abs() <= 2.0 / 32768.0);
+        }
+    }
+
+    #[test]
+    fn test_generate_wav_with_spec() {
+        let config = PipelineConfig {
+            timestep_samples: 250,
+            ..Default::default()
+        };
+        let expected = render(config.clone(), staggered_events());
+
+        let path = "/tmp/test_generate_wav_with_spec.wav";
+        let spec = WavSpec::pcm(44100, 2, 24);
+        Pipeline::new(config, staggered_events())
+            .generate_wav_with(path, &spec)
+            .unwrap();
+        let audio = crate::wav::read_wav(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(audio.bits_per_sample, 24);
+        assert_eq!(audio.channels[0], audio.channels[1]);
+        for (read, rendered) in audio.channels[0].iter().zip(&expected) {
+            assert!((read - rendered).abs() <= 1.0 / 8_388_608.0);
+        }
+    }
+
+    #[test]
+    fn test_note_markers() {
+        let config = PipelineConfig {
+            timestep_samples: 250,
+            sample_accurate: true,
+            ..Default::default()
+        };
+        let path = "/tmp/test_note_markers.wav";
+        let metadata = WavMetadata {
+            cue_points: vec![CuePoint::new(0, "intro")],
+            ..Default::default()
+        };
+        Pipeline::new(config, staggered_events())
+            .with_note_markers()
+            .generate_wav_with_metadata(path, &WavSpec::pcm(44100, 1, 16), &metadata)
+            .unwrap();
+        let audio = crate::wav::read_wav(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            audio.metadata.cue_points,
+            vec![
+                CuePoint::new(0, "intro"),
+                CuePoint::new(250, "4c"),
+                CuePoint::new(500, "4e"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sampler_root_matches_written_pitch() {
+        use crate::pipeline::parser::parse_transcription;
+        use crate::wav::SamplerInfo;
+
+        let mut config = PipelineConfig::default();
+        config.voice_config.fm_params.mod_depth = 0.0;
+        let events = parse_transcription("+0| 3ad\n+20| 3au").unwrap();
+        let mut pipeline = Pipeline::new(config, events);
+
+        // As `play --root` does
+        let root = pipeline
+            .note_frequency(&c4().transposed(-3).unwrap())
+            .unwrap();
+        let metadata = WavMetadata {
+            sampler: Some(SamplerInfo::from_frequency(root as f64)),
+            ..Default::default()
+        };
+        let path = "/tmp/test_sampler_root_pitch.wav";
+        pipeline
+            .generate_wav_with_metadata(path, &WavSpec::float(44100, 1), &metadata)
+            .unwrap();
+        let audio = crate::wav::read_wav(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let stored = audio.metadata.sampler.unwrap().frequency();
+        let measured =
+            crate::pitch_check::measure_frequency(&audio.channels[0][4410..17640], 44100);
+        assert!(
+            (measured / stored - 1.0).abs() < 1e-4,
+            "stored {} Hz, measured {} Hz",
+            stored,
+            measured
+        );
+    }
+
+    #[test]
+    fn test_generate_wav_resamples_to_spec_rate() {
+        let config = PipelineConfig {
+            sample_rate: 88200,
+            timestep_samples: 500,
+            sample_accurate: true,
+            ..Default::default()
+        };
+        let rendered = render(config.clone(), staggered_events());
+
+        let path = "/tmp/test_generate_wav_resampled.wav";
+        Pipeline::new(config, staggered_events())
+            .with_note_markers()
+            .generate_wav_with(path, &WavSpec::float(44100, 1))
+            .unwrap();
+        let audio = crate::wav::read_wav(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(audio.sample_rate, 44100);
+        assert_eq!(audio.frames(), rendered.len().div_ceil(2));
+        let expected = resample(&rendered, 88200, 44100, ResampleQuality::Sinc);
+        assert_eq!(audio.channels[0], expected);
+        let positions: Vec<u32> = audio
+            .metadata
+            .cue_points
+            .iter()
+            .map(|p| p.position)
+            .collect();
+        assert_eq!(positions, vec![250, 500]);
+    }
+
+    #[test]
+    fn test_generate_wav_normalized_to_peak() {
+        let config = PipelineConfig {
+            timestep_samples: 250,
+            ..Default::default()
+        };
+        let rendered = render(config.clone(), staggered_events());
+        let peak = rendered.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+
+        let path = "/tmp/test_generate_wav_normalized.wav";
+        let mut pipeline = Pipeline::new(config, staggered_events())
+            .with_normalization(NormalizeTarget::Peak(-6.0));
+        pipeline
+            .generate_wav_with(path, &WavSpec::float(44100, 1))
+            .unwrap();
+        let audio = crate::wav::read_wav(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let measured = pipeline.measured_loudness().unwrap();
+        assert_eq!(measured.peak, peak);
+        let gain = crate::analysis::from_db(-6.0) as f32 / peak;
+        assert_eq!(audio.channels[0].len(), rendered.len());
+        for (read, rendered) in audio.channels[0].iter().zip(&rendered) {
+            assert!((read - rendered * gain).abs() <= 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_normalized_resampled_output_matches_plain_render() {
+        let config = PipelineConfig {
+            timestep_samples: 250,
+            ..Default::default()
+        };
+        let spec = WavSpec::float(48000, 1);
+        let write = |path: &str, target: Option<NormalizeTarget>| {
+            let mut pipeline =
+                Pipeline::new(config.clone(), staggered_events()).with_note_markers();
+            if let Some(target) = target {
+                pipeline = pipeline.with_normalization(target);
+            }
+            pipeline
+                .generate_wav_with_metadata(path, &spec, &WavMetadata::default())
+                .unwrap();
+            let audio = crate::wav::read_wav(path).unwrap();
+            std::fs::remove_file(path).unwrap();
+            (audio, pipeline.measured_loudness().copied())
+        };
+        let (plain, _) = write("/tmp/test_normalized_resampled_plain.wav", None);
+        let (normalized, measured) = write(
+            "/tmp/test_normalized_resampled.wav",
+            Some(NormalizeTarget::Peak(-6.0)),
+        );
+
+        // The measuring pass leaves no trace in the written file
+        assert_eq!(normalized.metadata.cue_points, plain.metadata.cue_points);
+        assert_eq!(normalized.metadata.cue_points.len(), 2);
+        let measured = measured.unwrap();
+        assert_eq!(
+            measured,
+            crate::analysis::analyze(&plain.channels[0], 48000)
+        );
+        let gain = crate::analysis::from_db(-6.0) as f32 / measured.peak;
+        assert_eq!(normalized.channels[0].len(), plain.channels[0].len());
+        for (read, plain) in normalized.channels[0].iter().zip(&plain.channels[0]) {
+            assert!((read - plain * gain).abs() <= 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_sample_accurate_independent_of_frame_size() {
+        let render_with = |frame_size: usize, sample_accurate: bool| {
+            let config = PipelineConfig {
+                timestep_samples: 250,
+                frame_size,
+                sample_accurate,
+                voice_config: VoiceConfig {
+                    release_samples: 200,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            render(config, staggered_events())
+        };
+
+        let reference = render_with(64, true);
+        for frame_size in [1, 37, 100, 512] {
+            let samples = render_with(frame_size, true);
+            let common = reference.len().min(samples.len());
+            assert!(common > 1500);
+            assert_eq!(
+                &samples[..common],
+                &reference[..common],
+                "frame size {}",
+                frame_size
+            );
+        }
+
+        // Without it, timing shifts with the frame size
+        let coarse = render_with(100, false);
+        let fine = render_with(37, false);
+        let common = coarse.len().min(fine.len());
+        assert_ne!(&coarse[..common], &fine[..common]);
+    }
+
+    #[test]
+    fn test_merge_tracks_time_order() {
+        let melody = track(
+            "melody",
+            vec![
+                create_simple_event(2, c4(), KeyDirection::Down),
+                create_simple_event(3, c4(), KeyDirection::Up),
+            ],
+        );
+        let bass = track(
+            "bass",
+            vec![
+                create_simple_event(0, e4(), KeyDirection::Down),
+                create_simple_event(5, e4(), KeyDirection::Up),
+            ],
+        );
+
+        let merged = merge_tracks(&[melody, bass]);
+
+        // Absolute timesteps: bass@0, melody@2, melody@5 + bass@5
+        let deltas: Vec<usize> = merged.iter().map(|m| m.delta).collect();
+        assert_eq!(deltas, vec![0, 2, 3]);
+        assert_eq!(merged[0].events[0].0, 1);
+        assert_eq!(merged[1].events[0].0, 0);
+        let last_tracks: Vec<usize> = merged[2].events.iter().map(|(t, _)| *t).collect();
+        assert_eq!(last_tracks, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_pipeline_tracks_use_separate_voices() {
+        let config = PipelineConfig {
+            timestep_samples: 100,
+            frame_size: 32,
+            ..Default::default()
+        };
+
+        // Same note on two tracks must not be treated as a duplicate
+        let tracks = vec![
+            track(
+                "melody",
+                vec![create_simple_event(0, c4(), KeyDirection::Down)],
+            ),
+            track(
+                "bass",
+                vec![create_simple_event(0, c4(), KeyDirection::Down)],
+            ),
+        ];
+
+        let mut pipeline = Pipeline::with_tracks(config, tracks).unwrap();
+        assert_eq!(pipeline.track_count(), 2);
+
+        let mut buffer = vec![0.0f32; 32];
+        pipeline.process_frame(&mut buffer);
+        assert_eq!(pipeline.voice_count(), 2);
+    }
+
+    #[test]
+    fn test_pipeline_track_gain() {
+        let config = PipelineConfig {
+            timestep_samples: 100,
+            frame_size: 64,
+            ..Default::default()
+        };
+        let events = vec![create_simple_event(0, c4(), KeyDirection::Down)];
+
+        let mut full = Pipeline::new(config.clone(), events.clone());
+        let mut quiet_track = track("quiet", events);
+        quiet_track.gain = 0.5;
+        let silent_track = track("silent", vec![]);
+        let mut quiet = Pipeline::with_tracks(config, vec![quiet_track, silent_track]).unwrap();
+
+        let mut full_buffer = vec![0.0f32; 64];
+        let mut quiet_buffer = vec![0.0f32; 64];
+        for _ in 0..10 {
+            full.process_frame(&mut full_buffer);
+            quiet.process_frame(&mut quiet_buffer);
+        }
+
+        for (f, q) in full_buffer.iter().zip(quiet_buffer.iter()) {
+            assert!((f * 0.5 - q).abs() < 1e-6, "expected {} got {}", f * 0.5, q);
+        }
+    }
+
+    #[test]
+    fn test_pipeline_pedal_order_within_line() {
+        let config = PipelineConfig {
+            timestep_samples: 100,
+            frame_size: 32,
+            voice_config: VoiceConfig {
+                attack_samples: 10,
+                decay_samples: 10,
+                release_samples: 64,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // The pedal goes down after the C is released on the same line,
+        // so only the E is held until the pedal lifts
+        let events = crate::pipeline::parser::parse_transcription(
+            "+0| 4cd, 4ed\n+1| 4cu, ped+\n+1| 4eu\n+20| ped-",
+        )
+        .unwrap();
+        let mut pipeline = Pipeline::new(config, events);
+
+        let mut buffer = vec![0.0f32; 32];
+        let mut counts = Vec::new();
+        for _ in 0..100 {
+            pipeline.process_frame(&mut buffer);
+            counts.push(pipeline.voice_count());
+        }
+
+        // Frame 16 is around sample 512, frame 90 around sample 2900
+        assert_eq!(counts[16], 1);
+        assert_eq!(counts[90], 0);
+    }
+
+    #[test]
+    fn test_pipeline_track_instrument_lookup() {
+        let mut config = PipelineConfig::default();
+        let pad = VoiceConfig {
+            attack_samples: 20000,
+            ..Default::default()
+        };
+        config.instruments.insert("pad".to_string(), pad);
+
+        assert_eq!(config.instrument("pad").unwrap().attack_samples, 20000);
+        assert_eq!(
+            config.instrument("unknown").unwrap_err(),
+            PipelineError::UnknownInstrument("unknown".to_string())
+        );
+
+        // Without an explicit instrument a track uses the one named after it
+        let pad_track = Track::new("pad");
+        assert_eq!(
+            config.track_instrument(&pad_track).unwrap().attack_samples,
+            20000
+        );
+        let melody = Track::new("melody");
+        assert_eq!(
+            config.track_instrument(&melody).unwrap().attack_samples,
+            config.voice_config.attack_samples
+        );
+    }
+
+    #[test]
+    fn test_pipeline_unknown_instrument() {
+        let mut track = Track::new("melody");
+        track.instrument = Some("paad".to_string());
+        let error = Pipeline::with_tracks(PipelineConfig::default(), vec![track])
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "Unknown instrument: paad");
+    }
 }
@@ -0,0 +1,364 @@
+//! Transforms over parsed event streams
+//!
+//! Operations that rewrite a `Vec<TimedEvents>` so the same transcription
+//! can be reused in different keys and tempos:
+//! - Transpose: shift every note by a number of semitones
+//! - Scale time: stretch or compress timing by a ratio
+//! - Quantize: snap event lines to a grid of timesteps
+//! - Humanize: seeded random jitter of timing and velocity
+//!
+//! Timing transforms work on absolute timesteps and convert back to deltas,
+//! so rounding never accumulates across lines.
+
+use crate::pipeline::parser::{KeyDirection, Note, TimedEvents};
+
+/// Errors from transforms
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransformError {
+    /// Transposing this note leaves the supported octave range
+    NoteOutOfRange { note: Note, semitones: i32 },
+    /// Time scale ratio must be finite and positive
+    InvalidRatio(f64),
+    /// Quantize grid must be at least one timestep
+    InvalidGrid(usize),
+    /// Humanize timing jitter too large to apply
+    InvalidJitter(usize),
+    /// Humanize velocity jitter must be finite and non-negative
+    InvalidVelocity(f32),
+}
+
+impl std::fmt::Display for TransformError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransformError::NoteOutOfRange { note, semitones } => write!(
+                f,
+                "Note out of range: {} transposed by {} semitones",
+                note, semitones
+            ),
+            TransformError::InvalidRatio(r) => write!(f, "Invalid time ratio: {}", r),
+            TransformError::InvalidGrid(g) => write!(f, "Invalid quantize grid: {}", g),
+            TransformError::InvalidJitter(j) => write!(f, "Invalid humanize timing: {}", j),
+            TransformError::InvalidVelocity(v) => write!(f, "Invalid humanize velocity: {}", v),
+        }
+    }
+}
+
+impl std::error::Error for TransformError {}
+
+/// Convert deltas to absolute timesteps
+fn absolute_times(events: &[TimedEvents]) -> Vec<usize> {
+    events
+        .iter()
+        .scan(0usize, |time, timed| {
+            *time += timed.delta;
+            Some(*time)
+        })
+        .collect()
+}
+
+/// Rebuild events from absolute timesteps (which must be non-decreasing)
+fn with_absolute_times(events: &[TimedEvents], times: &[usize]) -> Vec<TimedEvents> {
+    let mut previous = 0;
+    events
+        .iter()
+        .zip(times)
+        .map(|(timed, &time)| {
+            let delta = time - previous;
+            previous = time;
+            TimedEvents {
+                delta,
+                events: timed.events.clone(),
+                controls: timed.controls.clone(),
+            }
+        })
+        .collect()
+}
+
+/// Transpose every note by a number of semitones
+///
+/// Fails without modifying anything if any note would leave octaves 0-9.
+pub fn transpose(
+    events: &[TimedEvents],
+    semitones: i32,
+) -> Result<Vec<TimedEvents>, TransformError> {
+    let mut result = events.to_vec();
+    for timed in result.iter_mut() {
+        for event in timed.events.iter_mut() {
+            event.note =
+                event
+                    .note
+                    .transposed(semitones)
+                    .ok_or(TransformError::NoteOutOfRange {
+                        note: event.note,
+                        semitones,
+                    })?;
+        }
+    }
+    Ok(result)
+}
+
+/// Scale timing by a ratio (2.0 = twice as long, 0.5 = twice as fast)
+///
+/// Absolute times are rounded to the nearest timestep.
+pub fn scale_time(events: &[TimedEvents], ratio: f64) -> Result<Vec<TimedEvents>, TransformError> {
+    if !(ratio.is_finite() && ratio > 0.0) {
+        return Err(TransformError::InvalidRatio(ratio));
+    }
+    let times: Vec<usize> = absolute_times(events)
+        .into_iter()
+        .map(|t| (t as f64 * ratio).round() as usize)
+        .collect();
+    Ok(with_absolute_times(events, &times))
+}
+
+/// Snap every line to the nearest multiple of `grid` timesteps
+///
+/// Ties round up, matching `f64::round`.
+pub fn quantize(events: &[TimedEvents], grid: usize) -> Result<Vec<TimedEvents>, TransformError> {
+    if grid == 0 {
+        return Err(TransformError::InvalidGrid(grid));
+    }
+    let times: Vec<usize> = absolute_times(events)
+        .into_iter()
+        .map(|t| (t + grid / 2) / grid * grid)
+        .collect();
+    Ok(with_absolute_times(events, &times))
+}
+
+/// Settings for [`humanize`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HumanizeParams {
+    /// Maximum timing offset in timesteps (either direction)
+    pub timing: usize,
+    /// Maximum velocity offset (either direction) applied to key-down events
+    pub velocity: f32,
+    /// Seed for the random generator; equal seeds give equal results
+    pub seed: u64,
+}
+
+/// Small deterministic PRNG (SplitMix64) so renders are reproducible
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform integer in [-max, max], or `None` if the range overflows
+    fn offset(&mut self, max: usize) -> Option<i64> {
+        let max = i64::try_from(max).ok()?;
+        let span = (max as u64).checked_mul(2)?.checked_add(1)?;
+        // In [-max, max], which fits an i64
+        Some(((self.next_u64() % span) as i128 - max as i128) as i64)
+    }
+
+    /// Uniform float in [-1.0, 1.0)
+    pub(crate) fn unit(&mut self) -> f32 {
+        ((self.next_u64() >> 40) as f32 / (1u64 << 24) as f32) * 2.0 - 1.0
+    }
+}
+
+/// Apply random timing and velocity jitter
+///
+/// Each line moves as a whole and never moves before the previous line,
+/// so key-up events stay after their key-down. Velocities are clamped to
+/// [0.0, 1.0]. Timing jitter is in whole timesteps; use [`scale_time`]
+/// first to work on a finer grid.
+///
+/// # Errors
+/// Returns [`TransformError::InvalidJitter`] if the timing jitter is too
+/// large to add to the event times, or [`TransformError::InvalidVelocity`]
+/// if the velocity jitter is negative, NaN or infinite.
+pub fn humanize(
+    events: &[TimedEvents],
+    params: &HumanizeParams,
+) -> Result<Vec<TimedEvents>, TransformError> {
+    if !(params.velocity.is_finite() && params.velocity >= 0.0) {
+        return Err(TransformError::InvalidVelocity(params.velocity));
+    }
+    let mut rng = SplitMix64(params.seed);
+    let mut previous = 0usize;
+
+    let times = absolute_times(events)
+        .into_iter()
+        .map(|t| {
+            let jittered = rng
+                .offset(params.timing)
+                .and_then(|offset| i64::try_from(t).ok()?.checked_add(offset))
+                .ok_or(TransformError::InvalidJitter(params.timing))?;
+            previous = (jittered.max(0) as usize).max(previous);
+            Ok(previous)
+        })
+        .collect::<Result<Vec<usize>, _>>()?;
+
+    let mut result = with_absolute_times(events, &times);
+    for timed in result.iter_mut() {
+        for event in timed.events.iter_mut() {
+            if event.direction == KeyDirection::Down {
+                let jitter = rng.unit() * params.velocity;
+                event.velocity = (event.velocity + jitter).clamp(0.0, 1.0);
+            }
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::parser::{parse_transcription, PitchClass};
+
+    fn song() -> Vec<TimedEvents> {
+        parse_transcription("+0| 4cd\n+3| 4cu, 4ed\n+3| 4eu, 4gd\n+5| 4gu\n+1| 5cd\n+2| 5cu")
+            .unwrap()
+    }
+
+    fn deltas(events: &[TimedEvents]) -> Vec<usize> {
+        events.iter().map(|t| t.delta).collect()
+    }
+
+    #[test]
+    fn test_transpose() {
+        let result = transpose(&song(), 2).unwrap();
+        assert_eq!(result[0].events[0].note.pitch_class, PitchClass::D);
+        assert_eq!(result[2].events[1].note.pitch_class, PitchClass::A);
+        assert_eq!(deltas(&result), deltas(&song()));
+
+        // Octave wrap
+        let result = transpose(&song(), -1).unwrap();
+        assert_eq!(result[0].events[0].note.octave, 3);
+        assert_eq!(result[0].events[0].note.pitch_class, PitchClass::B);
+
+        // Round trip
+        let back = transpose(&transpose(&song(), 7).unwrap(), -7).unwrap();
+        assert_eq!(back, song());
+    }
+
+    #[test]
+    fn test_transpose_out_of_range() {
+        let err = transpose(&song(), -49).unwrap_err();
+        assert!(matches!(
+            err,
+            TransformError::NoteOutOfRange { semitones: -49, .. }
+        ));
+        assert!(transpose(&song(), 12 * 5).is_err());
+        assert!(transpose(&song(), i32::MAX).is_err());
+        assert!(transpose(&song(), i32::MIN).is_err());
+    }
+
+    #[test]
+    fn test_scale_time() {
+        assert_eq!(
+            deltas(&scale_time(&song(), 2.0).unwrap()),
+            vec![0, 6, 6, 10, 2, 4]
+        );
+
+        // Absolute times 0,3,6,11,12,14 -> 0,2,3,6,6,7 (rounded)
+        assert_eq!(
+            deltas(&scale_time(&song(), 0.5).unwrap()),
+            vec![0, 2, 1, 3, 0, 1]
+        );
+
+        assert!(scale_time(&song(), 0.0).is_err());
+        assert!(scale_time(&song(), f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_quantize() {
+        // Absolute times 0,3,6,11,12,14 -> 0,4,8,12,12,16
+        let result = quantize(&song(), 4).unwrap();
+        assert_eq!(deltas(&result), vec![0, 4, 4, 4, 0, 4]);
+        assert_eq!(quantize(&song(), 1).unwrap(), song());
+        assert!(quantize(&song(), 0).is_err());
+    }
+
+    #[test]
+    fn test_humanize_is_seeded() {
+        let params = HumanizeParams {
+            timing: 1,
+            velocity: 0.2,
+            seed: 42,
+        };
+        let a = humanize(&song(), &params).unwrap();
+        let b = humanize(&song(), &params).unwrap();
+        assert_eq!(a, b);
+
+        let c = humanize(&song(), &HumanizeParams { seed: 7, ..params }).unwrap();
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_humanize_bounds() {
+        let params = HumanizeParams {
+            timing: 2,
+            velocity: 0.3,
+            seed: 1,
+        };
+        let original = absolute_times(&song());
+        let result = humanize(&song(), &params).unwrap();
+
+        // Lines keep their order and stay within the jitter window
+        let times = absolute_times(&result);
+        for (i, (&t, &o)) in times.iter().zip(original.iter()).enumerate() {
+            if i > 0 {
+                assert!(t >= times[i - 1]);
+            }
+            assert!(t + 2 >= o && t <= o + 2, "line {}: {} vs {}", i, t, o);
+        }
+
+        for timed in &result {
+            for event in &timed.events {
+                if event.direction == KeyDirection::Down {
+                    assert!(event.velocity >= 0.7 && event.velocity <= 1.0);
+                } else {
+                    assert_eq!(event.velocity, 1.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_humanize_zero_is_identity() {
+        let params = HumanizeParams {
+            timing: 0,
+            velocity: 0.0,
+            seed: 3,
+        };
+        assert_eq!(humanize(&song(), &params).unwrap(), song());
+    }
+
+    #[test]
+    fn test_humanize_rejects_overflowing_jitter() {
+        let params = HumanizeParams {
+            timing: usize::MAX,
+            velocity: 0.0,
+            seed: 3,
+        };
+        assert_eq!(
+            humanize(&song(), &params),
+            Err(TransformError::InvalidJitter(usize::MAX))
+        );
+    }
+
+    #[test]
+    fn test_humanize_rejects_invalid_velocity() {
+        for velocity in [f32::NAN, f32::INFINITY, -0.1] {
+            let params = HumanizeParams {
+                timing: 0,
+                velocity,
+                seed: 1,
+            };
+            let result = humanize(&song(), &params);
+            assert!(
+                matches!(result, Err(TransformError::InvalidVelocity(v)) if v.to_bits() == velocity.to_bits()),
+                "{:?} for {}",
+                result,
+                velocity
+            );
+        }
+    }
+}
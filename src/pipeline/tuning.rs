@@ -0,0 +1,556 @@
+//! Tunings: mapping notes to frequencies
+//!
+//! A [`Tuning`] combines a [`Scale`] (the pitches of one period, such as an
+//! octave) with a [`KeyboardMapping`] (which key plays which scale degree,
+//! and which key sounds at a reference frequency). Both can be loaded from
+//! Scala files:
+//! - `.scl`: scale degrees in cents (`701.955`) or ratios (`3/2`, `2`)
+//! - `.kbm`: key range, middle key, reference key and frequency, formal
+//!   octave and the degree played by each key (`x` for unmapped keys)
+//!
+//! Built-in scales cover equal divisions of any period (12-TET, other EDOs,
+//! stretched octaves) and 5-limit just intonation.
+//!
+//! Keys are MIDI note numbers, as in `.kbm` files (24 = 1C, 69 = 4a); keys
+//! outside 0..=127 are allowed.
+
+use std::path::Path;
+
+use crate::pipeline::parser::{Note, MIDI_OFFSET};
+
+/// Most keys a `.kbm` mapping pattern may have (a full MIDI keyboard)
+pub const MAX_MAP_SIZE: usize = 128;
+
+/// Errors from loading or building a tuning
+#[derive(Debug)]
+pub enum TuningError {
+    /// Invalid `.scl` contents at a 1-based line
+    InvalidScale { line: usize, message: String },
+    /// Invalid `.kbm` contents at a 1-based line
+    InvalidMapping { line: usize, message: String },
+    /// The mapping's reference key has no scale degree
+    UnmappedReference(i32),
+    /// Reading a file failed
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for TuningError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TuningError::InvalidScale { line, message } => {
+                write!(f, "Invalid scale at line {}: {}", line, message)
+            }
+            TuningError::InvalidMapping { line, message } => {
+                write!(f, "Invalid keyboard mapping at line {}: {}", line, message)
+            }
+            TuningError::UnmappedReference(key) => {
+                write!(f, "Reference key {} is not mapped to a scale degree", key)
+            }
+            TuningError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TuningError {}
+
+impl From<std::io::Error> for TuningError {
+    fn from(e: std::io::Error) -> Self {
+        TuningError::Io(e)
+    }
+}
+
+/// Non-comment lines of a Scala file with their 1-based line numbers
+fn scala_lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.starts_with('!'))
+        .map(|(i, line)| (i + 1, line.trim()))
+}
+
+/// Parse a Scala pitch: cents if it contains a '.', otherwise a ratio
+fn parse_pitch(s: &str) -> Option<f64> {
+    // Anything after the first whitespace is a label
+    let value = s.split_whitespace().next()?;
+    if value.contains('.') {
+        return value.parse::<f64>().ok().filter(|c| c.is_finite());
+    }
+    let (numerator, denominator) = value.split_once('/').unwrap_or((value, "1"));
+    let numerator: u64 = numerator.parse().ok()?;
+    let denominator: u64 = denominator.parse().ok()?;
+    if numerator == 0 || denominator == 0 {
+        return None;
+    }
+    Some(1200.0 * (numerator as f64 / denominator as f64).log2())
+}
+
+/// The pitches of one period of a scale
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scale {
+    /// Free-form description (first line of a `.scl` file)
+    pub description: String,
+    /// Degrees 1..=n in cents above degree 0; the last one is the period
+    pub degrees: Vec<f64>,
+}
+
+impl Scale {
+    /// Create a scale from degrees in cents (the last one is the period)
+    ///
+    /// # Panics
+    /// Panics if `degrees` is empty
+    pub fn new(description: &str, degrees: Vec<f64>) -> Self {
+        assert!(!degrees.is_empty(), "A scale needs at least one degree");
+        Self {
+            description: description.to_string(),
+            degrees,
+        }
+    }
+
+    /// Equal division of a period into `divisions` steps
+    ///
+    /// A period of 1200 cents gives an EDO (12 for standard tuning); other
+    /// periods give stretched or compressed octaves, or non-octave scales.
+    ///
+    /// # Panics
+    /// Panics if `divisions` is 0
+    pub fn equal(divisions: usize, period_cents: f64) -> Self {
+        assert!(divisions > 0, "An equal scale needs at least one division");
+        let step = period_cents / divisions as f64;
+        let degrees = (1..=divisions).map(|i| step * i as f64).collect();
+        Self::new(
+            &format!("{} equal divisions of {} cents", divisions, period_cents),
+            degrees,
+        )
+    }
+
+    /// Standard 12-tone equal temperament
+    pub fn equal_temperament() -> Self {
+        Self::equal(12, 1200.0)
+    }
+
+    /// 12-note 5-limit just intonation (degree 0 is the tonic)
+    pub fn just_intonation() -> Self {
+        let ratios = [
+            16.0 / 15.0,
+            9.0 / 8.0,
+            6.0 / 5.0,
+            5.0 / 4.0,
+            4.0 / 3.0,
+            45.0 / 32.0,
+            3.0 / 2.0,
+            8.0 / 5.0,
+            5.0 / 3.0,
+            9.0 / 5.0,
+            15.0 / 8.0,
+            2.0,
+        ];
+        let degrees = ratios.iter().map(|r: &f64| 1200.0 * r.log2()).collect();
+        Self::new("5-limit just intonation", degrees)
+    }
+
+    /// Parse the contents of a Scala `.scl` file
+    pub fn parse_scl(text: &str) -> Result<Self, TuningError> {
+        let invalid = |line: usize, message: String| TuningError::InvalidScale { line, message };
+        let mut lines = scala_lines(text);
+
+        let (_, description) = lines
+            .next()
+            .ok_or_else(|| invalid(1, "missing description".to_string()))?;
+        let (count_line, count) = lines
+            .next()
+            .ok_or_else(|| invalid(1, "missing note count".to_string()))?;
+        let count: usize = count
+            .split_whitespace()
+            .next()
+            .and_then(|c| c.parse().ok())
+            .ok_or_else(|| invalid(count_line, format!("invalid note count: {}", count)))?;
+        if count == 0 {
+            return Err(invalid(count_line, "scale has no notes".to_string()));
+        }
+
+        // The count is only trusted once that many notes have been read
+        let mut degrees = Vec::new();
+        for (line, pitch) in lines.take(count) {
+            let cents = parse_pitch(pitch)
+                .ok_or_else(|| invalid(line, format!("invalid pitch: {}", pitch)))?;
+            degrees.push(cents);
+        }
+        if degrees.len() < count {
+            return Err(invalid(
+                count_line,
+                format!("expected {} notes, found {}", count, degrees.len()),
+            ));
+        }
+
+        Ok(Self::new(description, degrees))
+    }
+
+    /// Load a Scala `.scl` file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TuningError> {
+        Self::parse_scl(&std::fs::read_to_string(path)?)
+    }
+
+    /// Number of degrees per period
+    pub fn len(&self) -> usize {
+        self.degrees.len()
+    }
+
+    /// Check if the scale has no degrees (never true for a valid scale)
+    pub fn is_empty(&self) -> bool {
+        self.degrees.is_empty()
+    }
+
+    /// Size of the period in cents
+    pub fn period_cents(&self) -> f64 {
+        *self.degrees.last().expect("scale has degrees")
+    }
+
+    /// Cents of any degree, counting into further periods (may be negative)
+    pub fn degree_cents(&self, degree: i64) -> f64 {
+        let n = self.len() as i64;
+        let period = degree.div_euclid(n);
+        let index = degree.rem_euclid(n) as usize;
+        let within = if index == 0 {
+            0.0
+        } else {
+            self.degrees[index - 1]
+        };
+        period as f64 * self.period_cents() + within
+    }
+}
+
+/// Which key plays which scale degree, and the reference pitch
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyboardMapping {
+    /// Lowest key that sounds
+    pub first_key: i32,
+    /// Highest key that sounds
+    pub last_key: i32,
+    /// Key that plays degree 0 (the start of the mapping pattern)
+    pub middle_key: i32,
+    /// Key tuned to `reference_frequency`
+    pub reference_key: i32,
+    /// Frequency of the reference key in Hz
+    pub reference_frequency: f64,
+    /// Scale degree reached by one repetition of the mapping pattern
+    /// (0 = the scale's own period)
+    pub octave_degree: usize,
+    /// Degree played by each key of the pattern (`None` = silent); empty
+    /// means every key plays the next degree
+    pub degrees: Vec<Option<usize>>,
+}
+
+impl KeyboardMapping {
+    /// Map consecutive keys to consecutive degrees
+    ///
+    /// # Arguments
+    /// * `middle_key` - Key that plays degree 0
+    /// * `reference_key` - Key tuned to `reference_frequency`
+    /// * `reference_frequency` - Frequency in Hz
+    pub fn linear(middle_key: i32, reference_key: i32, reference_frequency: f64) -> Self {
+        Self {
+            first_key: i32::MIN,
+            last_key: i32::MAX,
+            middle_key,
+            reference_key,
+            reference_frequency,
+            octave_degree: 0,
+            degrees: Vec::new(),
+        }
+    }
+
+    /// Parse the contents of a Scala `.kbm` file
+    pub fn parse_kbm(text: &str) -> Result<Self, TuningError> {
+        let invalid = |line: usize, message: String| TuningError::InvalidMapping { line, message };
+        // First token of each non-empty line; anything after it is a comment
+        let values: Vec<(usize, &str)> = scala_lines(text)
+            .filter_map(|(line, value)| Some((line, value.split_whitespace().next()?)))
+            .collect();
+        let last_line = values.last().map_or(1, |(line, _)| *line);
+
+        let value = |index: usize, name: &str| -> Result<(usize, &str), TuningError> {
+            values
+                .get(index)
+                .copied()
+                .ok_or_else(|| invalid(last_line, format!("missing {}", name)))
+        };
+        let header = |index: usize, name: &str| -> Result<i64, TuningError> {
+            let (line, v) = value(index, name)?;
+            v.parse()
+                .map_err(|_| invalid(line, format!("invalid {}: {}", name, v)))
+        };
+
+        let size = header(0, "map size")?;
+        if size > MAX_MAP_SIZE as i64 {
+            return Err(invalid(
+                values[0].0,
+                format!("map size {} is over {}", size, MAX_MAP_SIZE),
+            ));
+        }
+        let first_key = header(1, "first key")?;
+        let last_key = header(2, "last key")?;
+        let middle_key = header(3, "middle key")?;
+        let reference_key = header(4, "reference key")?;
+        let (line, frequency) = value(5, "reference frequency")?;
+        let reference_frequency = frequency
+            .parse::<f64>()
+            .ok()
+            .filter(|f| f.is_finite() && *f > 0.0)
+            .ok_or_else(|| invalid(line, format!("invalid reference frequency: {}", frequency)))?;
+        let octave_degree = header(6, "octave degree")?;
+
+        let mut degrees = Vec::new();
+        for index in 0..size.max(0) as usize {
+            // Missing entries at the end are unmapped
+            match values.get(7 + index) {
+                None | Some((_, "x")) => degrees.push(None),
+                Some((line, entry)) => {
+                    let degree = entry
+                        .parse()
+                        .map_err(|_| invalid(*line, format!("invalid mapping entry: {}", entry)))?;
+                    degrees.push(Some(degree));
+                }
+            }
+        }
+
+        let to_key = |v: i64| v.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+        Ok(Self {
+            first_key: to_key(first_key),
+            last_key: to_key(last_key),
+            middle_key: to_key(middle_key),
+            reference_key: to_key(reference_key),
+            reference_frequency,
+            octave_degree: octave_degree.max(0) as usize,
+            degrees,
+        })
+    }
+
+    /// Load a Scala `.kbm` file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TuningError> {
+        Self::parse_kbm(&std::fs::read_to_string(path)?)
+    }
+}
+
+/// A scale laid out on the keyboard
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tuning {
+    scale: Scale,
+    mapping: KeyboardMapping,
+    /// Cents of the reference key relative to degree 0
+    reference_cents: f64,
+}
+
+impl Tuning {
+    /// Combine a scale with a keyboard mapping
+    ///
+    /// Fails if the mapping's reference key does not play a scale degree.
+    pub fn new(scale: Scale, mapping: KeyboardMapping) -> Result<Self, TuningError> {
+        let reference_cents = key_cents(&scale, &mapping, mapping.reference_key)
+            .ok_or(TuningError::UnmappedReference(mapping.reference_key))?;
+        Ok(Self {
+            scale,
+            mapping,
+            reference_cents,
+        })
+    }
+
+    /// A scale with degree 0 on `key`, which sounds at `frequency`
+    pub fn from_scale(scale: Scale, key: i32, frequency: f64) -> Self {
+        Self::new(scale, KeyboardMapping::linear(key, key, frequency))
+            .expect("linear mappings map every key")
+    }
+
+    /// 12-TET with 1C at `base_frequency` (the pipeline's default tuning)
+    pub fn equal_temperament(base_frequency: f64) -> Self {
+        Self::from_scale(Scale::equal_temperament(), 12 + MIDI_OFFSET, base_frequency)
+    }
+
+    /// The scale being played
+    pub fn scale(&self) -> &Scale {
+        &self.scale
+    }
+
+    /// The keyboard mapping in use
+    pub fn mapping(&self) -> &KeyboardMapping {
+        &self.mapping
+    }
+
+    /// Frequency of a key in Hz, or `None` if the key is out of range or
+    /// unmapped
+    pub fn key_frequency(&self, key: i32) -> Option<f64> {
+        let cents = key_cents(&self.scale, &self.mapping, key)?;
+        Some(self.mapping.reference_frequency * 2f64.powf((cents - self.reference_cents) / 1200.0))
+    }
+
+    /// Frequency of a note in Hz, or `None` if its key is not mapped
+    pub fn note_frequency(&self, note: &Note) -> Option<f64> {
+        self.key_frequency(note.semitone_index() + MIDI_OFFSET)
+    }
+}
+
+/// Cents of a key relative to the mapping's degree 0
+fn key_cents(scale: &Scale, mapping: &KeyboardMapping, key: i32) -> Option<f64> {
+    if key < mapping.first_key || key > mapping.last_key {
+        return None;
+    }
+    let offset = key as i64 - mapping.middle_key as i64;
+    if mapping.degrees.is_empty() {
+        return Some(scale.degree_cents(offset));
+    }
+
+    let size = mapping.degrees.len() as i64;
+    let repeat = offset.div_euclid(size);
+    let degree = mapping.degrees[offset.rem_euclid(size) as usize]?;
+    let octave_degree = if mapping.octave_degree == 0 {
+        scale.len()
+    } else {
+        mapping.octave_degree
+    };
+    Some(
+        repeat as f64 * scale.degree_cents(octave_degree as i64)
+            + scale.degree_cents(degree as i64),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::parser::PitchClass;
+
+    const MEANTONE_SCL: &str = "! meanquar.scl
+!
+1/4-comma meantone scale. Pietro Aaron's temperament (1523)
+ 12
+!
+ 76.04900
+ 193.15686
+ 310.26471
+ 5/4
+ 503.42157
+ 579.47057
+ 696.57843
+ 25/16
+ 889.73529
+ 1006.84314
+ 1082.89214
+ 2/1
+";
+
+    fn approx(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn test_parse_scl() {
+        let scale = Scale::parse_scl(MEANTONE_SCL).unwrap();
+        assert_eq!(
+            scale.description,
+            "1/4-comma meantone scale. Pietro Aaron's temperament (1523)"
+        );
+        assert_eq!(scale.len(), 12);
+        assert!(approx(scale.degrees[3], 1200.0 * 1.25f64.log2()));
+        assert!(approx(scale.period_cents(), 1200.0));
+        assert!(approx(scale.degree_cents(-1), 1082.89214 - 1200.0));
+    }
+
+    #[test]
+    fn test_parse_scl_errors() {
+        let err = Scale::parse_scl("desc\n 2\n 100.0\n 3/0\n").unwrap_err();
+        assert!(matches!(err, TuningError::InvalidScale { line: 4, .. }));
+
+        let err = Scale::parse_scl("desc\n 3\n 100.0\n").unwrap_err();
+        assert!(matches!(err, TuningError::InvalidScale { line: 2, .. }));
+
+        assert!(Scale::parse_scl("desc\n 0\n").is_err());
+    }
+
+    #[test]
+    fn test_equal_temperament_matches_base_frequency() {
+        let tuning = Tuning::equal_temperament(110.0);
+        let c1 = Note {
+            octave: 1,
+            pitch_class: PitchClass::C,
+        };
+        let a4 = Note {
+            octave: 4,
+            pitch_class: PitchClass::A,
+        };
+        assert!(approx(tuning.note_frequency(&c1).unwrap(), 110.0));
+        let expected = 110.0 * 2f64.powf(3.0 + 9.0 / 12.0);
+        assert!(approx(tuning.note_frequency(&a4).unwrap(), expected));
+    }
+
+    #[test]
+    fn test_edo_and_stretched_octave() {
+        let edo19 = Tuning::from_scale(Scale::equal(19, 1200.0), 0, 100.0);
+        assert!(approx(edo19.key_frequency(19).unwrap(), 200.0));
+        assert!(approx(edo19.key_frequency(-19).unwrap(), 50.0));
+
+        let stretched = Tuning::from_scale(Scale::equal(12, 1203.0), 0, 100.0);
+        assert!(stretched.key_frequency(12).unwrap() > 200.0);
+    }
+
+    #[test]
+    fn test_just_intonation() {
+        let just = Tuning::from_scale(Scale::just_intonation(), 0, 200.0);
+        assert!(approx(just.key_frequency(7).unwrap(), 300.0));
+        assert!(approx(just.key_frequency(4).unwrap(), 250.0));
+        assert!(approx(just.key_frequency(12 + 4).unwrap(), 500.0));
+    }
+
+    #[test]
+    fn test_parse_kbm_with_unmapped_keys() {
+        // 7 white keys per octave: C D E F G A B of a 12-note scale, A = 440
+        let kbm = "! white keys only
+12
+0
+127
+60
+69
+440.0
+12
+0
+x
+2
+x
+4
+5
+x
+7
+x
+9
+x
+11
+";
+        let mapping = KeyboardMapping::parse_kbm(kbm).unwrap();
+        assert_eq!(mapping.degrees.len(), 12);
+        assert_eq!(mapping.degrees[1], None);
+
+        let tuning = Tuning::new(Scale::equal_temperament(), mapping).unwrap();
+        assert!(approx(tuning.key_frequency(69).unwrap(), 440.0));
+        assert!(approx(tuning.key_frequency(81).unwrap(), 880.0));
+        assert!(approx(
+            tuning.key_frequency(60).unwrap(),
+            440.0 * 2f64.powf(-9.0 / 12.0)
+        ));
+        assert_eq!(tuning.key_frequency(61), None);
+        assert_eq!(tuning.key_frequency(128), None);
+    }
+
+    #[test]
+    fn test_kbm_errors() {
+        let err = KeyboardMapping::parse_kbm("0\n0\n127\n60\n69\nfast\n").unwrap_err();
+        assert!(matches!(err, TuningError::InvalidMapping { line: 6, .. }));
+
+        let err =
+            KeyboardMapping::parse_kbm("! huge\n1000000000000000000\n0\n127\n60\n69\n440\n0\n")
+                .unwrap_err();
+        assert!(matches!(err, TuningError::InvalidMapping { line: 2, .. }));
+        let err = Scale::parse_scl("huge\n1000000000000000000\n100.0\n").unwrap_err();
+        assert!(matches!(err, TuningError::InvalidScale { line: 2, .. }));
+
+        let mut mapping = KeyboardMapping::linear(60, 61, 440.0);
+        mapping.degrees = vec![Some(0), None];
+        let err = Tuning::new(Scale::equal_temperament(), mapping).unwrap_err();
+        assert!(matches!(err, TuningError::UnmappedReference(61)));
+    }
+}
@@ -2,11 +2,284 @@
 //!
 //! Manages active FM synthesizer voices, handling note allocation,
 //! note release, and cleanup of completed voices.
+//!
+//! The number of simultaneous voices can be limited. When a new note needs
+//! a voice beyond the limit, an existing voice is stolen according to the
+//! configured [`StealPolicy`] and faded out quickly to avoid a click.
+//!
+//! In mono mode a single voice follows the held keys: a stack of held notes
+//! and a [`NotePriority`] pick which one sounds, legato keeps the envelope
+//! running across note changes, and glide slides the pitch between notes.
+//!
+//! Key-downs and key-ups are reference counted per note, so a note pressed
+//! twice is only released by the second key-up. A key-down for a note that
+//! is already sounding follows the configured [`RetriggerPolicy`].
+//!
+//! While the sustain pedal is down, key-ups leave their voices sounding
+//! until the pedal lifts. The sostenuto pedal does the same, but only for
+//! the notes whose keys were down when it was pressed.
+//!
+//! Pitch bend moves every voice by up to `bend_range` semitones. Other
+//! controllers drive voice parameters through the instrument's
+//! [`ControllerMapping`]s (by default the mod wheel scales the modulation
+//! depth, and volume and expression scale the output).
+//!
+//! Notes are tuned to 12-TET from the base frequency unless a [`Tuning`]
+//! is set; keys the tuning leaves unmapped are ignored.
+//!
+//! A [`SampleMap`] layers recorded samples onto the FM voices: each note
+//! plays the sample of the first zone covering its key and velocity,
+//! pitched from the sample's root note. `fm_level` sets the level of the
+//! FM layer (0 plays the samples alone).
+//!
+//! Rendering and event handling do not allocate once the manager is built:
+//! voice storage, finished synthesizers (reused for new notes) and scratch
+//! buffers are allocated up front. Only playing more voices at once than
+//! were preallocated allocates.
+//!
+//! For offline rendering, [`VoiceManager::with_render_threads`] spreads the
+//! voices of busy frames over worker threads. The output is bit-identical
+//! to single-threaded rendering.
 
 use crate::generator::adsr::AdsrGenerator;
-use crate::generator::fm_synth::{FmSynthGenerator, FmSynthParams};
-use crate::generator::{GeneratorState, SignalGenerator};
-use crate::pipeline::parser::{KeyDirection, Note};
+use crate::generator::fm_synth::{
+    FmSynthGenerator, FmSynthParams, GlideCurve, MAX_PHASE_PER_SAMPLE,
+};
+use crate::generator::sampler::{Sample, SamplerGenerator};
+use crate::generator::{block, GeneratorState, SignalGenerator, MAX_FRAME_SIZE};
+use crate::pipeline::parser::{
+    Control, KeyDirection, Note, Pedal, CC_EXPRESSION, CC_MOD_WHEEL, CC_SOSTENUTO, CC_SUSTAIN,
+    CC_VOLUME,
+};
+use crate::pipeline::tuning::Tuning;
+use crate::pipeline::workers::WorkerPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Voices preallocated when there is no polyphony limit
+const DEFAULT_VOICE_CAPACITY: usize = 32;
+
+/// Most voices preallocated however high the polyphony limit; voices beyond
+/// this are allocated as they start
+const MAX_VOICE_CAPACITY: usize = 256;
+
+/// Upper bound on distinct notes and controllers (sizes the lookup tables)
+const KEY_CAPACITY: usize = 128;
+
+/// Fewest voices worth handing to a render thread
+const MIN_VOICES_PER_JOB: usize = 4;
+
+/// How to choose the voice to steal when the polyphony limit is reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StealPolicy {
+    /// The voice that started first
+    Oldest,
+    /// The voice with the lowest current output level
+    Quietest,
+    /// The oldest releasing voice, or the oldest voice if none is releasing
+    ReleasingFirst,
+    /// A voice playing the same note, or the oldest voice if there is none
+    SameNote,
+}
+
+impl std::str::FromStr for StealPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "oldest" => Ok(StealPolicy::Oldest),
+            "quietest" => Ok(StealPolicy::Quietest),
+            "releasing" | "releasing-first" => Ok(StealPolicy::ReleasingFirst),
+            "same" | "same-note" => Ok(StealPolicy::SameNote),
+            _ => Err(format!("Invalid steal policy: {}", s)),
+        }
+    }
+}
+
+/// What a key-down does when its note is already sounding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetriggerPolicy {
+    /// Keep the sounding voice as it is
+    Ignore,
+    /// Restart the envelopes of the existing voice (also when releasing)
+    Retrigger,
+    /// Start an additional voice; each key-up releases the oldest one
+    Layer,
+}
+
+impl std::str::FromStr for RetriggerPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(RetriggerPolicy::Ignore),
+            "retrigger" => Ok(RetriggerPolicy::Retrigger),
+            "layer" => Ok(RetriggerPolicy::Layer),
+            _ => Err(format!("Invalid retrigger policy: {}", s)),
+        }
+    }
+}
+
+/// Whether notes get their own voices or share one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceMode {
+    /// One voice per note
+    Poly,
+    /// A single voice follows the held keys
+    Mono,
+}
+
+/// Which held note sounds in mono mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotePriority {
+    /// The most recently pressed note
+    Last,
+    /// The lowest held note
+    Low,
+    /// The highest held note
+    High,
+}
+
+impl std::str::FromStr for NotePriority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "last" => Ok(NotePriority::Last),
+            "low" => Ok(NotePriority::Low),
+            "high" => Ok(NotePriority::High),
+            _ => Err(format!("Invalid note priority: {}", s)),
+        }
+    }
+}
+
+/// A voice parameter driven by a controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlTarget {
+    /// Multiplies the FM modulation depth
+    ModDepth,
+    /// Multiplies the output level
+    Amplitude,
+}
+
+/// Maps a controller to a voice parameter
+///
+/// The controller value (0.0 to 1.0) is mapped linearly onto a multiplier
+/// between `min` and `max`. A controller that has not been received yet
+/// leaves the parameter unchanged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ControllerMapping {
+    /// Controller number (0-127)
+    pub controller: u8,
+    /// Parameter the controller drives
+    pub target: ControlTarget,
+    /// Multiplier at controller value 0.0
+    pub min: f32,
+    /// Multiplier at controller value 1.0
+    pub max: f32,
+}
+
+impl ControllerMapping {
+    pub fn new(controller: u8, target: ControlTarget, min: f32, max: f32) -> Self {
+        Self {
+            controller,
+            target,
+            min,
+            max,
+        }
+    }
+
+    /// Multiplier for a controller value
+    fn scale(&self, value: f32) -> f32 {
+        self.min + (self.max - self.min) * value
+    }
+}
+
+/// A sample played for a range of keys and velocities
+#[derive(Debug, Clone)]
+pub struct SampleZone {
+    pub sample: Arc<Sample>,
+    /// Lowest key of the zone
+    pub low_key: Note,
+    /// Highest key of the zone (inclusive)
+    pub high_key: Note,
+    /// Lowest key-down velocity of the zone
+    pub min_velocity: f32,
+    /// Highest key-down velocity of the zone (inclusive)
+    pub max_velocity: f32,
+}
+
+impl SampleZone {
+    /// Zone covering keys `low_key..=high_key` at every velocity
+    pub fn new(sample: Arc<Sample>, low_key: Note, high_key: Note) -> Self {
+        Self {
+            sample,
+            low_key,
+            high_key,
+            min_velocity: 0.0,
+            max_velocity: 1.0,
+        }
+    }
+
+    /// Restrict the zone to velocities `min..=max`
+    pub fn with_velocity(mut self, min: f32, max: f32) -> Self {
+        self.min_velocity = min;
+        self.max_velocity = max;
+        self
+    }
+
+    /// Whether a key-down falls in this zone
+    fn contains(&self, note: &Note, velocity: f32) -> bool {
+        let key = note.semitone_index();
+        (self.low_key.semitone_index()..=self.high_key.semitone_index()).contains(&key)
+            && (self.min_velocity..=self.max_velocity).contains(&velocity)
+    }
+}
+
+/// Samples making up an instrument, chosen by key and velocity
+///
+/// Zones may overlap; the first zone covering a key-down is played.
+#[derive(Debug, Clone, Default)]
+pub struct SampleMap {
+    pub zones: Vec<SampleZone>,
+}
+
+impl SampleMap {
+    /// Add a zone after the existing ones
+    pub fn with_zone(mut self, zone: SampleZone) -> Self {
+        self.zones.push(zone);
+        self
+    }
+
+    /// Check if there are no zones
+    pub fn is_empty(&self) -> bool {
+        self.zones.is_empty()
+    }
+
+    /// The zone to play for a key-down, if any
+    pub fn zone(&self, note: &Note, velocity: f32) -> Option<&SampleZone> {
+        self.zones.iter().find(|z| z.contains(note, velocity))
+    }
+}
+
+/// Diagnostic counters for a voice manager
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VoiceStats {
+    /// Total number of voices stolen to stay within the polyphony limit
+    pub voices_stolen: usize,
+    /// Highest number of simultaneous voices (including fading ones)
+    pub peak_voices: usize,
+}
+
+impl VoiceStats {
+    /// Combine counters from several voice managers
+    pub fn merge(&self, other: &VoiceStats) -> VoiceStats {
+        VoiceStats {
+            voices_stolen: self.voices_stolen + other.voices_stolen,
+            peak_voices: self.peak_voices + other.peak_voices,
+        }
+    }
+}
 
 /// Configuration for all voices (common settings)
 #[derive(Debug, Clone)]
@@ -21,6 +294,33 @@ pub struct VoiceConfig {
     pub sustain_level: f32,
     /// ADSR release duration in samples
     pub release_samples: usize,
+    /// Maximum number of sounding voices (`None` = unlimited)
+    pub max_voices: Option<usize>,
+    /// Which voice to steal when `max_voices` is reached
+    pub steal_policy: StealPolicy,
+    /// Fade-out length for stolen voices in samples
+    pub steal_fade_samples: usize,
+    /// What a key-down does for a note that is already sounding
+    pub retrigger_policy: RetriggerPolicy,
+    /// Polyphonic or monophonic playing
+    pub mode: VoiceMode,
+    /// Which held note sounds in mono mode
+    pub note_priority: NotePriority,
+    /// In mono mode, change pitch without restarting the envelopes while a
+    /// key is still held
+    pub legato: bool,
+    /// Portamento time between notes in mono mode, in samples (0 = off)
+    pub glide_samples: usize,
+    /// Shape of the portamento slide
+    pub glide_curve: GlideCurve,
+    /// Pitch bend range in semitones (at bend = ±1.0)
+    pub bend_range: f32,
+    /// Controller to parameter mappings
+    pub controllers: Vec<ControllerMapping>,
+    /// Samples played alongside the FM synthesizer (none when empty)
+    pub samples: SampleMap,
+    /// Output level of the FM synthesizer (0 = samples only)
+    pub fm_level: f32,
 }
 
 impl Default for VoiceConfig {
@@ -39,6 +339,23 @@ impl Default for VoiceConfig {
             decay_samples: 8820,  // 200ms at 44.1kHz
             sustain_level: 0.7,
             release_samples: 13230, // 300ms at 44.1kHz
+            max_voices: None,
+            steal_policy: StealPolicy::Oldest,
+            steal_fade_samples: 220, // 5ms at 44.1kHz
+            retrigger_policy: RetriggerPolicy::Ignore,
+            mode: VoiceMode::Poly,
+            note_priority: NotePriority::Last,
+            legato: false,
+            glide_samples: 0,
+            glide_curve: GlideCurve::Exponential,
+            bend_range: 2.0,
+            controllers: vec![
+                ControllerMapping::new(CC_MOD_WHEEL, ControlTarget::ModDepth, 1.0, 3.0),
+                ControllerMapping::new(CC_VOLUME, ControlTarget::Amplitude, 0.0, 1.0),
+                ControllerMapping::new(CC_EXPRESSION, ControlTarget::Amplitude, 0.0, 1.0),
+            ],
+            samples: SampleMap::default(),
+            fm_level: 1.0,
         }
     }
 }
@@ -47,15 +364,181 @@ impl Default for VoiceConfig {
 struct Voice {
     note: Note,
     synth: FmSynthGenerator,
+    /// Sample layer, if the note falls in a sample zone
+    sampler: Option<SamplerGenerator>,
+    /// Sample layer replaced by a new strike, with the samples left in its
+    /// anti-click fade
+    outgoing_sampler: Option<(SamplerGenerator, usize)>,
+    /// Output level of the FM synthesizer
+    fm_level: f32,
     is_releasing: bool,
+    /// Output gain from the key-down velocity
+    velocity: f32,
+    /// Note-on order (lower is older)
+    started: u64,
+    /// Samples left in the anti-click fade of a stolen voice
+    fade_remaining: Option<usize>,
+    /// Key is up but a pedal keeps the voice sounding
+    sustained: bool,
+    /// Caught by the sostenuto pedal
+    sostenuto: bool,
+    /// Finished sounding in the last frame
+    finished: bool,
+}
+
+impl Voice {
+    /// Start the release phase
+    fn release(&mut self) {
+        self.synth.note_off();
+        if let Some(sampler) = self.sampler.as_mut() {
+            sampler.note_off();
+        }
+        self.is_releasing = true;
+    }
+
+    /// Release on key-up, unless a pedal holds the voice
+    fn key_up(&mut self, sustain_pedal: bool) {
+        if sustain_pedal || self.sostenuto {
+            self.sustained = true;
+        } else {
+            self.release();
+        }
+    }
+
+    /// Swap in the sample layer of a new strike
+    ///
+    /// The old layer fades out over `fade` samples rather than stopping
+    /// dead; one still fading from an earlier strike is cut.
+    fn replace_sampler(&mut self, sampler: Option<SamplerGenerator>, fade: usize) {
+        let old = std::mem::replace(&mut self.sampler, sampler);
+        self.outgoing_sampler = old.filter(|_| fade > 0).map(|old| (old, fade));
+    }
+
+    /// Current output level (louder layer's envelope times velocity)
+    fn level(&self) -> f32 {
+        let sample_level = self.sampler.as_ref().map_or(0.0, |s| s.amplitude());
+        (self.synth.amplitude() * self.fm_level).max(sample_level) * self.velocity
+    }
+
+    /// Render the next `output.len()` samples, before velocity
+    ///
+    /// Marks the voice finished when its synthesizer and sample layer
+    /// complete or its steal fade (`fade_total` samples long) runs out.
+    fn render(&mut self, output: &mut [f32], fade_total: f32) {
+        output.fill(0.0);
+        let mut state = GeneratorState::Complete;
+        if self.fm_level > 0.0 {
+            state = self.synth.process(output);
+            if self.fm_level != 1.0 {
+                block::scale_ramp(output, self.fm_level, 0.0);
+            }
+        }
+        if let Some(sampler) = self.sampler.as_mut() {
+            if sampler.process_add(output) == GeneratorState::Running {
+                state = GeneratorState::Running;
+            }
+        }
+
+        // Fade out a sample layer replaced by a new strike
+        if let Some((outgoing, remaining)) = self.outgoing_sampler.as_mut() {
+            let mut faded = [0.0f32; MAX_FRAME_SIZE];
+            let mut start = 0;
+            while start < output.len() && *remaining > 0 {
+                let len = (*remaining).min(output.len() - start).min(MAX_FRAME_SIZE);
+                let faded = &mut faded[..len];
+                faded.fill(0.0);
+                outgoing.process_add(faded);
+                block::scale_ramp(faded, *remaining as f32 / fade_total, -1.0 / fade_total);
+                block::mix(&mut output[start..start + len], faded, 1.0);
+                *remaining -= len;
+                start += len;
+            }
+            if *remaining == 0 {
+                self.outgoing_sampler = None;
+            } else {
+                state = GeneratorState::Running;
+            }
+        }
+
+        // Fade out stolen voices and drop them once silent
+        if let Some(remaining) = self.fade_remaining.as_mut() {
+            let fading = (*remaining).min(output.len());
+            block::scale_ramp(
+                &mut output[..fading],
+                *remaining as f32 / fade_total,
+                -1.0 / fade_total,
+            );
+            output[fading..].fill(0.0);
+            *remaining -= fading;
+            if *remaining == 0 {
+                state = GeneratorState::Complete;
+            }
+        }
+
+        self.finished = state == GeneratorState::Complete;
+    }
+}
+
+/// A share of the voices rendered on one thread
+#[derive(Default)]
+struct RenderJob {
+    voices: Vec<Voice>,
+    /// Rendered samples, `len` per voice in voice order
+    output: Vec<f32>,
+    len: usize,
+    fade_total: f32,
+}
+
+impl RenderJob {
+    fn render(&mut self) {
+        self.output.resize(self.voices.len() * self.len, 0.0);
+        for (voice, output) in self
+            .voices
+            .iter_mut()
+            .zip(self.output.chunks_exact_mut(self.len))
+        {
+            voice.render(output, self.fade_total);
+        }
+    }
 }
 
 /// Manages polyphonic voices
 pub struct VoiceManager {
     config: VoiceConfig,
     active_voices: Vec<Voice>,
+    /// Finished synthesizers kept for reuse by new voices
+    spare_synths: Vec<FmSynthGenerator>,
+    /// Parameters for the next voice (reused to avoid cloning)
+    synth_params: FmSynthParams,
+    /// Scratch buffer for rendering one voice (MAX_FRAME_SIZE samples)
+    voice_buffer: Vec<f32>,
+    /// Threads rendering voices in parallel (see `with_render_threads`)
+    render_pool: Option<WorkerPool<RenderJob>>,
+    /// One job per render thread, reused every frame
+    render_jobs: Vec<RenderJob>,
     base_frequency: f32,
+    /// Tuning used instead of 12-TET from `base_frequency`
+    tuning: Option<Tuning>,
     sample_rate: u32,
+    /// Note-on counter used to order voices by age
+    next_start: u64,
+    stats: VoiceStats,
+    /// Keys held in mono mode with their velocities, in press order
+    held_notes: Vec<(Note, f32)>,
+    /// Outstanding key-downs per note
+    key_counts: HashMap<Note, usize>,
+    sustain_pedal: bool,
+    sostenuto_pedal: bool,
+    /// Pitch ratio from the current pitch bend
+    pitch_ratio: f32,
+    /// Last value received per controller
+    controller_values: HashMap<u8, f32>,
+    /// Modulation depth multiplier from controllers
+    mod_depth_scale: f32,
+    /// Output level multiplier from controllers
+    amplitude_scale: f32,
+    /// Output level multiplier reached at the end of the last frame
+    amplitude_gain: f32,
 }
 
 impl VoiceManager {
@@ -66,38 +549,136 @@ impl VoiceManager {
     /// * `base_frequency` - Frequency of 1C in Hz (e.g., 110.0)
     /// * `sample_rate` - Sample rate in Hz
     pub fn new(config: VoiceConfig, base_frequency: f32, sample_rate: u32) -> Self {
-        Self {
+        // Stolen voices fade out alongside the voices replacing them
+        let voice_capacity = config
+            .max_voices
+            .map_or(DEFAULT_VOICE_CAPACITY, |max| max.saturating_mul(2))
+            .min(MAX_VOICE_CAPACITY);
+        let mut manager = Self {
+            active_voices: Vec::with_capacity(voice_capacity),
+            spare_synths: Vec::with_capacity(voice_capacity),
+            synth_params: config.fm_params.clone(),
+            voice_buffer: vec![0.0; MAX_FRAME_SIZE],
+            render_pool: None,
+            render_jobs: Vec::new(),
             config,
-            active_voices: Vec::new(),
             base_frequency,
+            tuning: None,
             sample_rate,
+            next_start: 0,
+            stats: VoiceStats::default(),
+            held_notes: Vec::with_capacity(KEY_CAPACITY),
+            key_counts: HashMap::with_capacity(KEY_CAPACITY),
+            sustain_pedal: false,
+            sostenuto_pedal: false,
+            pitch_ratio: 1.0,
+            controller_values: HashMap::with_capacity(KEY_CAPACITY),
+            mod_depth_scale: 1.0,
+            amplitude_scale: 1.0,
+            amplitude_gain: 1.0,
+        };
+
+        for _ in 0..voice_capacity {
+            let (mod_env, wav_env) = manager.envelopes();
+            let synth = FmSynthGenerator::new(manager.synth_params.clone(), mod_env, wav_env);
+            manager.spare_synths.push(synth);
+        }
+        manager
+    }
+
+    /// Render voices on `threads` threads, including the calling thread
+    ///
+    /// Voices are shared between the threads and their outputs summed in
+    /// voice order, so the result is bit-identical to rendering on one
+    /// thread. Frames with only a few voices stay on the calling thread.
+    /// A `threads` of 0 or 1 renders everything on the calling thread.
+    ///
+    /// # Panics
+    /// Panics if the worker threads cannot be spawned
+    pub fn with_render_threads(mut self, threads: usize) -> Self {
+        if threads > 1 {
+            self.render_pool = Some(WorkerPool::new(threads - 1, RenderJob::render));
+            self.render_jobs = (0..threads).map(|_| RenderJob::default()).collect();
+        } else {
+            self.render_pool = None;
+            self.render_jobs = Vec::new();
         }
+        self
+    }
+
+    /// Play notes through a tuning instead of 12-TET from `base_frequency`
+    ///
+    /// Keys the tuning leaves unmapped are silent.
+    pub fn with_tuning(mut self, tuning: Tuning) -> Self {
+        self.tuning = Some(tuning);
+        self
     }
 
-    /// Calculate frequency for a note
+    /// Calculate frequency for a note, or `None` if the tuning leaves it
+    /// unmapped
     ///
-    /// Formula: f = base_freq * 2^((octave-1) + semitone/12)
-    fn note_frequency(&self, note: &Note) -> f32 {
+    /// Without a tuning: f = base_freq * 2^((octave-1) + semitone/12)
+    pub fn note_frequency(&self, note: &Note) -> Option<f32> {
+        if let Some(tuning) = &self.tuning {
+            return tuning.note_frequency(note).map(|f| f as f32);
+        }
         let octave_offset = (note.octave as f32 - 1.0) * 12.0;
         let semitone_offset = note.pitch_class.semitone() as f32;
         let total_semitones = octave_offset + semitone_offset;
-        self.base_frequency * 2f32.powf(total_semitones / 12.0)
+        Some(self.base_frequency * 2f32.powf(total_semitones / 12.0))
     }
 
     /// Calculate phase increment per sample for a frequency
+    ///
+    /// Frequencies above Nyquist are clamped to [`MAX_PHASE_PER_SAMPLE`],
+    /// the same in every voice mode.
     fn phase_per_sample(&self, frequency: f32) -> f32 {
-        2.0 * std::f32::consts::PI * frequency / self.sample_rate as f32
+        (2.0 * std::f32::consts::PI * frequency / self.sample_rate as f32).min(MAX_PHASE_PER_SAMPLE)
     }
 
-    /// Create a new FM synthesizer for a note
-    fn create_synth(&self, note: &Note) -> FmSynthGenerator {
-        let frequency = self.note_frequency(note);
+    /// Set up an FM synthesizer for a note, reusing a finished one if any
+    fn create_synth(&mut self, note: &Note) -> FmSynthGenerator {
+        let frequency = self
+            .note_frequency(note)
+            .expect("unmapped notes never start voices");
         let phase_per_sample = self.phase_per_sample(frequency);
 
-        // Clone base params and set phase_per_sample for this note's frequency
-        let mut fm_params = self.config.fm_params.clone();
-        fm_params.phase_per_sample = phase_per_sample;
+        // Base params with phase_per_sample for this note's frequency
+        self.synth_params.phase_per_sample = phase_per_sample;
+        self.synth_params.mod_depth = self.config.fm_params.mod_depth * self.mod_depth_scale;
+
+        let (mod_env, wav_env) = self.envelopes();
+        let mut synth = match self.spare_synths.pop() {
+            Some(mut synth) => {
+                synth.restart(&self.synth_params, mod_env, wav_env);
+                synth
+            }
+            None => FmSynthGenerator::new(self.synth_params.clone(), mod_env, wav_env),
+        };
+        synth.set_pitch_ratio(self.pitch_ratio);
+        synth
+    }
+
+    /// Set up the sample layer for a key-down, if a zone covers it
+    ///
+    /// Sample playback shares the voice's release time.
+    fn create_sampler(&self, note: &Note, velocity: f32) -> Option<SamplerGenerator> {
+        let zone = self.config.samples.zone(note, velocity.clamp(0.0, 1.0))?;
+        let frequency = self.note_frequency(note)?;
+        let rate = zone
+            .sample
+            .playback_rate(frequency as f64, self.sample_rate);
+        let mut sampler = SamplerGenerator::new(
+            Arc::clone(&zone.sample),
+            rate as f32,
+            self.config.release_samples,
+        );
+        sampler.set_pitch_ratio(self.pitch_ratio);
+        Some(sampler)
+    }
 
+    /// Modulation and waveform envelopes for a new voice
+    fn envelopes(&self) -> (AdsrGenerator, AdsrGenerator) {
         // Create ADSR envelopes - both with same settings
         // Use a large but not max value for sustain to avoid overflow
         let max_sustain = self.sample_rate as usize * 60 * 60; // 1 hour max
@@ -119,96 +700,486 @@ impl VoiceManager {
             self.config.release_samples,
         );
 
-        FmSynthGenerator::new(fm_params, mod_env, wav_env)
+        (mod_env, wav_env)
     }
 
-    /// Handle a note event (key down or key up)
+    /// Handle a note event (key down or key up) at full velocity
     pub fn handle_event(&mut self, note: &Note, direction: KeyDirection) {
-        match direction {
+        self.handle_event_with_velocity(note, direction, 1.0);
+    }
+
+    /// Handle a note event (key down or key up)
+    ///
+    /// `velocity` (0.0 to 1.0) scales the output of a newly started voice.
+    /// A key-up without a matching key-down is ignored.
+    pub fn handle_event_with_velocity(
+        &mut self,
+        note: &Note,
+        direction: KeyDirection,
+        velocity: f32,
+    ) {
+        // Keys without a pitch in the tuning are ignored
+        if self.note_frequency(note).is_none() {
+            return;
+        }
+
+        // Pair key-downs with key-ups
+        let count = self.key_counts.entry(*note).or_insert(0);
+        let still_held = match direction {
             KeyDirection::Down => {
-                // Check if note is already active (ignore duplicates)
-                if self
-                    .active_voices
-                    .iter()
-                    .any(|v| v.note == *note && !v.is_releasing)
-                {
+                *count += 1;
+                true
+            }
+            KeyDirection::Up => {
+                if *count == 0 {
                     return;
                 }
+                *count -= 1;
+                *count > 0
+            }
+        };
+        if !still_held {
+            self.key_counts.remove(note);
+        }
 
-                // Create new voice
-                let synth = self.create_synth(note);
-                let voice = Voice {
-                    note: *note,
-                    synth,
-                    is_releasing: false,
+        if self.config.mode == VoiceMode::Mono {
+            // The held-note stack tracks keys, not presses
+            if direction == KeyDirection::Up && still_held {
+                return;
+            }
+            self.handle_mono_event(note, direction, velocity);
+            return;
+        }
+
+        match direction {
+            KeyDirection::Down => {
+                // Handle a note that is already active
+                let restrike = match self.config.retrigger_policy {
+                    RetriggerPolicy::Ignore => {
+                        let held = self
+                            .active_voices
+                            .iter()
+                            .position(|v| v.note == *note && !v.is_releasing);
+                        match held {
+                            // The key is still down
+                            Some(i) if !self.active_voices[i].sustained => return,
+                            // Only a pedal holds it: strike it again
+                            Some(i) => Some(i),
+                            None => None,
+                        }
+                    }
+                    RetriggerPolicy::Retrigger => self
+                        .active_voices
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, v)| v.note == *note && v.fade_remaining.is_none())
+                        .max_by_key(|(_, v)| v.started)
+                        .map(|(i, _)| i),
+                    RetriggerPolicy::Layer => None,
                 };
-                self.active_voices.push(voice);
+                if let Some(i) = restrike {
+                    self.restrike(i, note, velocity);
+                    return;
+                }
+
+                // Make room within the polyphony limit
+                if let Some(max_voices) = self.config.max_voices {
+                    while self.sounding_voice_count() >= max_voices.max(1) {
+                        self.steal_voice(note);
+                    }
+                }
+
+                self.start_voice(note, velocity);
             }
             KeyDirection::Up => {
-                // Find the active voice for this note and trigger release
-                if let Some(voice) = self
+                let sustain_pedal = self.sustain_pedal;
+                let held = self
                     .active_voices
                     .iter_mut()
-                    .find(|v| v.note == *note && !v.is_releasing)
-                {
-                    voice.synth.note_off();
-                    voice.is_releasing = true;
+                    .filter(|v| v.note == *note && !v.is_releasing && !v.sustained);
+
+                if self.config.retrigger_policy == RetriggerPolicy::Layer {
+                    // Each key-up releases the oldest layered voice
+                    if let Some(voice) = held.min_by_key(|v| v.started) {
+                        voice.key_up(sustain_pedal);
+                    }
+                } else if !still_held {
+                    for voice in held {
+                        voice.key_up(sustain_pedal);
+                    }
                 }
             }
         }
     }
 
-    /// Process one frame and mix all active voices
+    /// Strike a sounding voice again, restarting its envelopes and sample
+    fn restrike(&mut self, index: usize, note: &Note, velocity: f32) {
+        let sampler = self.create_sampler(note, velocity);
+        let voice = &mut self.active_voices[index];
+        voice.synth.retrigger();
+        voice.replace_sampler(sampler, self.config.steal_fade_samples);
+        voice.is_releasing = false;
+        voice.sustained = false;
+        voice.velocity = velocity.clamp(0.0, 1.0);
+    }
+
+    /// Create a new voice for a note
+    fn start_voice(&mut self, note: &Note, velocity: f32) {
+        let synth = self.create_synth(note);
+        let sampler = self.create_sampler(note, velocity);
+        let voice = Voice {
+            note: *note,
+            synth,
+            sampler,
+            outgoing_sampler: None,
+            fm_level: self.config.fm_level,
+            is_releasing: false,
+            velocity: velocity.clamp(0.0, 1.0),
+            started: self.next_start,
+            fade_remaining: None,
+            sustained: false,
+            sostenuto: false,
+            finished: false,
+        };
+        self.next_start += 1;
+        self.active_voices.push(voice);
+        self.stats.peak_voices = self.stats.peak_voices.max(self.active_voices.len());
+    }
+
+    /// Handle a note event in mono mode
     ///
-    /// Returns the mixed samples for this frame and removes completed voices.
-    pub fn process_frame(&mut self, buffer: &mut [f32]) {
-        // Clear buffer
-        for sample in buffer.iter_mut() {
-            *sample = 0.0;
+    /// The held-note stack decides which note sounds. The single voice is
+    /// reused (gliding to the new pitch) as long as it has not finished,
+    /// even while it is releasing.
+    fn handle_mono_event(&mut self, note: &Note, direction: KeyDirection, velocity: f32) {
+        match direction {
+            KeyDirection::Down => {
+                self.held_notes.retain(|(n, _)| n != note);
+                self.held_notes.push((*note, velocity.clamp(0.0, 1.0)));
+            }
+            KeyDirection::Up => {
+                if !self.held_notes.iter().any(|(n, _)| n == note) {
+                    return;
+                }
+                self.held_notes.retain(|(n, _)| n != note);
+            }
         }
 
-        if self.active_voices.is_empty() {
+        let voice_index = self
+            .active_voices
+            .iter()
+            .position(|v| v.fade_remaining.is_none());
+
+        let Some((target, velocity)) = self.priority_note() else {
+            // Last key released
+            let sustain_pedal = self.sustain_pedal;
+            if let Some(voice) = voice_index.map(|i| &mut self.active_voices[i]) {
+                if !voice.is_releasing {
+                    voice.key_up(sustain_pedal);
+                }
+            }
+            return;
+        };
+
+        let Some(i) = voice_index else {
+            self.start_voice(&target, velocity);
+            return;
+        };
+
+        let frequency = self
+            .note_frequency(&target)
+            .expect("unmapped notes are never held");
+        let phase_per_sample = self.phase_per_sample(frequency);
+        let glide_samples = self.config.glide_samples;
+        let glide_curve = self.config.glide_curve;
+        let legato = self.config.legato;
+
+        let voice = &mut self.active_voices[i];
+        voice.sustained = false;
+        if voice.note == target && !voice.is_releasing {
             return;
         }
+        // Legato only applies while a key is still holding the voice
+        let restart = !legato || voice.is_releasing;
+        let previous = voice.note;
+        if restart {
+            // A new sample for the new note, sliding from the old pitch
+            let previous_frequency = self.note_frequency(&previous);
+            let mut sampler = self.create_sampler(&target, velocity);
+            if let (Some(sampler), Some(from)) = (sampler.as_mut(), previous_frequency) {
+                let rate = sampler.rate();
+                let from_rate = sampler
+                    .sample()
+                    .playback_rate(from as f64, self.sample_rate);
+                sampler.set_rate(from_rate as f32);
+                sampler.glide_to(rate, glide_samples, glide_curve);
+            }
+            let fade = self.config.steal_fade_samples;
+            self.active_voices[i].replace_sampler(sampler, fade);
+        }
+
+        let voice = &mut self.active_voices[i];
+        if restart {
+            voice.synth.retrigger();
+            voice.velocity = velocity;
+        } else if let Some(sampler) = voice.sampler.as_mut() {
+            let rate = sampler
+                .sample()
+                .playback_rate(frequency as f64, self.sample_rate);
+            sampler.glide_to(rate as f32, glide_samples, glide_curve);
+        }
+        voice.is_releasing = false;
+        voice.note = target;
+        voice
+            .synth
+            .glide_to(phase_per_sample, glide_samples, glide_curve);
+    }
+
+    /// The held note that should sound in mono mode
+    fn priority_note(&self) -> Option<(Note, f32)> {
+        let held = self.held_notes.iter().copied();
+        match self.config.note_priority {
+            NotePriority::Last => self.held_notes.last().copied(),
+            NotePriority::Low => held.min_by_key(|(n, _)| n.semitone_index()),
+            NotePriority::High => held.max_by_key(|(n, _)| n.semitone_index()),
+        }
+    }
 
-        // Temporary buffer for individual voice processing
-        let mut voice_buffer = vec![0.0f32; buffer.len()];
+    /// Handle a pedal or controller event
+    ///
+    /// Controllers 64 and 66 act as the sustain and sostenuto pedals
+    /// (down at 0.5 and above).
+    pub fn handle_control(&mut self, control: &Control) {
+        let pedal_direction = |value: f32| {
+            if value >= 0.5 {
+                KeyDirection::Down
+            } else {
+                KeyDirection::Up
+            }
+        };
 
-        // Track which voices completed this frame
-        let mut completed_indices: Vec<usize> = Vec::new();
+        match *control {
+            Control::Pedal(pedal, direction) => self.handle_pedal(pedal, direction),
+            Control::PitchBend(value) => {
+                let semitones = value.clamp(-1.0, 1.0) * self.config.bend_range;
+                self.pitch_ratio = 2f32.powf(semitones / 12.0);
+                for voice in self.active_voices.iter_mut() {
+                    voice.synth.set_pitch_ratio(self.pitch_ratio);
+                    if let Some(sampler) = voice.sampler.as_mut() {
+                        sampler.set_pitch_ratio(self.pitch_ratio);
+                    }
+                }
+            }
+            Control::ControlChange(CC_SUSTAIN, value) => {
+                self.handle_pedal(Pedal::Sustain, pedal_direction(value));
+            }
+            Control::ControlChange(CC_SOSTENUTO, value) => {
+                self.handle_pedal(Pedal::Sostenuto, pedal_direction(value));
+            }
+            Control::ControlChange(number, value) => {
+                self.controller_values.insert(number, value.clamp(0.0, 1.0));
+                self.mod_depth_scale = self.controller_scale(ControlTarget::ModDepth);
+                self.amplitude_scale = self.controller_scale(ControlTarget::Amplitude);
 
-        // Process each voice and accumulate
-        for (i, voice) in self.active_voices.iter_mut().enumerate() {
-            // Clear voice buffer
-            for sample in voice_buffer.iter_mut() {
-                *sample = 0.0;
+                let mod_depth = self.config.fm_params.mod_depth * self.mod_depth_scale;
+                for voice in self.active_voices.iter_mut() {
+                    voice.synth.set_mod_depth(mod_depth);
+                }
             }
+        }
+    }
+
+    /// Combined multiplier of every mapped controller driving a target
+    fn controller_scale(&self, target: ControlTarget) -> f32 {
+        self.config
+            .controllers
+            .iter()
+            .filter(|m| m.target == target)
+            .filter_map(|m| {
+                self.controller_values
+                    .get(&m.controller)
+                    .map(|&v| m.scale(v))
+            })
+            .product()
+    }
 
-            // Process voice
-            let state = voice.synth.process(&mut voice_buffer);
+    /// Handle a pedal press or lift
+    fn handle_pedal(&mut self, pedal: Pedal, direction: KeyDirection) {
+        match (pedal, direction) {
+            (Pedal::Sustain, direction) => {
+                self.sustain_pedal = direction == KeyDirection::Down;
+            }
+            (Pedal::Sostenuto, KeyDirection::Down) => {
+                // Catch the notes whose keys are down right now
+                if !self.sostenuto_pedal {
+                    for voice in self.active_voices.iter_mut() {
+                        if !voice.is_releasing && !voice.sustained {
+                            voice.sostenuto = true;
+                        }
+                    }
+                }
+                self.sostenuto_pedal = true;
+            }
+            (Pedal::Sostenuto, KeyDirection::Up) => {
+                self.sostenuto_pedal = false;
+                for voice in self.active_voices.iter_mut() {
+                    voice.sostenuto = false;
+                }
+            }
+        }
 
-            // Add to mix
-            for (j, sample) in buffer.iter_mut().enumerate() {
-                *sample += voice_buffer[j];
+        // Release voices no pedal holds any more
+        for voice in self.active_voices.iter_mut() {
+            if voice.sustained && !self.sustain_pedal && !voice.sostenuto {
+                voice.sustained = false;
+                voice.release();
             }
+        }
+    }
+
+    /// Number of voices not already fading out after being stolen
+    fn sounding_voice_count(&self) -> usize {
+        self.active_voices
+            .iter()
+            .filter(|v| v.fade_remaining.is_none())
+            .count()
+    }
+
+    /// Pick a voice to steal for `note` and start fading it out
+    fn steal_voice(&mut self, note: &Note) {
+        let candidates = self
+            .active_voices
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.fade_remaining.is_none());
+        let oldest = |voices: &mut dyn Iterator<Item = (usize, &Voice)>| {
+            voices.min_by_key(|(_, v)| v.started).map(|(i, _)| i)
+        };
 
-            // Check if voice completed
-            if state == GeneratorState::Complete {
-                completed_indices.push(i);
+        let victim = match self.config.steal_policy {
+            StealPolicy::Oldest => oldest(&mut candidates.clone()),
+            StealPolicy::Quietest => candidates
+                .clone()
+                .min_by(|(_, a), (_, b)| {
+                    a.level()
+                        .total_cmp(&b.level())
+                        .then(a.started.cmp(&b.started))
+                })
+                .map(|(i, _)| i),
+            StealPolicy::ReleasingFirst => {
+                oldest(&mut candidates.clone().filter(|(_, v)| v.is_releasing))
+                    .or_else(|| oldest(&mut candidates.clone()))
+            }
+            StealPolicy::SameNote => {
+                oldest(&mut candidates.clone().filter(|(_, v)| v.note == *note))
+                    .or_else(|| oldest(&mut candidates.clone()))
             }
+        };
+
+        if let Some(i) = victim {
+            let voice = &mut self.active_voices[i];
+            voice.is_releasing = true;
+            voice.fade_remaining = Some(self.config.steal_fade_samples);
+            self.stats.voices_stolen += 1;
+        }
+    }
+
+    /// Process one frame and mix all active voices
+    ///
+    /// Returns the mixed samples for this frame and removes completed voices.
+    /// Frames longer than [`MAX_FRAME_SIZE`] are rendered in pieces.
+    pub fn process_frame(&mut self, buffer: &mut [f32]) {
+        for chunk in buffer.chunks_mut(MAX_FRAME_SIZE) {
+            self.process_chunk(chunk);
+        }
+    }
+
+    /// Render at most MAX_FRAME_SIZE samples
+    fn process_chunk(&mut self, buffer: &mut [f32]) {
+        buffer.fill(0.0);
+
+        if self.active_voices.is_empty() {
+            self.amplitude_gain = self.amplitude_scale;
+            return;
         }
 
-        // Remove completed voices (in reverse order to maintain indices)
-        for &i in completed_indices.iter().rev() {
-            self.active_voices.remove(i);
+        let fade_total = self.config.steal_fade_samples.max(1) as f32;
+        let jobs = self.parallel_jobs();
+        if jobs > 1 {
+            self.render_parallel(buffer, jobs, fade_total);
+        } else {
+            let voice_buffer = &mut self.voice_buffer[..buffer.len()];
+            for voice in self.active_voices.iter_mut() {
+                voice.render(voice_buffer, fade_total);
+                block::mix(buffer, voice_buffer, voice.velocity);
+            }
         }
 
+        self.remove_finished_voices();
+
+        // Ramp the controller level across the frame to avoid zipper noise
+        let gain = self.amplitude_gain;
+        let step = (self.amplitude_scale - gain) / buffer.len() as f32;
+        block::scale_ramp(buffer, gain + step, step);
+        self.amplitude_gain = self.amplitude_scale;
+
         // Clip to prevent overflow (soft clip)
         for sample in buffer.iter_mut() {
             *sample = soft_clip(*sample);
         }
     }
 
+    /// Number of threads to share the voices between this frame
+    fn parallel_jobs(&self) -> usize {
+        match &self.render_pool {
+            Some(pool) => (pool.workers() + 1).min(self.active_voices.len() / MIN_VOICES_PER_JOB),
+            None => 0,
+        }
+    }
+
+    /// Render the voices split into `jobs` consecutive groups, one per thread
+    ///
+    /// Outputs are mixed in voice order afterwards, so the sum is the same
+    /// as rendering on one thread.
+    fn render_parallel(&mut self, buffer: &mut [f32], jobs: usize, fade_total: f32) {
+        let per_job = self.active_voices.len().div_ceil(jobs);
+        for job in self.render_jobs[..jobs].iter_mut() {
+            let count = per_job.min(self.active_voices.len());
+            job.voices.extend(self.active_voices.drain(..count));
+            job.len = buffer.len();
+            job.fade_total = fade_total;
+        }
+
+        if let Some(pool) = self.render_pool.as_mut() {
+            pool.run(&mut self.render_jobs[..jobs]);
+        }
+
+        for job in self.render_jobs[..jobs].iter_mut() {
+            for (voice, output) in job.voices.iter().zip(job.output.chunks_exact(job.len)) {
+                block::mix(buffer, output, voice.velocity);
+            }
+            self.active_voices.append(&mut job.voices);
+        }
+    }
+
+    /// Drop finished voices, keeping the others in order, and keep their
+    /// synthesizers for reuse
+    fn remove_finished_voices(&mut self) {
+        let mut kept = 0;
+        for i in 0..self.active_voices.len() {
+            if !self.active_voices[i].finished {
+                self.active_voices.swap(kept, i);
+                kept += 1;
+            }
+        }
+        for voice in self.active_voices.drain(kept..) {
+            // Beyond the preallocated pool, let the synthesizer go
+            if self.spare_synths.len() < self.spare_synths.capacity() {
+                self.spare_synths.push(voice.synth);
+            }
+        }
+    }
+
     /// Check if there are any active voices
     pub fn has_active_voices(&self) -> bool {
         !self.active_voices.is_empty()
@@ -219,19 +1190,34 @@ impl VoiceManager {
         self.active_voices.len()
     }
 
+    /// Get diagnostic counters (stolen voices, peak polyphony)
+    pub fn stats(&self) -> VoiceStats {
+        self.stats
+    }
+
     /// Trigger release on all active voices (for early termination)
     pub fn all_notes_off(&mut self) {
+        self.held_notes.clear();
+        self.key_counts.clear();
+        self.sustain_pedal = false;
+        self.sostenuto_pedal = false;
         for voice in self.active_voices.iter_mut() {
             if !voice.is_releasing {
-                voice.synth.note_off();
-                voice.is_releasing = true;
+                voice.release();
             }
         }
     }
 
     /// Clear all voices immediately
     pub fn clear(&mut self) {
-        self.active_voices.clear();
+        for voice in self.active_voices.iter_mut() {
+            voice.finished = true;
+        }
+        self.remove_finished_voices();
+        self.held_notes.clear();
+        self.key_counts.clear();
+        self.sustain_pedal = false;
+        self.sostenuto_pedal = false;
     }
 }
 
@@ -263,28 +1249,28 @@ mod tests {
             octave: 1,
             pitch_class: PitchClass::C,
         };
-        assert!((mgr.note_frequency(&c1) - 110.0).abs() < 0.01);
+        assert!((mgr.note_frequency(&c1).unwrap() - 110.0).abs() < 0.01);
 
         // 2C should be 2x base (one octave up)
         let c2 = Note {
             octave: 2,
             pitch_class: PitchClass::C,
         };
-        assert!((mgr.note_frequency(&c2) - 220.0).abs() < 0.01);
+        assert!((mgr.note_frequency(&c2).unwrap() - 220.0).abs() < 0.01);
 
         // 4C should be 8x base (three octaves up from 1C)
         let c4 = Note {
             octave: 4,
             pitch_class: PitchClass::C,
         };
-        assert!((mgr.note_frequency(&c4) - 880.0).abs() < 0.1);
+        assert!((mgr.note_frequency(&c4).unwrap() - 880.0).abs() < 0.1);
 
         // 4A (9 semitones above 4C): 880 * 2^(9/12) ≈ 1480 Hz
         let a4 = Note {
             octave: 4,
             pitch_class: PitchClass::A,
         };
-        let freq = mgr.note_frequency(&a4);
+        let freq = mgr.note_frequency(&a4).unwrap();
         let expected = 880.0 * 2f32.powf(9.0 / 12.0);
         assert!(
             (freq - expected).abs() < 1.0,
@@ -294,6 +1280,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_tuning_and_unmapped_keys() {
+        use crate::pipeline::tuning::{KeyboardMapping, Scale};
+
+        // 19-EDO with 1C (MIDI 24) at 100 Hz: 19 keys up is one octave
+        let edo19 = Tuning::from_scale(Scale::equal(19, 1200.0), 24, 100.0);
+        let mgr = create_test_manager().with_tuning(edo19);
+        let c1 = Note {
+            octave: 1,
+            pitch_class: PitchClass::C,
+        };
+        assert!((mgr.note_frequency(&c1).unwrap() - 100.0).abs() < 0.01);
+
+        // Only white keys mapped: black keys start no voice
+        let mut mapping = KeyboardMapping::linear(24, 24, 110.0);
+        mapping.degrees = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]
+            .iter()
+            .map(|&d| (![1, 3, 6, 8, 10].contains(&d)).then_some(d))
+            .collect();
+        let tuning = Tuning::new(Scale::equal_temperament(), mapping).unwrap();
+        let mut mgr = create_test_manager().with_tuning(tuning);
+        let cs1 = Note {
+            octave: 1,
+            pitch_class: PitchClass::CSharp,
+        };
+        mgr.handle_event(&cs1, KeyDirection::Down);
+        assert_eq!(mgr.voice_count(), 0);
+        mgr.handle_event(&cs1, KeyDirection::Up);
+        mgr.handle_event(&c1, KeyDirection::Down);
+        assert_eq!(mgr.voice_count(), 1);
+    }
+
     #[test]
     fn test_duplicate_note_ignored() {
         let mut mgr = create_test_manager();
@@ -338,6 +1356,566 @@ mod tests {
         assert!(!mgr.has_active_voices());
     }
 
+    #[test]
+    fn test_velocity_scales_output() {
+        let note = Note {
+            octave: 4,
+            pitch_class: PitchClass::C,
+        };
+        let mut loud = create_test_manager();
+        let mut soft = create_test_manager();
+        loud.handle_event(&note, KeyDirection::Down);
+        soft.handle_event_with_velocity(&note, KeyDirection::Down, 0.25);
+
+        let mut loud_buffer = vec![0.0f32; 64];
+        let mut soft_buffer = vec![0.0f32; 64];
+        for _ in 0..20 {
+            loud.process_frame(&mut loud_buffer);
+            soft.process_frame(&mut soft_buffer);
+        }
+
+        for (l, s) in loud_buffer.iter().zip(soft_buffer.iter()) {
+            assert!((l * 0.25 - s).abs() < 1e-6);
+        }
+    }
+
+    fn note(octave: u8, pitch_class: PitchClass) -> Note {
+        Note {
+            octave,
+            pitch_class,
+        }
+    }
+
+    fn limited_manager(max_voices: usize, steal_policy: StealPolicy) -> VoiceManager {
+        let config = VoiceConfig {
+            max_voices: Some(max_voices),
+            steal_policy,
+            steal_fade_samples: 64,
+            ..Default::default()
+        };
+        VoiceManager::new(config, 110.0, 44100)
+    }
+
+    /// Notes of voices that are not being stolen
+    fn sounding_notes(mgr: &VoiceManager) -> Vec<Note> {
+        mgr.active_voices
+            .iter()
+            .filter(|v| v.fade_remaining.is_none())
+            .map(|v| v.note)
+            .collect()
+    }
+
+    fn run_frames(mgr: &mut VoiceManager, frames: usize) {
+        let mut buffer = vec![0.0f32; 64];
+        for _ in 0..frames {
+            mgr.process_frame(&mut buffer);
+        }
+    }
+
+    #[test]
+    fn test_polyphony_limit_steals_oldest() {
+        let mut mgr = limited_manager(2, StealPolicy::Oldest);
+        let (c, e, g) = (
+            note(4, PitchClass::C),
+            note(4, PitchClass::E),
+            note(4, PitchClass::G),
+        );
+
+        mgr.handle_event(&c, KeyDirection::Down);
+        mgr.handle_event(&e, KeyDirection::Down);
+        mgr.handle_event(&g, KeyDirection::Down);
+
+        assert_eq!(sounding_notes(&mgr), vec![e, g]);
+        assert_eq!(mgr.stats().voices_stolen, 1);
+
+        // The stolen voice fades out within one 64-sample frame
+        assert_eq!(mgr.voice_count(), 3);
+        run_frames(&mut mgr, 1);
+        assert_eq!(mgr.voice_count(), 2);
+        assert_eq!(mgr.stats().peak_voices, 3);
+    }
+
+    #[test]
+    fn test_steal_releasing_first() {
+        let mut mgr = limited_manager(2, StealPolicy::ReleasingFirst);
+        let (c, e, g) = (
+            note(4, PitchClass::C),
+            note(4, PitchClass::E),
+            note(4, PitchClass::G),
+        );
+
+        mgr.handle_event(&c, KeyDirection::Down);
+        mgr.handle_event(&e, KeyDirection::Down);
+        mgr.handle_event(&e, KeyDirection::Up);
+        mgr.handle_event(&g, KeyDirection::Down);
+
+        assert_eq!(sounding_notes(&mgr), vec![c, g]);
+    }
+
+    #[test]
+    fn test_steal_same_note() {
+        let mut mgr = limited_manager(2, StealPolicy::SameNote);
+        let (c, e) = (note(4, PitchClass::C), note(4, PitchClass::E));
+
+        mgr.handle_event(&c, KeyDirection::Down);
+        mgr.handle_event(&e, KeyDirection::Down);
+        mgr.handle_event(&e, KeyDirection::Up);
+        mgr.handle_event(&e, KeyDirection::Down);
+
+        // The releasing E is replaced by the new E; C keeps sounding
+        assert_eq!(sounding_notes(&mgr), vec![c, e]);
+        assert!(!mgr.active_voices[2].is_releasing);
+    }
+
+    #[test]
+    fn test_steal_quietest() {
+        let mut mgr = limited_manager(2, StealPolicy::Quietest);
+        let (c, e, g) = (
+            note(4, PitchClass::C),
+            note(4, PitchClass::E),
+            note(4, PitchClass::G),
+        );
+
+        // C is older but louder (further into its attack) than E
+        mgr.handle_event(&c, KeyDirection::Down);
+        run_frames(&mut mgr, 20);
+        mgr.handle_event_with_velocity(&e, KeyDirection::Down, 0.5);
+        run_frames(&mut mgr, 1);
+        mgr.handle_event(&g, KeyDirection::Down);
+
+        assert_eq!(sounding_notes(&mgr), vec![c, g]);
+    }
+
+    #[test]
+    fn test_stolen_voice_fades_without_click() {
+        let mut mgr = limited_manager(1, StealPolicy::Oldest);
+        mgr.handle_event(&note(4, PitchClass::C), KeyDirection::Down);
+        run_frames(&mut mgr, 100);
+
+        mgr.handle_event(&note(4, PitchClass::E), KeyDirection::Down);
+
+        // Render the stolen voice alone: its gain ramps down to zero
+        mgr.active_voices.truncate(1);
+        let mut buffer = vec![0.0f32; 64];
+        mgr.process_frame(&mut buffer);
+        assert!(buffer[0].abs() > buffer[63].abs());
+        assert!(buffer[63].abs() < 0.05);
+        assert!(!mgr.has_active_voices());
+    }
+
+    #[test]
+    fn test_unlimited_by_default() {
+        let mut mgr = create_test_manager();
+        for semitone in 0..24 {
+            mgr.handle_event(
+                &Note::from_semitone_index(48 + semitone).unwrap(),
+                KeyDirection::Down,
+            );
+        }
+        assert_eq!(mgr.voice_count(), 24);
+        assert_eq!(mgr.stats().voices_stolen, 0);
+    }
+
+    fn retrigger_manager(retrigger_policy: RetriggerPolicy) -> VoiceManager {
+        let config = VoiceConfig {
+            retrigger_policy,
+            ..Default::default()
+        };
+        VoiceManager::new(config, 110.0, 44100)
+    }
+
+    fn releasing_flags(mgr: &VoiceManager) -> Vec<bool> {
+        mgr.active_voices.iter().map(|v| v.is_releasing).collect()
+    }
+
+    #[test]
+    fn test_overlapping_notes_are_reference_counted() {
+        let c = note(4, PitchClass::C);
+        for policy in [RetriggerPolicy::Ignore, RetriggerPolicy::Retrigger] {
+            let mut mgr = retrigger_manager(policy);
+            mgr.handle_event(&c, KeyDirection::Down);
+            mgr.handle_event(&c, KeyDirection::Down);
+            assert_eq!(mgr.voice_count(), 1);
+
+            // The first key-up leaves the note sounding
+            mgr.handle_event(&c, KeyDirection::Up);
+            assert_eq!(releasing_flags(&mgr), vec![false]);
+            mgr.handle_event(&c, KeyDirection::Up);
+            assert_eq!(releasing_flags(&mgr), vec![true]);
+
+            // Unpaired key-ups are ignored
+            mgr.handle_event(&c, KeyDirection::Up);
+            mgr.handle_event(&c, KeyDirection::Down);
+            assert_eq!(
+                mgr.active_voices.last().map(|v| v.is_releasing),
+                Some(false)
+            );
+        }
+    }
+
+    #[test]
+    fn test_retrigger_restarts_existing_voice() {
+        let mut mgr = retrigger_manager(RetriggerPolicy::Retrigger);
+        let c = note(4, PitchClass::C);
+
+        mgr.handle_event(&c, KeyDirection::Down);
+        run_frames(&mut mgr, 300);
+        let sustain = mgr.active_voices[0].synth.amplitude();
+
+        mgr.handle_event_with_velocity(&c, KeyDirection::Down, 0.5);
+        run_frames(&mut mgr, 10);
+        assert_eq!(mgr.voice_count(), 1);
+        assert!(mgr.active_voices[0].synth.amplitude() > sustain);
+        assert_eq!(mgr.active_voices[0].velocity, 0.5);
+
+        // A releasing voice is picked up again instead of starting a new one
+        mgr.handle_event(&c, KeyDirection::Up);
+        mgr.handle_event(&c, KeyDirection::Up);
+        mgr.handle_event(&c, KeyDirection::Down);
+        assert_eq!(releasing_flags(&mgr), vec![false]);
+    }
+
+    #[test]
+    fn test_layer_pairs_key_ups_with_voices() {
+        let mut mgr = retrigger_manager(RetriggerPolicy::Layer);
+        let c = note(4, PitchClass::C);
+
+        mgr.handle_event(&c, KeyDirection::Down);
+        mgr.handle_event(&c, KeyDirection::Down);
+        assert_eq!(mgr.voice_count(), 2);
+
+        mgr.handle_event(&c, KeyDirection::Up);
+        assert_eq!(releasing_flags(&mgr), vec![true, false]);
+        mgr.handle_event(&c, KeyDirection::Up);
+        assert_eq!(releasing_flags(&mgr), vec![true, true]);
+    }
+
+    #[test]
+    fn test_mono_counts_repeated_key_downs() {
+        let mut mgr = mono_manager(NotePriority::Last, false);
+        let (c, e) = (note(4, PitchClass::C), note(4, PitchClass::E));
+
+        mgr.handle_event(&c, KeyDirection::Down);
+        mgr.handle_event(&e, KeyDirection::Down);
+        mgr.handle_event(&e, KeyDirection::Down);
+        mgr.handle_event(&e, KeyDirection::Up);
+        assert_eq!(mgr.active_voices[0].note, e);
+        mgr.handle_event(&e, KeyDirection::Up);
+        assert_eq!(mgr.active_voices[0].note, c);
+    }
+
+    fn pedal(pedal: Pedal, direction: KeyDirection) -> Control {
+        Control::Pedal(pedal, direction)
+    }
+
+    #[test]
+    fn test_sustain_pedal_defers_release() {
+        let mut mgr = create_test_manager();
+        let (c, e) = (note(4, PitchClass::C), note(4, PitchClass::E));
+
+        mgr.handle_event(&c, KeyDirection::Down);
+        mgr.handle_control(&pedal(Pedal::Sustain, KeyDirection::Down));
+        mgr.handle_event(&c, KeyDirection::Up);
+        mgr.handle_event(&e, KeyDirection::Down);
+        mgr.handle_event(&e, KeyDirection::Up);
+        assert_eq!(releasing_flags(&mgr), vec![false, false]);
+
+        // Pressing a sustained note again keeps it going after the pedal lifts
+        mgr.handle_event(&c, KeyDirection::Down);
+        mgr.handle_control(&pedal(Pedal::Sustain, KeyDirection::Up));
+        assert_eq!(releasing_flags(&mgr), vec![false, true]);
+
+        mgr.handle_event(&c, KeyDirection::Up);
+        assert_eq!(releasing_flags(&mgr), vec![true, true]);
+    }
+
+    #[test]
+    fn test_restrike_under_sustain_pedal() {
+        let mut mgr = create_test_manager();
+        let c = note(4, PitchClass::C);
+
+        mgr.handle_event(&c, KeyDirection::Down);
+        mgr.handle_control(&pedal(Pedal::Sustain, KeyDirection::Down));
+        run_frames(&mut mgr, 300); // well into sustain
+        mgr.handle_event(&c, KeyDirection::Up);
+        run_frames(&mut mgr, 10);
+        let level = mgr.active_voices[0].synth.amplitude();
+
+        // A pedal-held note struck again attacks anew
+        mgr.handle_event(&c, KeyDirection::Down);
+        run_frames(&mut mgr, 10);
+        assert_eq!(mgr.active_voices.len(), 1);
+        assert!(mgr.active_voices[0].synth.amplitude() > level);
+
+        // A key still down is not struck again
+        run_frames(&mut mgr, 300);
+        let level = mgr.active_voices[0].synth.amplitude();
+        mgr.handle_event(&c, KeyDirection::Down);
+        run_frames(&mut mgr, 10);
+        assert_eq!(mgr.active_voices[0].synth.amplitude(), level);
+    }
+
+    #[test]
+    fn test_sostenuto_holds_only_caught_notes() {
+        let mut mgr = create_test_manager();
+        let (c, e) = (note(4, PitchClass::C), note(4, PitchClass::E));
+
+        mgr.handle_event(&c, KeyDirection::Down);
+        mgr.handle_control(&pedal(Pedal::Sostenuto, KeyDirection::Down));
+        mgr.handle_event(&e, KeyDirection::Down);
+        mgr.handle_event(&c, KeyDirection::Up);
+        mgr.handle_event(&e, KeyDirection::Up);
+        assert_eq!(releasing_flags(&mgr), vec![false, true]);
+
+        mgr.handle_control(&pedal(Pedal::Sostenuto, KeyDirection::Up));
+        assert_eq!(releasing_flags(&mgr), vec![true, true]);
+    }
+
+    #[test]
+    fn test_pedals_combine() {
+        let mut mgr = create_test_manager();
+        let c = note(4, PitchClass::C);
+
+        mgr.handle_event(&c, KeyDirection::Down);
+        mgr.handle_control(&pedal(Pedal::Sostenuto, KeyDirection::Down));
+        mgr.handle_control(&pedal(Pedal::Sustain, KeyDirection::Down));
+        mgr.handle_event(&c, KeyDirection::Up);
+
+        // Still held by the sostenuto pedal
+        mgr.handle_control(&pedal(Pedal::Sustain, KeyDirection::Up));
+        assert_eq!(releasing_flags(&mgr), vec![false]);
+        mgr.handle_control(&pedal(Pedal::Sostenuto, KeyDirection::Up));
+        assert_eq!(releasing_flags(&mgr), vec![true]);
+    }
+
+    #[test]
+    fn test_mono_sustain_pedal() {
+        let mut mgr = mono_manager(NotePriority::Last, false);
+        let c = note(4, PitchClass::C);
+
+        mgr.handle_control(&pedal(Pedal::Sustain, KeyDirection::Down));
+        mgr.handle_event(&c, KeyDirection::Down);
+        mgr.handle_event(&c, KeyDirection::Up);
+        assert_eq!(releasing_flags(&mgr), vec![false]);
+        mgr.handle_control(&pedal(Pedal::Sustain, KeyDirection::Up));
+        assert_eq!(releasing_flags(&mgr), vec![true]);
+    }
+
+    #[test]
+    fn test_pitch_bend_applies_to_all_voices() {
+        let mut mgr = create_test_manager();
+        mgr.handle_event(&note(4, PitchClass::C), KeyDirection::Down);
+        mgr.handle_control(&Control::PitchBend(1.0));
+        mgr.handle_event(&note(4, PitchClass::E), KeyDirection::Down);
+
+        // Full bend is two semitones by default
+        let whole_tone = 2f32.powf(2.0 / 12.0);
+        for voice in &mgr.active_voices {
+            assert!((voice.synth.pitch_ratio() - whole_tone).abs() < 1e-6);
+        }
+
+        mgr.handle_control(&Control::PitchBend(0.0));
+        assert_eq!(mgr.active_voices[0].synth.pitch_ratio(), 1.0);
+    }
+
+    #[test]
+    fn test_mod_wheel_scales_mod_depth() {
+        let mut mgr = create_test_manager();
+        let depth = mgr.config.fm_params.mod_depth;
+        mgr.handle_event(&note(4, PitchClass::C), KeyDirection::Down);
+
+        mgr.handle_control(&Control::ControlChange(CC_MOD_WHEEL, 0.5));
+        assert_eq!(mgr.active_voices[0].synth.mod_depth(), depth * 2.0);
+
+        // New voices pick up the current controller state
+        mgr.handle_event(&note(4, PitchClass::E), KeyDirection::Down);
+        assert_eq!(mgr.active_voices[1].synth.mod_depth(), depth * 2.0);
+    }
+
+    #[test]
+    fn test_expression_ramps_output() {
+        let mut reference = create_test_manager();
+        let mut mgr = create_test_manager();
+        for m in [&mut reference, &mut mgr] {
+            m.handle_event(&note(4, PitchClass::A), KeyDirection::Down);
+            run_frames(m, 300);
+        }
+
+        mgr.handle_control(&Control::ControlChange(CC_EXPRESSION, 0.5));
+        let mut expected = vec![0.0f32; 64];
+        let mut actual = vec![0.0f32; 64];
+        for frame in 0..2 {
+            reference.process_frame(&mut expected);
+            mgr.process_frame(&mut actual);
+            for j in 0..64 {
+                let gain = if frame == 0 {
+                    1.0 - 0.5 * (j + 1) as f32 / 64.0
+                } else {
+                    0.5
+                };
+                assert!((actual[j] - expected[j] * gain).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sustain_controller_acts_as_pedal() {
+        let mut mgr = create_test_manager();
+        let c = note(4, PitchClass::C);
+        mgr.handle_control(&Control::ControlChange(CC_SUSTAIN, 1.0));
+        mgr.handle_event(&c, KeyDirection::Down);
+        mgr.handle_event(&c, KeyDirection::Up);
+        assert_eq!(releasing_flags(&mgr), vec![false]);
+
+        mgr.handle_control(&Control::ControlChange(CC_SUSTAIN, 0.0));
+        assert_eq!(releasing_flags(&mgr), vec![true]);
+    }
+
+    fn mono_manager(note_priority: NotePriority, legato: bool) -> VoiceManager {
+        let config = VoiceConfig {
+            mode: VoiceMode::Mono,
+            note_priority,
+            legato,
+            ..Default::default()
+        };
+        VoiceManager::new(config, 110.0, 44100)
+    }
+
+    #[test]
+    fn test_mono_returns_to_held_note() {
+        let mut mgr = mono_manager(NotePriority::Last, false);
+        let (c, e, g) = (
+            note(4, PitchClass::C),
+            note(4, PitchClass::E),
+            note(4, PitchClass::G),
+        );
+
+        mgr.handle_event(&c, KeyDirection::Down);
+        mgr.handle_event(&e, KeyDirection::Down);
+        mgr.handle_event(&g, KeyDirection::Down);
+        assert_eq!(mgr.voice_count(), 1);
+        assert_eq!(mgr.active_voices[0].note, g);
+
+        // Releasing the sounding note falls back to the previous held one
+        mgr.handle_event(&g, KeyDirection::Up);
+        assert_eq!(mgr.active_voices[0].note, e);
+
+        // Releasing a note that is not sounding changes nothing
+        mgr.handle_event(&c, KeyDirection::Up);
+        assert_eq!(mgr.active_voices[0].note, e);
+        assert!(!mgr.active_voices[0].is_releasing);
+
+        mgr.handle_event(&e, KeyDirection::Up);
+        assert!(mgr.active_voices[0].is_releasing);
+        assert_eq!(mgr.voice_count(), 1);
+    }
+
+    #[test]
+    fn test_mono_note_priority() {
+        let (c, e, g) = (
+            note(4, PitchClass::C),
+            note(4, PitchClass::E),
+            note(4, PitchClass::G),
+        );
+
+        let mut low = mono_manager(NotePriority::Low, false);
+        let mut high = mono_manager(NotePriority::High, false);
+        for n in [e, c, g] {
+            low.handle_event(&n, KeyDirection::Down);
+            high.handle_event(&n, KeyDirection::Down);
+        }
+        assert_eq!(low.active_voices[0].note, c);
+        assert_eq!(high.active_voices[0].note, g);
+
+        low.handle_event(&c, KeyDirection::Up);
+        high.handle_event(&g, KeyDirection::Up);
+        assert_eq!(low.active_voices[0].note, e);
+        assert_eq!(high.active_voices[0].note, e);
+    }
+
+    #[test]
+    fn test_mono_legato_keeps_envelope() {
+        let (c, e) = (note(4, PitchClass::C), note(4, PitchClass::E));
+
+        for legato in [true, false] {
+            let mut mgr = mono_manager(NotePriority::Last, legato);
+            mgr.handle_event(&c, KeyDirection::Down);
+            run_frames(&mut mgr, 300); // well into sustain
+            let level = mgr.active_voices[0].synth.amplitude();
+
+            mgr.handle_event(&e, KeyDirection::Down);
+            run_frames(&mut mgr, 10);
+            let after = mgr.active_voices[0].synth.amplitude();
+
+            if legato {
+                assert_eq!(after, level);
+            } else {
+                // Retriggered: back in the attack, rising above sustain
+                assert!(after > level);
+            }
+        }
+    }
+
+    #[test]
+    fn test_mono_glide() {
+        let config = VoiceConfig {
+            mode: VoiceMode::Mono,
+            glide_samples: 4410,
+            ..Default::default()
+        };
+        let mut mgr = VoiceManager::new(config, 110.0, 44100);
+        let (c, e) = (note(4, PitchClass::C), note(4, PitchClass::E));
+        let start = mgr.phase_per_sample(mgr.note_frequency(&c).unwrap());
+        let target = mgr.phase_per_sample(mgr.note_frequency(&e).unwrap());
+
+        mgr.handle_event(&c, KeyDirection::Down);
+        run_frames(&mut mgr, 10);
+        mgr.handle_event(&e, KeyDirection::Down);
+
+        run_frames(&mut mgr, 10);
+        let pps = mgr.active_voices[0].synth.phase_per_sample();
+        assert!(pps > start && pps < target);
+
+        run_frames(&mut mgr, 100);
+        assert_eq!(mgr.active_voices[0].synth.phase_per_sample(), target);
+    }
+
+    #[test]
+    fn test_mono_note_above_nyquist() {
+        // 9b is about 52.7 kHz at the default base frequency
+        let high = note(9, PitchClass::B);
+        for legato in [true, false] {
+            let mut mgr = mono_manager(NotePriority::Last, legato);
+            mgr.handle_event(&note(4, PitchClass::C), KeyDirection::Down);
+            run_frames(&mut mgr, 10);
+            mgr.handle_event(&high, KeyDirection::Down);
+            run_frames(&mut mgr, 100);
+            assert_eq!(
+                mgr.active_voices[0].synth.phase_per_sample(),
+                MAX_PHASE_PER_SAMPLE
+            );
+        }
+
+        // Poly mode plays it at the same pitch
+        let mut mgr = create_test_manager();
+        mgr.handle_event(&high, KeyDirection::Down);
+        run_frames(&mut mgr, 10);
+        assert_eq!(
+            mgr.active_voices[0].synth.phase_per_sample(),
+            MAX_PHASE_PER_SAMPLE
+        );
+    }
+
+    #[test]
+    fn test_huge_polyphony_limit_preallocates_bounded_pool() {
+        let config = VoiceConfig {
+            max_voices: Some(usize::MAX),
+            ..Default::default()
+        };
+        let mgr = VoiceManager::new(config, 110.0, 44100);
+        assert_eq!(mgr.spare_synths.len(), MAX_VOICE_CAPACITY);
+    }
+
     #[test]
     fn test_polyphony() {
         let mut mgr = create_test_manager();
@@ -371,4 +1949,312 @@ mod tests {
         // Should still have 3 voices
         assert_eq!(mgr.voice_count(), 3);
     }
+
+    #[test]
+    fn test_audio_path_does_not_allocate() {
+        let config = VoiceConfig {
+            max_voices: Some(4),
+            release_samples: 200,
+            ..Default::default()
+        };
+        let mut mgr = VoiceManager::new(config, 110.0, 44100);
+        let mut buffer = vec![0.0f32; MAX_FRAME_SIZE + 100];
+        let notes: Vec<Note> = (0..8)
+            .map(|i| Note::from_semitone_index(48 + i).unwrap())
+            .collect();
+
+        let ((), allocations) = crate::alloc_check::count_allocations(|| {
+            for round in 0..3 {
+                mgr.handle_control(&Control::Pedal(Pedal::Sustain, KeyDirection::Down));
+                mgr.handle_control(&Control::ControlChange(CC_MOD_WHEEL, 0.5));
+                mgr.handle_control(&Control::PitchBend(0.25));
+                // More notes than the limit, so some voices are stolen
+                for note in &notes {
+                    mgr.handle_event_with_velocity(note, KeyDirection::Down, 0.8);
+                    mgr.process_frame(&mut buffer[..64]);
+                }
+                for note in &notes {
+                    mgr.handle_event(note, KeyDirection::Up);
+                }
+                mgr.handle_control(&Control::Pedal(Pedal::Sustain, KeyDirection::Up));
+                for _ in 0..20 {
+                    mgr.process_frame(&mut buffer);
+                }
+                assert_eq!(mgr.voice_count(), 0, "round {}", round);
+            }
+        });
+        assert_eq!(allocations, 0);
+        assert!(mgr.stats().voices_stolen > 0);
+    }
+
+    #[test]
+    fn test_parallel_render_is_bit_identical() {
+        let config = VoiceConfig {
+            max_voices: Some(40),
+            release_samples: 3000,
+            ..Default::default()
+        };
+        let mut serial = VoiceManager::new(config.clone(), 110.0, 44100);
+        let mut parallel = VoiceManager::new(config, 110.0, 44100).with_render_threads(4);
+        let notes: Vec<Note> = (0..48)
+            .map(|i| Note::from_semitone_index(24 + i).unwrap())
+            .collect();
+
+        let mut expected = vec![0.0f32; MAX_FRAME_SIZE + 100];
+        let mut actual = expected.clone();
+        let mut used_threads = false;
+        let mut render = |serial: &mut VoiceManager, parallel: &mut VoiceManager, len| {
+            used_threads |= parallel.parallel_jobs() > 1;
+            serial.process_frame(&mut expected[..len]);
+            parallel.process_frame(&mut actual[..len]);
+            assert_eq!(expected[..len], actual[..len]);
+            assert_eq!(serial.voice_count(), parallel.voice_count());
+        };
+
+        // More notes than the limit, so some voices are stolen mid-frame
+        for (i, note) in notes.iter().enumerate() {
+            let velocity = 0.3 + (i % 5) as f32 * 0.1;
+            for m in [&mut serial, &mut parallel] {
+                m.handle_event_with_velocity(note, KeyDirection::Down, velocity);
+            }
+            render(&mut serial, &mut parallel, 37);
+        }
+        for m in [&mut serial, &mut parallel] {
+            m.handle_control(&Control::ControlChange(CC_EXPRESSION, 0.6));
+        }
+        render(&mut serial, &mut parallel, MAX_FRAME_SIZE + 100);
+        for note in notes.iter().step_by(2) {
+            for m in [&mut serial, &mut parallel] {
+                m.handle_event(note, KeyDirection::Up);
+            }
+        }
+        for _ in 0..10 {
+            render(&mut serial, &mut parallel, 64);
+        }
+        for m in [&mut serial, &mut parallel] {
+            m.all_notes_off();
+        }
+        while serial.has_active_voices() {
+            render(&mut serial, &mut parallel, 128);
+        }
+        assert!(!parallel.has_active_voices());
+        assert!(used_threads);
+        assert!(parallel.stats().voices_stolen > 0);
+    }
+
+    fn key(index: i32) -> Note {
+        Note::from_semitone_index(index).unwrap()
+    }
+
+    /// A looped 110 Hz sine (the pitch of 1C) covering keys 12..=35
+    fn sine_zone() -> SampleZone {
+        let tone: Vec<f32> = (0..44100)
+            .map(|i| (2.0 * std::f32::consts::PI * 110.0 * i as f32 / 44100.0).sin())
+            .collect();
+        let sample = Sample::new(&tone, 44100, 110.0).with_loop(4410, 44100, 400);
+        SampleZone::new(Arc::new(sample), key(12), key(35))
+    }
+
+    #[test]
+    fn test_sample_zone_lookup() {
+        let zone = sine_zone();
+        let map = SampleMap::default()
+            .with_zone(zone.clone().with_velocity(0.0, 0.5))
+            .with_zone(SampleZone::new(Arc::clone(&zone.sample), key(24), key(47)));
+
+        assert!(std::ptr::eq(
+            map.zone(&key(12), 0.3).unwrap(),
+            &map.zones[0]
+        ));
+        assert!(std::ptr::eq(
+            map.zone(&key(30), 0.9).unwrap(),
+            &map.zones[1]
+        ));
+        assert!(map.zone(&key(12), 0.9).is_none());
+        assert!(map.zone(&key(48), 0.3).is_none());
+        assert!(SampleMap::default().is_empty());
+    }
+
+    #[test]
+    fn test_sampler_layer_follows_pitch() {
+        let config = VoiceConfig {
+            samples: SampleMap::default().with_zone(sine_zone()),
+            fm_level: 0.0,
+            release_samples: 100,
+            ..Default::default()
+        };
+        let mut mgr = VoiceManager::new(config, 110.0, 44100);
+        let c1 = key(12);
+        let c2 = key(24);
+        assert_eq!(c1.octave, 1);
+
+        mgr.handle_event(&c1, KeyDirection::Down);
+        mgr.handle_event(&c2, KeyDirection::Down);
+        let rates: Vec<f32> = mgr
+            .active_voices
+            .iter()
+            .map(|v| v.sampler.as_ref().unwrap().rate())
+            .collect();
+        assert!((rates[0] - 1.0).abs() < 1e-6);
+        assert!((rates[1] - 2.0).abs() < 1e-6);
+
+        mgr.handle_control(&Control::PitchBend(1.0));
+        let sampler = mgr.active_voices[0].sampler.as_ref().unwrap();
+        assert!((sampler.pitch_ratio() - 2f32.powf(2.0 / 12.0)).abs() < 1e-6);
+
+        // Held notes keep looping long after the sample's length
+        let mut buffer = vec![0.0f32; 512];
+        for _ in 0..200 {
+            mgr.process_frame(&mut buffer);
+        }
+        assert_eq!(mgr.voice_count(), 2);
+        assert!(buffer.iter().any(|s| s.abs() > 0.5));
+
+        mgr.all_notes_off();
+        for _ in 0..2 {
+            mgr.process_frame(&mut buffer);
+        }
+        assert!(!mgr.has_active_voices());
+    }
+
+    #[test]
+    fn test_fm_and_sample_layers_share_pitch() {
+        // Render one layer at a time: pure FM carrier, then the sample alone
+        let render_layer = |fm_level: f32, samples: SampleMap| {
+            let mut config = VoiceConfig {
+                samples,
+                fm_level,
+                ..Default::default()
+            };
+            config.fm_params.mod_depth = 0.0;
+            let mut mgr = VoiceManager::new(config, 110.0, 44100);
+            mgr.handle_event(&key(30), KeyDirection::Down);
+            let mut output = vec![0.0f32; 22050];
+            for frame in output.chunks_mut(512) {
+                mgr.process_frame(frame);
+            }
+            crate::pitch_check::measure_frequency(&output[4410..], 44100)
+        };
+        let fm = render_layer(1.0, SampleMap::default());
+        let sample = render_layer(0.0, SampleMap::default().with_zone(sine_zone()));
+
+        // Key 30 is 18 semitones above 1C
+        let expected = 110.0 * 2f64.powf(18.0 / 12.0);
+        assert!((fm / expected - 1.0).abs() < 1e-4, "FM at {} Hz", fm);
+        assert!(
+            (sample / fm - 1.0).abs() < 1e-4,
+            "{} Hz vs {} Hz",
+            sample,
+            fm
+        );
+    }
+
+    #[test]
+    fn test_restrike_fades_out_old_sample() {
+        let config = VoiceConfig {
+            samples: SampleMap::default().with_zone(sine_zone()),
+            fm_level: 0.0,
+            retrigger_policy: RetriggerPolicy::Retrigger,
+            ..Default::default()
+        };
+        let mut mgr = VoiceManager::new(config, 110.0, 44100);
+        let c1 = key(12);
+        mgr.handle_event(&c1, KeyDirection::Down);
+        let mut before = vec![0.0f32; 9000];
+        for frame in before.chunks_mut(64) {
+            mgr.process_frame(frame);
+        }
+
+        // The old sample fades over the steal fade instead of stopping dead
+        mgr.handle_event(&c1, KeyDirection::Down);
+        assert!(mgr.active_voices[0].outgoing_sampler.is_some());
+        let mut after = vec![0.0f32; 512];
+        mgr.process_frame(&mut after);
+        assert!(mgr.active_voices[0].outgoing_sampler.is_none());
+
+        // A 110 Hz sine moves at most about 0.016 per sample
+        let jump = (after[0] - before[before.len() - 1]).abs();
+        let max_step = after
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).abs())
+            .fold(jump, f32::max);
+        assert!(max_step < 0.05, "step of {}", max_step);
+    }
+
+    #[test]
+    fn test_keys_outside_zones_use_fm_only() {
+        let config = VoiceConfig {
+            samples: SampleMap::default().with_zone(sine_zone()),
+            ..Default::default()
+        };
+        let mut mgr = VoiceManager::new(config.clone(), 110.0, 44100);
+        mgr.handle_event(&key(48), KeyDirection::Down);
+        assert!(mgr.active_voices[0].sampler.is_none());
+
+        // Without the FM layer such a key is silent and ends at once
+        let mut silent = VoiceManager::new(
+            VoiceConfig {
+                fm_level: 0.0,
+                ..config
+            },
+            110.0,
+            44100,
+        );
+        silent.handle_event(&key(48), KeyDirection::Down);
+        let mut buffer = vec![1.0f32; 64];
+        silent.process_frame(&mut buffer);
+        assert!(buffer.iter().all(|&s| s == 0.0));
+        assert!(!silent.has_active_voices());
+    }
+
+    #[test]
+    fn test_mono_legato_glides_sample() {
+        let config = VoiceConfig {
+            samples: SampleMap::default().with_zone(sine_zone()),
+            mode: VoiceMode::Mono,
+            legato: true,
+            glide_samples: 100,
+            ..Default::default()
+        };
+        let mut mgr = VoiceManager::new(config, 110.0, 44100);
+        let mut buffer = vec![0.0f32; 256];
+        mgr.handle_event(&key(12), KeyDirection::Down);
+        mgr.process_frame(&mut buffer);
+        let position = mgr.active_voices[0].sampler.as_ref().unwrap().position();
+
+        mgr.handle_event(&key(24), KeyDirection::Down);
+        mgr.process_frame(&mut buffer);
+        let sampler = mgr.active_voices[0].sampler.as_ref().unwrap();
+        // Same sample, still playing, now at the new pitch
+        assert!(sampler.position() > position);
+        assert!((sampler.rate() - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_sampler_audio_path_does_not_allocate() {
+        let config = VoiceConfig {
+            max_voices: Some(4),
+            release_samples: 200,
+            samples: SampleMap::default().with_zone(sine_zone()),
+            fm_level: 0.5,
+            ..Default::default()
+        };
+        let mut mgr = VoiceManager::new(config, 110.0, 44100);
+        let mut buffer = vec![0.0f32; MAX_FRAME_SIZE + 100];
+        let notes: Vec<Note> = (0..8).map(|i| key(20 + i)).collect();
+
+        let ((), allocations) = crate::alloc_check::count_allocations(|| {
+            mgr.handle_control(&Control::PitchBend(-0.5));
+            for note in &notes {
+                mgr.handle_event_with_velocity(note, KeyDirection::Down, 0.8);
+                mgr.process_frame(&mut buffer[..64]);
+            }
+            mgr.all_notes_off();
+            for _ in 0..20 {
+                mgr.process_frame(&mut buffer);
+            }
+            assert_eq!(mgr.voice_count(), 0);
+        });
+        assert_eq!(allocations, 0);
+    }
 }
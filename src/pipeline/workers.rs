@@ -0,0 +1,170 @@
+//! Worker threads for parallel rendering
+//!
+//! A [`WorkerPool`] keeps a fixed set of threads alive and runs one job per
+//! thread per call, so rendering a frame in parallel costs two channel
+//! hand-offs per worker instead of spawning threads every frame:
+//! - Jobs are moved to a worker and back, so they need no locking
+//! - The calling thread runs the last job itself
+//! - [`WorkerPool::run`] returns once every job is done, with each job back
+//!   in its original slot
+
+use std::mem;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+/// One worker thread and the channels to reach it
+struct Worker<J> {
+    /// Dropped to stop the thread
+    jobs: Option<SyncSender<J>>,
+    results: Receiver<J>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Fixed set of threads running the same function on jobs of type `J`
+pub struct WorkerPool<J> {
+    workers: Vec<Worker<J>>,
+    work: fn(&mut J),
+}
+
+impl<J: Send + Default + 'static> WorkerPool<J> {
+    /// Start `workers` threads, each running `work` on the jobs it is given
+    ///
+    /// # Panics
+    /// Panics if a thread cannot be spawned
+    pub fn new(workers: usize, work: fn(&mut J)) -> Self {
+        let workers = (0..workers)
+            .map(|index| {
+                let (job_sender, job_receiver) = mpsc::sync_channel::<J>(1);
+                let (result_sender, result_receiver) = mpsc::sync_channel(1);
+                let thread = thread::Builder::new()
+                    .name(format!("render-{}", index))
+                    .spawn(move || {
+                        for mut job in job_receiver {
+                            work(&mut job);
+                            if result_sender.send(job).is_err() {
+                                break;
+                            }
+                        }
+                    })
+                    .expect("Failed to spawn render thread");
+                Worker {
+                    jobs: Some(job_sender),
+                    results: result_receiver,
+                    thread: Some(thread),
+                }
+            })
+            .collect();
+        Self { workers, work }
+    }
+
+    /// Number of worker threads (not counting the calling thread)
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Run the work function on every job in parallel
+    ///
+    /// The last job runs on the calling thread and the others on workers.
+    ///
+    /// # Panics
+    /// Panics if there are more jobs than workers + 1, or if a worker
+    /// panicked
+    pub fn run(&mut self, jobs: &mut [J]) {
+        assert!(
+            jobs.len() <= self.workers.len() + 1,
+            "Too many jobs for {} workers: {}",
+            self.workers.len(),
+            jobs.len()
+        );
+        let Some((local, remote)) = jobs.split_last_mut() else {
+            return;
+        };
+        for (job, worker) in remote.iter_mut().zip(&self.workers) {
+            let sender = worker.jobs.as_ref().expect("Worker is running");
+            sender
+                .send(mem::take(job))
+                .expect("Render worker stopped unexpectedly");
+        }
+        (self.work)(local);
+        for (job, worker) in remote.iter_mut().zip(&self.workers) {
+            *job = worker
+                .results
+                .recv()
+                .expect("Render worker stopped unexpectedly");
+        }
+    }
+}
+
+impl<J> Drop for WorkerPool<J> {
+    fn drop(&mut self) {
+        // Closing the job channels ends each worker's loop
+        for worker in self.workers.iter_mut() {
+            worker.jobs.take();
+        }
+        for worker in self.workers.iter_mut() {
+            if let Some(thread) = worker.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Job {
+        input: Vec<u64>,
+        sum: u64,
+        thread: Option<String>,
+    }
+
+    fn sum_job(job: &mut Job) {
+        job.sum = job.input.iter().sum();
+        job.thread = thread::current().name().map(str::to_string);
+    }
+
+    #[test]
+    fn test_jobs_return_to_their_slots() {
+        let mut pool = WorkerPool::new(3, sum_job);
+        assert_eq!(pool.workers(), 3);
+        for round in 0..50u64 {
+            let mut jobs: Vec<Job> = (0..4)
+                .map(|i| Job {
+                    input: (0..=i + round).collect(),
+                    ..Default::default()
+                })
+                .collect();
+            pool.run(&mut jobs);
+            for (i, job) in jobs.iter().enumerate() {
+                let n = i as u64 + round;
+                assert_eq!(job.sum, n * (n + 1) / 2);
+                assert_eq!(job.input.len() as u64, n + 1);
+            }
+            assert_eq!(jobs[0].thread.as_deref(), Some("render-0"));
+            assert_eq!(jobs[2].thread.as_deref(), Some("render-2"));
+            assert_eq!(jobs[3].thread, thread::current().name().map(str::to_string));
+        }
+    }
+
+    #[test]
+    fn test_fewer_jobs_than_threads() {
+        let mut pool = WorkerPool::new(3, sum_job);
+        let mut jobs = vec![Job {
+            input: vec![1, 2, 3],
+            ..Default::default()
+        }];
+        pool.run(&mut jobs);
+        assert_eq!(jobs[0].sum, 6);
+        pool.run(&mut []);
+    }
+
+    #[test]
+    #[should_panic(expected = "Too many jobs")]
+    fn test_too_many_jobs() {
+        let mut pool = WorkerPool::new(1, sum_job);
+        let mut jobs: Vec<Job> = (0..3).map(|_| Job::default()).collect();
+        pool.run(&mut jobs);
+    }
+}
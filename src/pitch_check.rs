@@ -0,0 +1,35 @@
+//! Pitch measurement for tests
+//!
+//! Estimates the frequency of a steady tone from its rising zero crossings,
+//! interpolated between samples, so tests can check what a render actually
+//! sounds like rather than the frequency it was asked for:
+//!
+//! ```ignore
+//! let frequency = measure_frequency(&rendered[4410..], 44100);
+//! assert!((frequency - 440.0).abs() < 0.5);
+//! ```
+//!
+//! Only meaningful for tones with one crossing per cycle, such as sines or
+//! gently modulated FM.
+
+/// Frequency in Hz of the tone in `samples`
+///
+/// # Panics
+/// Panics if `samples` holds fewer than two rising zero crossings
+pub fn measure_frequency(samples: &[f32], sample_rate: u32) -> f64 {
+    let crossings: Vec<f64> = samples
+        .windows(2)
+        .enumerate()
+        .filter(|(_, pair)| pair[0] < 0.0 && pair[1] >= 0.0)
+        .map(|(i, pair)| {
+            let (a, b) = (pair[0] as f64, pair[1] as f64);
+            i as f64 + a / (a - b)
+        })
+        .collect();
+    assert!(
+        crossings.len() >= 2,
+        "Too few zero crossings to measure a frequency"
+    );
+    let cycles = (crossings.len() - 1) as f64;
+    cycles * sample_rate as f64 / (crossings[crossings.len() - 1] - crossings[0])
+}
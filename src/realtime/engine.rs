@@ -0,0 +1,330 @@
+//! Real-time synthesis engine
+//!
+//! [`Engine::new`] returns two halves:
+//! - [`Engine`]: owned by the audio thread; [`Engine::process`] is the audio
+//!   callback
+//! - [`EngineHandle`]: owned by a control thread; sends timestamped events
+//!
+//! Events travel through a lock-free SPSC queue. Each event carries the
+//! sample position at which it should take effect; the engine splits the
+//! callback buffer at event positions, so timing is sample-accurate whatever
+//! the callback size. Events that arrive late take effect at the start of the
+//! next callback.
+//!
+//! The engine publishes its sample position after every callback so the
+//! control thread can schedule events relative to what is being played.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::pipeline::parser::{Control, KeyDirection, Note};
+use crate::pipeline::tuning::Tuning;
+use crate::pipeline::voicemgr::{VoiceConfig, VoiceManager};
+use crate::realtime::queue::{self, Consumer, Producer};
+
+/// An event sent to the engine
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EngineEvent {
+    /// Key down with velocity (0.0 to 1.0)
+    NoteOn { note: Note, velocity: f32 },
+    /// Key up
+    NoteOff { note: Note },
+    /// Pedal, pitch bend or controller change
+    Control(Control),
+    /// Release every voice and reset pedals and held keys
+    AllNotesOff,
+}
+
+/// An event with the sample position it takes effect at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimedEvent {
+    /// Sample position since the engine started
+    pub time: u64,
+    pub event: EngineEvent,
+}
+
+/// Configuration for a real-time engine
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    /// Sample rate in Hz
+    pub sample_rate: u32,
+    /// Instrument played by the engine
+    pub voice_config: VoiceConfig,
+    /// Base frequency for 1C (Hz)
+    pub base_frequency: f32,
+    /// Tuning; 12-TET from `base_frequency` when `None`
+    pub tuning: Option<Tuning>,
+    /// Maximum number of events waiting for the audio thread
+    pub queue_capacity: usize,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 44100,
+            voice_config: VoiceConfig::default(),
+            base_frequency: 110.0, // 1C = 110 Hz
+            tuning: None,
+            queue_capacity: 1024,
+        }
+    }
+}
+
+/// Audio-thread half of the engine
+pub struct Engine {
+    voice_manager: VoiceManager,
+    events: Consumer<TimedEvent>,
+    /// Event taken from the queue that is not due yet
+    pending: Option<TimedEvent>,
+    /// Sample position of the start of the next callback
+    position: u64,
+    /// `position` as seen by the control thread
+    shared_position: Arc<AtomicU64>,
+}
+
+impl Engine {
+    /// Create an engine and the handle used to control it
+    ///
+    /// All allocation happens here; the returned [`Engine`] can be moved to
+    /// the audio thread.
+    pub fn new(config: EngineConfig) -> (Engine, EngineHandle) {
+        let (producer, consumer) = queue::channel(config.queue_capacity);
+        let shared_position = Arc::new(AtomicU64::new(0));
+
+        let mut voice_manager = VoiceManager::new(
+            config.voice_config,
+            config.base_frequency,
+            config.sample_rate,
+        );
+        if let Some(tuning) = config.tuning {
+            voice_manager = voice_manager.with_tuning(tuning);
+        }
+
+        let engine = Engine {
+            voice_manager,
+            events: consumer,
+            pending: None,
+            position: 0,
+            shared_position: Arc::clone(&shared_position),
+        };
+        let handle = EngineHandle {
+            events: producer,
+            position: shared_position,
+        };
+        (engine, handle)
+    }
+
+    /// Audio callback: render the next `buffer.len()` samples
+    ///
+    /// Applies every event due before the end of the buffer at its sample
+    /// position. Event handling takes no locks, does no I/O and does not
+    /// allocate.
+    pub fn process(&mut self, buffer: &mut [f32]) {
+        let mut start = 0;
+        while start < buffer.len() {
+            let now = self.position + start as u64;
+            let next_due = self.apply_due_events(now);
+
+            let mut length = buffer.len() - start;
+            if let Some(time) = next_due {
+                length = length.min((time - now) as usize);
+            }
+            self.voice_manager
+                .process_frame(&mut buffer[start..start + length]);
+            start += length;
+        }
+
+        self.position += buffer.len() as u64;
+        self.shared_position.store(self.position, Ordering::Release);
+    }
+
+    /// Apply queued events due at or before `now`
+    ///
+    /// Returns the time of the next queued event, if it is in the future.
+    fn apply_due_events(&mut self, now: u64) -> Option<u64> {
+        while let Some(timed) = self.pending.take().or_else(|| self.events.pop()) {
+            if timed.time > now {
+                self.pending = Some(timed);
+                return Some(timed.time);
+            }
+            self.apply(timed.event);
+        }
+        None
+    }
+
+    fn apply(&mut self, event: EngineEvent) {
+        match event {
+            EngineEvent::NoteOn { note, velocity } => self
+                .voice_manager
+                .handle_event_with_velocity(&note, KeyDirection::Down, velocity),
+            EngineEvent::NoteOff { note } => {
+                self.voice_manager.handle_event(&note, KeyDirection::Up)
+            }
+            EngineEvent::Control(control) => self.voice_manager.handle_control(&control),
+            EngineEvent::AllNotesOff => self.voice_manager.all_notes_off(),
+        }
+    }
+
+    /// Sample position of the start of the next callback
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Get the number of sounding voices
+    pub fn voice_count(&self) -> usize {
+        self.voice_manager.voice_count()
+    }
+}
+
+/// Control-thread half of the engine
+pub struct EngineHandle {
+    events: Producer<TimedEvent>,
+    position: Arc<AtomicU64>,
+}
+
+impl EngineHandle {
+    /// Sample position the engine will render next
+    ///
+    /// Events timestamped before this position play at the start of the next
+    /// callback.
+    pub fn position(&self) -> u64 {
+        self.position.load(Ordering::Acquire)
+    }
+
+    /// Queue an event
+    ///
+    /// Events should be sent in time order; an event earlier than one already
+    /// queued waits for it. Returns the event back if the queue is full.
+    pub fn send(&mut self, time: u64, event: EngineEvent) -> Result<(), TimedEvent> {
+        self.events.push(TimedEvent { time, event })
+    }
+
+    /// Queue a key down at `time`
+    pub fn note_on(&mut self, time: u64, note: Note, velocity: f32) -> Result<(), TimedEvent> {
+        self.send(time, EngineEvent::NoteOn { note, velocity })
+    }
+
+    /// Queue a key up at `time`
+    pub fn note_off(&mut self, time: u64, note: Note) -> Result<(), TimedEvent> {
+        self.send(time, EngineEvent::NoteOff { note })
+    }
+
+    /// Queue a pedal, pitch bend or controller change at `time`
+    pub fn control(&mut self, time: u64, control: Control) -> Result<(), TimedEvent> {
+        self.send(time, EngineEvent::Control(control))
+    }
+
+    /// Number of events not yet taken by the audio thread
+    pub fn queued(&self) -> usize {
+        self.events.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::parser::{Event, PitchClass, TimedEvents};
+    use crate::pipeline::scheduler::{Pipeline, PipelineConfig};
+
+    fn c4() -> Note {
+        Note {
+            octave: 4,
+            pitch_class: PitchClass::C,
+        }
+    }
+
+    #[test]
+    fn test_engine_matches_offline_render() {
+        let e4 = c4().transposed(4).unwrap();
+        let (mut engine, mut handle) = Engine::new(EngineConfig::default());
+        handle.note_on(100, c4(), 1.0).unwrap();
+        handle.note_on(350, e4, 0.5).unwrap();
+        handle.note_off(900, c4()).unwrap();
+        handle.note_off(1000, e4).unwrap();
+
+        let mut live = vec![0.0f32; 3000];
+        for chunk in live.chunks_mut(48) {
+            engine.process(chunk);
+        }
+        assert_eq!(handle.position(), 3000);
+
+        // Same events through the offline pipeline at 50-sample timesteps
+        let line = |delta, note, direction, velocity| TimedEvents {
+            delta,
+            events: vec![Event {
+                note,
+                direction,
+                velocity,
+            }],
+            controls: vec![],
+        };
+        let events = vec![
+            line(2, c4(), KeyDirection::Down, 1.0),
+            line(5, e4, KeyDirection::Down, 0.5),
+            line(11, c4(), KeyDirection::Up, 1.0),
+            line(2, e4, KeyDirection::Up, 1.0),
+        ];
+        let config = PipelineConfig {
+            timestep_samples: 50,
+            frame_size: 64,
+            sample_accurate: true,
+            ..Default::default()
+        };
+        let mut pipeline = Pipeline::new(config, events);
+        let mut offline = vec![0.0f32; 3000];
+        for chunk in offline.chunks_mut(64) {
+            pipeline.process_frame(chunk);
+        }
+
+        assert_eq!(live, offline);
+    }
+
+    #[test]
+    fn test_late_events_play_at_next_callback() {
+        let (mut engine, mut handle) = Engine::new(EngineConfig::default());
+        let mut buffer = vec![0.0f32; 64];
+        engine.process(&mut buffer);
+
+        // Already in the past when the engine sees it
+        handle.note_on(10, c4(), 1.0).unwrap();
+        engine.process(&mut buffer);
+        assert_eq!(engine.voice_count(), 1);
+        assert_ne!(buffer[1], 0.0);
+    }
+
+    #[test]
+    fn test_full_queue_returns_event() {
+        let config = EngineConfig {
+            queue_capacity: 2,
+            ..Default::default()
+        };
+        let (mut engine, mut handle) = Engine::new(config);
+        handle.note_on(0, c4(), 1.0).unwrap();
+        handle.note_off(10, c4()).unwrap();
+        let rejected = handle.note_on(20, c4(), 1.0).unwrap_err();
+        assert_eq!(rejected.time, 20);
+
+        engine.process(&mut [0.0; 32]);
+        assert_eq!(handle.queued(), 0);
+        handle.note_on(40, c4(), 1.0).unwrap();
+    }
+
+    #[test]
+    fn test_process_does_not_allocate() {
+        let (mut engine, mut handle) = Engine::new(EngineConfig::default());
+        for i in 0..12 {
+            let note = c4().transposed(i).unwrap();
+            handle.note_on(i as u64 * 100, note, 1.0).unwrap();
+            handle.note_off(i as u64 * 100 + 500, note).unwrap();
+        }
+
+        let mut buffer = [0.0f32; 128];
+        let ((), allocations) = crate::alloc_check::count_allocations(|| {
+            for _ in 0..200 {
+                engine.process(&mut buffer);
+            }
+        });
+        assert_eq!(allocations, 0);
+        assert_eq!(handle.queued(), 0);
+    }
+}
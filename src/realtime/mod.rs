@@ -0,0 +1,14 @@
+//! Real-time synthesis
+//!
+//! Runs voices from an audio callback while another thread sends events:
+//! - Queue: Lock-free single-producer single-consumer ring buffer
+//! - Engine: Audio-thread engine and the control-thread handle feeding it
+//! - Driver: Simulated audio device calling the engine at a fixed rate
+
+pub mod driver;
+pub mod engine;
+pub mod queue;
+
+pub use driver::SimulatedDriver;
+pub use engine::{Engine, EngineConfig, EngineEvent, EngineHandle, TimedEvent};
+pub use queue::{channel, Consumer, Producer};
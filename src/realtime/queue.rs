@@ -0,0 +1,208 @@
+//! Lock-free single-producer single-consumer ring buffer
+//!
+//! Moves events from a control thread to the audio thread without locks or
+//! allocation. The buffer is allocated once by [`channel`]; after that,
+//! [`Producer::push`] and [`Consumer::pop`] only touch atomics and the
+//! preallocated slots, so both are safe to call from a real-time callback.
+//!
+//! Items are `Copy`, so slots never need dropping.
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Storage shared by both ends of the queue
+///
+/// `head` and `tail` count pops and pushes (wrapping); the slot for a count
+/// is `count % capacity`. Only the consumer writes `head` and only the
+/// producer writes `tail`.
+struct Shared<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+// Each slot is accessed by one side at a time: the producer only writes
+// slots the consumer has released (Acquire on `head`), and the consumer only
+// reads slots the producer has published (Acquire on `tail`).
+unsafe impl<T: Copy + Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+        tail.wrapping_sub(head)
+    }
+}
+
+/// Create a queue holding up to `capacity` items
+///
+/// # Panics
+/// Panics if `capacity` is 0
+pub fn channel<T: Copy + Send>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    assert!(capacity > 0, "Queue capacity must be at least 1");
+    let slots = (0..capacity)
+        .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+        .collect();
+    let shared = Arc::new(Shared {
+        slots,
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    (
+        Producer {
+            shared: Arc::clone(&shared),
+        },
+        Consumer { shared },
+    )
+}
+
+/// Sending end of a queue (one per queue)
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: Copy + Send> Producer<T> {
+    /// Add an item to the queue
+    ///
+    /// Returns the item back if the queue is full.
+    pub fn push(&mut self, item: T) -> Result<(), T> {
+        let tail = self.shared.tail.load(Ordering::Relaxed);
+        let head = self.shared.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == self.shared.capacity() {
+            return Err(item);
+        }
+        let slot = &self.shared.slots[tail % self.shared.capacity()];
+        // SAFETY: the slot is not visible to the consumer until `tail` is
+        // published below, and the consumer has finished with it (checked
+        // against `head` above).
+        unsafe { (*slot.get()).write(item) };
+        self.shared
+            .tail
+            .store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    /// Number of items waiting in the queue
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    /// Check if the queue is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Maximum number of items the queue holds
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity()
+    }
+}
+
+/// Receiving end of a queue (one per queue)
+pub struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: Copy + Send> Consumer<T> {
+    /// Take the oldest item from the queue, if any
+    pub fn pop(&mut self) -> Option<T> {
+        let head = self.shared.head.load(Ordering::Relaxed);
+        let tail = self.shared.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let slot = &self.shared.slots[head % self.shared.capacity()];
+        // SAFETY: the producer initialized this slot before publishing
+        // `tail`, and will not reuse it until `head` moves past it.
+        let item = unsafe { (*slot.get()).assume_init_read() };
+        self.shared
+            .head
+            .store(head.wrapping_add(1), Ordering::Release);
+        Some(item)
+    }
+
+    /// Number of items waiting in the queue
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    /// Check if the queue is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Maximum number of items the queue holds
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fifo_order_and_full_queue() {
+        let (mut producer, mut consumer) = channel(3);
+        assert!(consumer.is_empty());
+        for i in 0..3 {
+            producer.push(i).unwrap();
+        }
+        assert_eq!(producer.push(3), Err(3));
+        assert_eq!(consumer.len(), 3);
+
+        assert_eq!(consumer.pop(), Some(0));
+        producer.push(3).unwrap();
+        assert_eq!(consumer.pop(), Some(1));
+        assert_eq!(consumer.pop(), Some(2));
+        assert_eq!(consumer.pop(), Some(3));
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn test_wraparound() {
+        let (mut producer, mut consumer) = channel(4);
+        for i in 0..1000u32 {
+            producer.push(i).unwrap();
+            producer.push(i + 1).unwrap();
+            assert_eq!(consumer.pop(), Some(i));
+            assert_eq!(consumer.pop(), Some(i + 1));
+        }
+        assert!(producer.is_empty());
+    }
+
+    #[test]
+    fn test_cross_thread_transfer() {
+        let (mut producer, mut consumer) = channel(16);
+        let count = 10_000u64;
+
+        let sender = std::thread::spawn(move || {
+            for i in 0..count {
+                let mut item = i;
+                while let Err(returned) = producer.push(item) {
+                    item = returned;
+                    std::thread::yield_now();
+                }
+            }
+        });
+
+        let mut expected = 0;
+        while expected < count {
+            match consumer.pop() {
+                Some(item) => {
+                    assert_eq!(item, expected);
+                    expected += 1;
+                }
+                None => std::thread::yield_now(),
+            }
+        }
+        sender.join().unwrap();
+        assert_eq!(consumer.pop(), None);
+    }
+}
@@ -0,0 +1,164 @@
+//! Dither and noise shaping for integer output
+//!
+//! Rounding to fewer bits leaves an error that follows the signal, which is
+//! audible as distortion on quiet passages. Dither adds a little noise
+//! before rounding so the error becomes steady hiss instead:
+//! - Triangular: TPDF noise of ±1 LSB, which makes the error independent
+//!   of the signal
+//! - Shaped: triangular dither with second-order error feedback, moving
+//!   the noise up towards Nyquist where hearing is least sensitive
+//!
+//! Dither noise is seeded, so renders are reproducible.
+
+use crate::pipeline::transform::SplitMix64;
+
+/// Seed for dither noise
+const DITHER_SEED: u64 = 0x5EED_D17E;
+
+/// Noise added when reducing samples to integers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dither {
+    /// Round to the nearest integer
+    #[default]
+    None,
+    /// Triangular (TPDF) dither
+    Triangular,
+    /// Triangular dither with noise shaping
+    Shaped,
+}
+
+impl std::str::FromStr for Dither {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Dither::None),
+            "tpdf" | "triangular" => Ok(Dither::Triangular),
+            "shaped" => Ok(Dither::Shaped),
+            _ => Err(format!("Invalid dither: {}", s)),
+        }
+    }
+}
+
+/// Converts f32 samples to integers of a given bit depth
+///
+/// Keeps the noise-shaping state of each channel separately.
+pub(crate) struct Quantizer {
+    dither: Dither,
+    /// Integer value of 1.0
+    scale: f64,
+    min: f64,
+    max: f64,
+    rng: SplitMix64,
+    /// Last two total errors per channel, newest first
+    errors: Vec<[f64; 2]>,
+}
+
+impl Quantizer {
+    /// Create a quantizer for signed `bits`-bit samples
+    pub(crate) fn new(dither: Dither, bits: u16, channels: usize) -> Self {
+        let scale = (1u64 << (bits - 1)) as f64;
+        Self {
+            dither,
+            scale,
+            min: -scale,
+            max: scale - 1.0,
+            rng: SplitMix64(DITHER_SEED),
+            errors: vec![[0.0; 2]; channels],
+        }
+    }
+
+    /// Triangular noise in (-1, 1) LSB
+    fn triangular(&mut self) -> f64 {
+        0.5 * (self.rng.unit() as f64 + self.rng.unit() as f64)
+    }
+
+    /// Convert one sample of `channel` to an integer
+    ///
+    /// Samples outside [-1.0, 1.0] clip; NaN becomes 0.
+    pub(crate) fn quantize(&mut self, channel: usize, sample: f32) -> i32 {
+        if sample.is_nan() {
+            return 0;
+        }
+        let value = sample as f64 * self.scale;
+        let rounded = match self.dither {
+            Dither::None => value.round(),
+            Dither::Triangular => (value + self.triangular()).round(),
+            Dither::Shaped => {
+                // Error feedback with (1 - z^-1)^2: the total error is
+                // high-passed twice before it reaches the output
+                let [e1, e2] = self.errors[channel];
+                let target = value - (2.0 * e1 - e2);
+                let rounded = (target + self.triangular()).round();
+                // Taken before clipping, so a clipped sample doesn't feed
+                // back an ever larger error
+                self.errors[channel] = [rounded - target, e1];
+                rounded
+            }
+        };
+        rounded.clamp(self.min, self.max) as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Error of each sample quantizing a quiet sine to 8 bits, in LSB
+    fn quantization_errors(dither: Dither) -> Vec<f64> {
+        let mut quantizer = Quantizer::new(dither, 8, 1);
+        (0..20000)
+            .map(|i| {
+                let sample = 0.01 * (i as f32 * 0.01).sin();
+                quantizer.quantize(0, sample) as f64 - sample as f64 * 128.0
+            })
+            .collect()
+    }
+
+    /// Mean power of an error signal after a 32-sample moving average
+    fn low_frequency_power(errors: &[f64]) -> f64 {
+        let averages: Vec<f64> = errors
+            .windows(32)
+            .map(|w| w.iter().sum::<f64>() / 32.0)
+            .collect();
+        averages.iter().map(|a| a * a).sum::<f64>() / averages.len() as f64
+    }
+
+    #[test]
+    fn test_rounding_and_clipping() {
+        let mut quantizer = Quantizer::new(Dither::None, 16, 1);
+        assert_eq!(quantizer.quantize(0, 0.5), 16384);
+        assert_eq!(quantizer.quantize(0, -1.0), -32768);
+        assert_eq!(quantizer.quantize(0, 1.0), 32767);
+        assert_eq!(quantizer.quantize(0, -3.0), -32768);
+        assert_eq!(quantizer.quantize(0, f32::NAN), 0);
+        assert_eq!(quantizer.quantize(0, 1.4 / 32768.0), 1);
+    }
+
+    #[test]
+    fn test_triangular_dither_decorrelates_error() {
+        let rounded = quantization_errors(Dither::None);
+        let dithered = quantization_errors(Dither::Triangular);
+        assert!(dithered.iter().all(|e| e.abs() < 1.5));
+
+        // Rounding error follows the signal; dither error averages out
+        let mean = |errors: &[f64]| errors.iter().sum::<f64>() / errors.len() as f64;
+        assert!(mean(&dithered).abs() < 0.02);
+        assert!(low_frequency_power(&dithered) < low_frequency_power(&rounded) / 4.0);
+    }
+
+    #[test]
+    fn test_shaped_dither_moves_noise_up() {
+        let flat = quantization_errors(Dither::Triangular);
+        let shaped = quantization_errors(Dither::Shaped);
+        assert!(low_frequency_power(&shaped) < low_frequency_power(&flat) / 5.0);
+    }
+
+    #[test]
+    fn test_dither_is_reproducible() {
+        assert_eq!(
+            quantization_errors(Dither::Shaped),
+            quantization_errors(Dither::Shaped)
+        );
+    }
+}
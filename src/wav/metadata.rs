@@ -0,0 +1,447 @@
+//! WAV metadata chunks
+//!
+//! [`WavMetadata`] holds the chunks samplers and editors read next to the
+//! audio:
+//! - `LIST INFO`: title, artist, software and comment tags
+//! - `cue ` with `LIST adtl` labels: named markers at sample positions
+//! - `smpl`: root note, fine tuning and loop points for samplers
+//!
+//! The writer places these chunks after the audio data, which lets a
+//! streaming writer collect markers while it renders.
+
+use crate::resample::convert_position;
+
+/// A named position in the audio
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CuePoint {
+    /// Frame the marker points at
+    pub position: u32,
+    /// Marker name; empty for an unnamed marker
+    pub label: String,
+}
+
+impl CuePoint {
+    /// Marker at a frame
+    pub fn new(position: u32, label: &str) -> Self {
+        Self {
+            position,
+            label: label.to_string(),
+        }
+    }
+}
+
+/// How a sampler plays a loop
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoopKind {
+    /// Start to end, then jump back to start
+    #[default]
+    Forward,
+    /// Alternately forwards and backwards
+    PingPong,
+    /// End to start
+    Backward,
+}
+
+impl LoopKind {
+    fn code(self) -> u32 {
+        match self {
+            LoopKind::Forward => 0,
+            LoopKind::PingPong => 1,
+            LoopKind::Backward => 2,
+        }
+    }
+
+    fn from_code(code: u32) -> Self {
+        match code {
+            1 => LoopKind::PingPong,
+            2 => LoopKind::Backward,
+            _ => LoopKind::Forward,
+        }
+    }
+}
+
+/// A loop in a `smpl` chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleLoop {
+    /// First frame of the loop
+    pub start: u32,
+    /// Last frame of the loop (inclusive, as stored in the file)
+    pub end: u32,
+    pub kind: LoopKind,
+    /// Times to play the loop; 0 loops until the note is released
+    pub play_count: u32,
+}
+
+impl SampleLoop {
+    /// Forward loop over frames `start..=end`, repeated until release
+    pub fn forward(start: u32, end: u32) -> Self {
+        Self {
+            start,
+            end,
+            kind: LoopKind::Forward,
+            play_count: 0,
+        }
+    }
+}
+
+/// Sampler settings from a `smpl` chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SamplerInfo {
+    /// MIDI note played back at the recorded pitch (60 = middle C)
+    pub root_note: u8,
+    /// How far the recording is above `root_note`, in 1/2^32 of a semitone
+    pub pitch_fraction: u32,
+    pub loops: Vec<SampleLoop>,
+}
+
+impl SamplerInfo {
+    /// Root note exactly at a MIDI note number, without loops
+    pub fn new(root_note: u8) -> Self {
+        Self {
+            root_note,
+            pitch_fraction: 0,
+            loops: Vec::new(),
+        }
+    }
+
+    /// Root note and fine tuning for a recording of `frequency` Hz
+    ///
+    /// The root is the MIDI note at or just below the frequency (12-TET,
+    /// A4 = 440 Hz), clamped to 0..=127.
+    ///
+    /// # Panics
+    /// Panics if `frequency` is not positive and finite
+    pub fn from_frequency(frequency: f64) -> Self {
+        assert!(
+            frequency.is_finite() && frequency > 0.0,
+            "Invalid root frequency: {}",
+            frequency
+        );
+        let mut midi = (69.0 + 12.0 * (frequency / 440.0).log2()).clamp(0.0, 127.0);
+        // Within 0.01 cent of a note (e.g. an f32 frequency) is that note
+        if (midi - midi.round()).abs() < 1e-4 {
+            midi = midi.round();
+        }
+        let root = midi.floor();
+        Self {
+            root_note: root as u8,
+            pitch_fraction: ((midi - root) * 4_294_967_296.0) as u32,
+            loops: Vec::new(),
+        }
+    }
+
+    /// Frequency of the recording in Hz (12-TET, A4 = 440 Hz)
+    ///
+    /// The inverse of [`SamplerInfo::from_frequency`].
+    pub fn frequency(&self) -> f64 {
+        let midi = self.root_note as f64 + self.pitch_fraction as f64 / 4_294_967_296.0;
+        440.0 * 2f64.powf((midi - 69.0) / 12.0)
+    }
+
+    /// Add a loop
+    pub fn with_loop(mut self, sample_loop: SampleLoop) -> Self {
+        self.loops.push(sample_loop);
+        self
+    }
+}
+
+/// Metadata written alongside the audio
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct WavMetadata {
+    /// `INAM` tag
+    pub title: Option<String>,
+    /// `IART` tag
+    pub artist: Option<String>,
+    /// `ISFT` tag: the program that wrote the file
+    pub software: Option<String>,
+    /// `ICMT` tag
+    pub comment: Option<String>,
+    /// Markers, written in this order with IDs from 1
+    pub cue_points: Vec<CuePoint>,
+    /// Root note and loops for samplers
+    pub sampler: Option<SamplerInfo>,
+}
+
+/// Append a chunk, padding it to an even length
+fn push_chunk(out: &mut Vec<u8>, id: &[u8; 4], body: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+/// Text as stored in INFO and label chunks: null-terminated
+fn zstring(text: &str) -> Vec<u8> {
+    let mut bytes = text.as_bytes().to_vec();
+    bytes.push(0);
+    bytes
+}
+
+/// Text up to the first null byte
+fn read_zstring(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// Sub-chunks of a LIST chunk body (after its list type)
+fn sub_chunks(mut bytes: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    std::iter::from_fn(move || {
+        if bytes.len() < 8 {
+            return None;
+        }
+        let id = &bytes[..4];
+        let size = read_u32(bytes, 4) as usize;
+        let end = 8usize.saturating_add(size).min(bytes.len());
+        let body = &bytes[8..end];
+        let next = end.saturating_add(size & 1).min(bytes.len());
+        bytes = &bytes[next..];
+        Some((id, body))
+    })
+}
+
+impl WavMetadata {
+    /// Whether there is nothing to write
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Move cue points and loops to match audio converted between sample
+    /// rates (see [`convert_position`])
+    pub fn convert_positions(&mut self, from: u32, to: u32) {
+        let convert = |position: u32| {
+            u32::try_from(convert_position(position as u64, from, to)).unwrap_or(u32::MAX)
+        };
+        for point in self.cue_points.iter_mut() {
+            point.position = convert(point.position);
+        }
+        if let Some(sampler) = self.sampler.as_mut() {
+            for sample_loop in sampler.loops.iter_mut() {
+                sample_loop.start = convert(sample_loop.start);
+                // The end is inclusive: convert the exclusive end
+                sample_loop.end = convert(sample_loop.end.saturating_add(1)).saturating_sub(1);
+            }
+        }
+    }
+
+    fn info_tags(&self) -> [(&[u8; 4], &Option<String>); 4] {
+        [
+            (b"INAM", &self.title),
+            (b"IART", &self.artist),
+            (b"ISFT", &self.software),
+            (b"ICMT", &self.comment),
+        ]
+    }
+
+    /// Encode as complete RIFF chunks
+    ///
+    /// # Arguments
+    /// * `sample_rate` - Sample rate of the audio, for the `smpl` period
+    pub(crate) fn to_chunks(&self, sample_rate: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        let mut info = b"INFO".to_vec();
+        for (id, text) in self.info_tags() {
+            if let Some(text) = text {
+                push_chunk(&mut info, id, &zstring(text));
+            }
+        }
+        if info.len() > 4 {
+            push_chunk(&mut out, b"LIST", &info);
+        }
+
+        if !self.cue_points.is_empty() {
+            let mut cue = (self.cue_points.len() as u32).to_le_bytes().to_vec();
+            let mut labels = b"adtl".to_vec();
+            for (index, point) in self.cue_points.iter().enumerate() {
+                let id = index as u32 + 1;
+                cue.extend_from_slice(&id.to_le_bytes());
+                cue.extend_from_slice(&point.position.to_le_bytes()); // Play order position
+                cue.extend_from_slice(b"data");
+                cue.extend_from_slice(&0u32.to_le_bytes()); // Chunk start
+                cue.extend_from_slice(&0u32.to_le_bytes()); // Block start
+                cue.extend_from_slice(&point.position.to_le_bytes());
+                if !point.label.is_empty() {
+                    let mut label = id.to_le_bytes().to_vec();
+                    label.extend_from_slice(&zstring(&point.label));
+                    push_chunk(&mut labels, b"labl", &label);
+                }
+            }
+            push_chunk(&mut out, b"cue ", &cue);
+            if labels.len() > 4 {
+                push_chunk(&mut out, b"LIST", &labels);
+            }
+        }
+
+        if let Some(sampler) = &self.sampler {
+            let sample_period = (1e9 / sample_rate as f64).round() as u32;
+            let mut smpl = Vec::new();
+            for value in [
+                0, // Manufacturer
+                0, // Product
+                sample_period,
+                sampler.root_note as u32,
+                sampler.pitch_fraction,
+                0, // SMPTE format
+                0, // SMPTE offset
+                sampler.loops.len() as u32,
+                0, // Sampler-specific data size
+            ] {
+                smpl.extend_from_slice(&value.to_le_bytes());
+            }
+            for (index, sample_loop) in sampler.loops.iter().enumerate() {
+                for value in [
+                    index as u32,
+                    sample_loop.kind.code(),
+                    sample_loop.start,
+                    sample_loop.end,
+                    0, // Fraction
+                    sample_loop.play_count,
+                ] {
+                    smpl.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            push_chunk(&mut out, b"smpl", &smpl);
+        }
+
+        out
+    }
+
+    /// Read a metadata chunk; other chunks and malformed entries are ignored
+    pub(crate) fn read_chunk(&mut self, id: &[u8], body: &[u8]) {
+        match id {
+            b"LIST" if body.starts_with(b"INFO") => {
+                for (tag, text) in sub_chunks(&body[4..]) {
+                    let field = match tag {
+                        b"INAM" => &mut self.title,
+                        b"IART" => &mut self.artist,
+                        b"ISFT" => &mut self.software,
+                        b"ICMT" => &mut self.comment,
+                        _ => continue,
+                    };
+                    *field = Some(read_zstring(text));
+                }
+            }
+            b"LIST" if body.starts_with(b"adtl") => {
+                for (tag, label) in sub_chunks(&body[4..]) {
+                    if tag != b"labl" || label.len() < 4 {
+                        continue;
+                    }
+                    let index = read_u32(label, 0) as usize;
+                    if let Some(point) = index
+                        .checked_sub(1)
+                        .and_then(|i| self.cue_points.get_mut(i))
+                    {
+                        point.label = read_zstring(&label[4..]);
+                    }
+                }
+            }
+            b"cue " if body.len() >= 4 => {
+                // Labels refer to IDs, which this reader assumes run from 1
+                self.cue_points = body[4..]
+                    .chunks_exact(24)
+                    .take(read_u32(body, 0) as usize)
+                    .map(|point| CuePoint::new(read_u32(point, 20), ""))
+                    .collect();
+            }
+            b"smpl" if body.len() >= 36 => {
+                let loops = body[36..]
+                    .chunks_exact(24)
+                    .take(read_u32(body, 28) as usize)
+                    .map(|entry| SampleLoop {
+                        kind: LoopKind::from_code(read_u32(entry, 4)),
+                        start: read_u32(entry, 8),
+                        end: read_u32(entry, 12),
+                        play_count: read_u32(entry, 20),
+                    })
+                    .collect();
+                self.sampler = Some(SamplerInfo {
+                    root_note: read_u32(body, 12).min(127) as u8,
+                    pitch_fraction: read_u32(body, 16),
+                    loops,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> WavMetadata {
+        WavMetadata {
+            title: Some("Für Elise".to_string()),
+            artist: Some("Beethoven".to_string()),
+            software: Some("corroza".to_string()),
+            comment: None,
+            cue_points: vec![
+                CuePoint::new(0, "4e"),
+                CuePoint::new(1234, ""),
+                CuePoint::new(99_999, "4d#"),
+            ],
+            sampler: Some(SamplerInfo::new(69).with_loop(SampleLoop::forward(100, 4099))),
+        }
+    }
+
+    #[test]
+    fn test_chunks_round_trip() {
+        let metadata = example();
+        let bytes = metadata.to_chunks(44100);
+        assert_eq!(bytes.len() % 2, 0);
+
+        let mut read = WavMetadata::default();
+        for (id, body) in sub_chunks(&bytes) {
+            read.read_chunk(id, body);
+        }
+        assert_eq!(read, metadata);
+        assert!(WavMetadata::default().to_chunks(44100).is_empty());
+        assert!(WavMetadata::default().is_empty());
+    }
+
+    #[test]
+    fn test_smpl_layout() {
+        let bytes = example().to_chunks(48000);
+        let smpl = bytes.windows(4).position(|w| w == b"smpl").unwrap();
+        let body = &bytes[smpl + 8..];
+        assert_eq!(read_u32(&bytes, smpl + 4), 36 + 24);
+        assert_eq!(read_u32(body, 8), 20_833); // Nanoseconds per sample
+        assert_eq!(read_u32(body, 12), 69);
+        assert_eq!(read_u32(body, 28), 1);
+        assert_eq!(read_u32(body, 36 + 8), 100);
+        assert_eq!(read_u32(body, 36 + 12), 4099);
+    }
+
+    #[test]
+    fn test_root_from_frequency() {
+        let a4 = SamplerInfo::from_frequency(440.0);
+        assert_eq!((a4.root_note, a4.pitch_fraction), (69, 0));
+
+        let c4 = SamplerInfo::from_frequency(440.0 * 2f64.powf(-9.0 / 12.0));
+        assert_eq!((c4.root_note, c4.pitch_fraction), (60, 0));
+
+        // A quarter tone above A4
+        let sharp = SamplerInfo::from_frequency(440.0 * 2f64.powf(0.5 / 12.0));
+        assert_eq!(sharp.root_note, 69);
+        assert!((sharp.pitch_fraction as i64 - (1i64 << 31)).abs() < 1 << 12);
+        assert!((sharp.frequency() - 440.0 * 2f64.powf(0.5 / 12.0)).abs() < 1e-6);
+        assert!((c4.frequency() - 261.6256).abs() < 1e-3);
+
+        // 110 Hz (1C in the default tuning) is A2
+        assert_eq!(SamplerInfo::from_frequency(110.0).root_note, 45);
+        // 4a in the default tuning, computed in f32, is F#6
+        let f6_sharp = SamplerInfo::from_frequency((110.0f32 * 2f32.powf(3.75)) as f64);
+        assert_eq!((f6_sharp.root_note, f6_sharp.pitch_fraction), (90, 0));
+    }
+}
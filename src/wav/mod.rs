@@ -0,0 +1,28 @@
+//! WAV file reading and writing
+//!
+//! - Writer: PCM (8, 16, 24 and 32-bit) and 32-bit float files with any
+//!   number of channels
+//! - Dither: TPDF and noise-shaped dither when reducing to integers
+//! - Reader: PCM (8, 16, 24 and 32-bit) and IEEE float files with any
+//!   number of channels, converted to f32 samples per channel
+//! - Metadata: INFO tags, cue markers and sampler loop points, written and
+//!   read by both
+
+pub mod dither;
+pub mod metadata;
+pub mod reader;
+pub mod writer;
+
+pub use dither::Dither;
+pub use metadata::{CuePoint, LoopKind, SampleLoop, SamplerInfo, WavMetadata};
+pub use reader::{parse_wav, read_wav, WavAudio, WavError};
+pub use writer::{write_wav, write_wav_16bit, write_wav_with_metadata, WavSpec, WavWriter};
+
+/// How samples are encoded in a WAV file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// Signed integers (unsigned for 8-bit)
+    Pcm,
+    /// IEEE floating point
+    Float,
+}
@@ -0,0 +1,498 @@
+//! WAV file reader
+//!
+//! Parses RIFF/WAVE files into f32 samples, one vector per channel:
+//! - PCM 8-bit (unsigned), 16, 24 and 32-bit, scaled to [-1.0, 1.0)
+//! - IEEE float, 32 and 64-bit, passed through unchanged
+//! - `WAVE_FORMAT_EXTENSIBLE` headers with a PCM or float sub-format
+//! - Any number of interleaved channels
+//! - RF64 files, whose 64-bit sizes come from a `ds64` chunk
+//!
+//! INFO tags, cue markers and `smpl` loops are read into [`WavMetadata`];
+//! other chunks (fact, bext, ...) are skipped.
+//! A data chunk cut short, e.g. by a writer that never finished, is read up
+//! to the last whole frame.
+
+use std::path::Path;
+
+use super::metadata::WavMetadata;
+use super::SampleFormat;
+use crate::resample::{resample, ResampleQuality};
+
+const FORMAT_PCM: u16 = 0x0001;
+const FORMAT_FLOAT: u16 = 0x0003;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Errors from reading a WAV file
+#[derive(Debug)]
+pub enum WavError {
+    /// Not a well-formed RIFF/WAVE file
+    Invalid(String),
+    /// A well-formed file in an encoding the reader doesn't decode
+    Unsupported(String),
+    /// Reading the file failed
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for WavError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WavError::Invalid(message) => write!(f, "Invalid WAV file: {}", message),
+            WavError::Unsupported(message) => write!(f, "Unsupported WAV format: {}", message),
+            WavError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WavError {}
+
+impl From<std::io::Error> for WavError {
+    fn from(e: std::io::Error) -> Self {
+        WavError::Io(e)
+    }
+}
+
+/// Decoded audio from a WAV file
+#[derive(Debug, Clone, PartialEq)]
+pub struct WavAudio {
+    /// Sample rate in Hz
+    pub sample_rate: u32,
+    /// Encoding of the samples in the file
+    pub format: SampleFormat,
+    /// Bits per sample in the file
+    pub bits_per_sample: u16,
+    /// Samples of each channel (all the same length)
+    pub channels: Vec<Vec<f32>>,
+    /// Tags, markers and sampler data found in the file
+    pub metadata: WavMetadata,
+}
+
+impl WavAudio {
+    /// Number of channels
+    pub fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Number of samples per channel
+    pub fn frames(&self) -> usize {
+        self.channels.first().map_or(0, Vec::len)
+    }
+
+    /// Length in seconds
+    pub fn duration(&self) -> f64 {
+        self.frames() as f64 / self.sample_rate as f64
+    }
+
+    /// Average of all channels
+    pub fn to_mono(&self) -> Vec<f32> {
+        let scale = 1.0 / self.channels.len() as f32;
+        (0..self.frames())
+            .map(|i| self.channels.iter().map(|c| c[i]).sum::<f32>() * scale)
+            .collect()
+    }
+
+    /// Convert to another sample rate
+    ///
+    /// Cue points and sampler loops move with the audio. `format` and
+    /// `bits_per_sample` still describe the original file.
+    pub fn resampled(&self, sample_rate: u32, quality: ResampleQuality) -> WavAudio {
+        let channels = self
+            .channels
+            .iter()
+            .map(|channel| resample(channel, self.sample_rate, sample_rate, quality))
+            .collect();
+        let mut metadata = self.metadata.clone();
+        metadata.convert_positions(self.sample_rate, sample_rate);
+        WavAudio {
+            sample_rate,
+            channels,
+            metadata,
+            ..*self
+        }
+    }
+}
+
+/// Contents of the `fmt ` chunk
+struct Format {
+    format: SampleFormat,
+    channels: usize,
+    sample_rate: u32,
+    bits_per_sample: u16,
+    /// Bytes per sample of one channel
+    sample_bytes: usize,
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from(read_u32(bytes, offset)) | u64::from(read_u32(bytes, offset + 4)) << 32
+}
+
+fn invalid(message: &str) -> WavError {
+    WavError::Invalid(message.to_string())
+}
+
+fn parse_format(chunk: &[u8]) -> Result<Format, WavError> {
+    if chunk.len() < 16 {
+        return Err(invalid("fmt chunk is too short"));
+    }
+    let mut tag = read_u16(chunk, 0);
+    let channels = read_u16(chunk, 2) as usize;
+    let sample_rate = read_u32(chunk, 4);
+    let block_align = read_u16(chunk, 12) as usize;
+    let bits_per_sample = read_u16(chunk, 14);
+
+    if tag == FORMAT_EXTENSIBLE {
+        // The sub-format GUID starts with the plain format tag
+        if chunk.len() < 26 {
+            return Err(invalid("extensible fmt chunk is too short"));
+        }
+        tag = read_u16(chunk, 24);
+    }
+    let format = match tag {
+        FORMAT_PCM => SampleFormat::Pcm,
+        FORMAT_FLOAT => SampleFormat::Float,
+        other => return Err(WavError::Unsupported(format!("format tag {:#06x}", other))),
+    };
+
+    if channels == 0 {
+        return Err(invalid("no channels"));
+    }
+    if sample_rate == 0 {
+        return Err(invalid("sample rate is 0"));
+    }
+    let sample_bytes = match (format, bits_per_sample) {
+        (SampleFormat::Pcm, 8 | 16 | 24 | 32) | (SampleFormat::Float, 32 | 64) => {
+            bits_per_sample as usize / 8
+        }
+        (format, bits) => return Err(WavError::Unsupported(format!("{}-bit {:?}", bits, format))),
+    };
+    if block_align != channels * sample_bytes {
+        return Err(WavError::Invalid(format!(
+            "block align {} does not match {} channels of {} bits",
+            block_align, channels, bits_per_sample
+        )));
+    }
+
+    Ok(Format {
+        format,
+        channels,
+        sample_rate,
+        bits_per_sample,
+        sample_bytes,
+    })
+}
+
+/// Converter from one little-endian sample to f32
+fn sample_decoder(format: SampleFormat, sample_bytes: usize) -> fn(&[u8]) -> f32 {
+    match (format, sample_bytes) {
+        (SampleFormat::Pcm, 1) => |b| (b[0] as f32 - 128.0) / 128.0,
+        (SampleFormat::Pcm, 2) => |b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0,
+        (SampleFormat::Pcm, 3) => {
+            // Shift into the top of an i32 to sign-extend
+            |b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8_388_608.0
+        }
+        (SampleFormat::Pcm, _) => {
+            |b| (i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64 / 2_147_483_648.0) as f32
+        }
+        (SampleFormat::Float, 4) => |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        (SampleFormat::Float, _) => {
+            |b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32
+        }
+    }
+}
+
+/// Decode a WAV file held in memory
+///
+/// # Example
+/// ```
+/// use corroza::wav::{parse_wav, write_wav_16bit};
+///
+/// write_wav_16bit("/tmp/parse_example.wav", &[0.0, 0.5, -0.5], 8000).unwrap();
+/// let audio = parse_wav(&std::fs::read("/tmp/parse_example.wav").unwrap()).unwrap();
+/// assert_eq!(audio.sample_rate, 8000);
+/// assert_eq!(audio.frames(), 3);
+/// ```
+pub fn parse_wav(bytes: &[u8]) -> Result<WavAudio, WavError> {
+    if bytes.len() < 12 || !matches!(&bytes[0..4], b"RIFF" | b"RF64") || &bytes[8..12] != b"WAVE" {
+        return Err(invalid("missing RIFF/WAVE header"));
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut data_size64 = None;
+    let mut metadata = WavMetadata::default();
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let mut size = read_u32(bytes, offset + 4) as usize;
+        let start = offset + 8;
+        if id == b"data" && size == u32::MAX as usize {
+            // RF64 keeps the real size in the ds64 chunk
+            size = data_size64.unwrap_or(size);
+        }
+        let end = start.saturating_add(size).min(bytes.len());
+        match id {
+            b"ds64" if end - start >= 16 => {
+                let data_size = read_u64(bytes, start + 8);
+                data_size64 = Some(usize::try_from(data_size).unwrap_or(usize::MAX));
+            }
+            b"fmt " => format = Some(parse_format(&bytes[start..end])?),
+            b"data" => data = Some(&bytes[start..end]),
+            _ => metadata.read_chunk(id, &bytes[start..end]),
+        }
+        // Chunks are padded to an even length
+        offset = start.saturating_add(size).saturating_add(size & 1);
+    }
+
+    let format = format.ok_or_else(|| invalid("missing fmt chunk"))?;
+    let data = data.ok_or_else(|| invalid("missing data chunk"))?;
+
+    let decode = sample_decoder(format.format, format.sample_bytes);
+    let frame_bytes = format.channels * format.sample_bytes;
+    let frames = data.len() / frame_bytes;
+    let mut channels = vec![Vec::with_capacity(frames); format.channels];
+    for frame in data.chunks_exact(frame_bytes) {
+        for (channel, sample) in channels
+            .iter_mut()
+            .zip(frame.chunks_exact(format.sample_bytes))
+        {
+            channel.push(decode(sample));
+        }
+    }
+
+    Ok(WavAudio {
+        sample_rate: format.sample_rate,
+        format: format.format,
+        bits_per_sample: format.bits_per_sample,
+        channels,
+        metadata,
+    })
+}
+
+/// Read and decode a WAV file
+pub fn read_wav(path: impl AsRef<Path>) -> Result<WavAudio, WavError> {
+    parse_wav(&std::fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wav::write_wav_16bit;
+
+    /// Build a WAV file: fmt chunk fields, then extra chunks, then data
+    fn wav_bytes(
+        tag: u16,
+        channels: u16,
+        bits: u16,
+        extra_chunks: &[(&[u8; 4], &[u8])],
+        data: &[u8],
+    ) -> Vec<u8> {
+        let block_align = channels * bits / 8;
+        let mut fmt = Vec::new();
+        fmt.extend_from_slice(&tag.to_le_bytes());
+        fmt.extend_from_slice(&channels.to_le_bytes());
+        fmt.extend_from_slice(&8000u32.to_le_bytes());
+        fmt.extend_from_slice(&(8000 * block_align as u32).to_le_bytes());
+        fmt.extend_from_slice(&block_align.to_le_bytes());
+        fmt.extend_from_slice(&bits.to_le_bytes());
+
+        let mut chunks: Vec<(&[u8; 4], &[u8])> = vec![(b"fmt ", &fmt)];
+        chunks.extend_from_slice(extra_chunks);
+        chunks.push((b"data", data));
+
+        let mut body = b"WAVE".to_vec();
+        for (id, contents) in chunks {
+            body.extend_from_slice(id);
+            body.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            body.extend_from_slice(contents);
+            if contents.len() % 2 == 1 {
+                body.push(0);
+            }
+        }
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    #[test]
+    fn test_round_trip_with_writer() {
+        let path = "/tmp/test_reader_round_trip.wav";
+        let samples: Vec<f32> = (0..1000).map(|i| (i as f32 * 0.05).sin() * 0.9).collect();
+        write_wav_16bit(path, &samples, 22050).unwrap();
+
+        let audio = read_wav(path).unwrap();
+        assert_eq!(audio.sample_rate, 22050);
+        assert_eq!(audio.format, SampleFormat::Pcm);
+        assert_eq!(audio.bits_per_sample, 16);
+        assert_eq!(audio.channel_count(), 1);
+        assert_eq!(audio.frames(), 1000);
+        // The writer truncates and scales positive samples by 32767
+        for (read, written) in audio.channels[0].iter().zip(&samples) {
+            assert!((read - written).abs() <= 2.0 / 32768.0);
+        }
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_pcm_bit_depths() {
+        // 8-bit stereo: unsigned with 128 as zero
+        let audio = parse_wav(&wav_bytes(1, 2, 8, &[], &[128, 0, 255, 192])).unwrap();
+        assert_eq!(
+            audio.channels,
+            vec![vec![0.0, 127.0 / 128.0], vec![-1.0, 0.5]]
+        );
+
+        // 24-bit: sign-extended
+        let data = [0x00, 0x00, 0x80, 0xFF, 0xFF, 0x7F, 0x00, 0x00, 0x40];
+        let audio = parse_wav(&wav_bytes(1, 1, 24, &[], &data)).unwrap();
+        assert_eq!(
+            audio.channels[0],
+            vec![-1.0, 8_388_607.0 / 8_388_608.0, 0.5]
+        );
+
+        // 32-bit
+        let mut data = i32::MIN.to_le_bytes().to_vec();
+        data.extend_from_slice(&(1i32 << 29).to_le_bytes());
+        let audio = parse_wav(&wav_bytes(1, 1, 32, &[], &data)).unwrap();
+        assert_eq!(audio.channels[0], vec![-1.0, 0.25]);
+    }
+
+    #[test]
+    fn test_float_formats() {
+        let data: Vec<u8> = [0.25f32, -1.5]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let audio = parse_wav(&wav_bytes(3, 1, 32, &[(b"fact", &[2, 0, 0, 0])], &data)).unwrap();
+        assert_eq!(audio.format, SampleFormat::Float);
+        assert_eq!(audio.channels[0], vec![0.25, -1.5]);
+
+        let data: Vec<u8> = [0.125f64, 1.0]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let audio = parse_wav(&wav_bytes(3, 1, 64, &[], &data)).unwrap();
+        assert_eq!(audio.channels[0], vec![0.125, 1.0]);
+    }
+
+    #[test]
+    fn test_extensible_header_and_unknown_chunks() {
+        let mut bytes = wav_bytes(
+            FORMAT_EXTENSIBLE,
+            2,
+            16,
+            &[(b"LIST", b"INFOodd"), (b"junk", &[0; 4])],
+            &[0x00, 0x40, 0x00, 0xC0],
+        );
+        // Grow the fmt chunk to the 40-byte extensible layout (PCM sub-format)
+        let mut extension = vec![22, 0, 16, 0, 3, 0, 0, 0];
+        extension.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0x10, 0]);
+        extension.extend_from_slice(&[0x80, 0, 0, 0xAA, 0, 0x38, 0x9B, 0x71]);
+        bytes.splice(36..36, extension);
+        bytes[16] = 40;
+        let riff_size = (bytes.len() - 8) as u32;
+        bytes[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+        let audio = parse_wav(&bytes).unwrap();
+        assert_eq!(audio.channels, vec![vec![0.5], vec![-0.5]]);
+    }
+
+    #[test]
+    fn test_truncated_data_keeps_whole_frames() {
+        let mut bytes = wav_bytes(1, 2, 16, &[], &[0; 12]);
+        bytes.truncate(bytes.len() - 3);
+        let audio = parse_wav(&bytes).unwrap();
+        assert_eq!(audio.frames(), 2);
+        assert_eq!(audio.to_mono(), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_rf64_sizes_from_ds64() {
+        let data: Vec<u8> = [100i16, -200, 300]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let mut ds64 = Vec::new();
+        ds64.extend_from_slice(&0u64.to_le_bytes()); // RIFF size, unused
+        ds64.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        ds64.extend_from_slice(&3u64.to_le_bytes());
+        ds64.extend_from_slice(&0u32.to_le_bytes());
+        let mut bytes = wav_bytes(1, 1, 16, &[(b"ds64", &ds64)], &data);
+        // Trailing bytes past the real data size are not samples
+        bytes.extend_from_slice(&[0xFF; 4]);
+        bytes[0..4].copy_from_slice(b"RF64");
+        bytes[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        let size_at = bytes.len() - 4 - data.len() - 4;
+        bytes[size_at..size_at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let audio = parse_wav(&bytes).unwrap();
+        assert_eq!(
+            audio.channels,
+            vec![vec![100.0 / 32768.0, -200.0 / 32768.0, 300.0 / 32768.0]]
+        );
+    }
+
+    #[test]
+    fn test_resampled_moves_markers() {
+        use crate::wav::{CuePoint, SampleLoop, SamplerInfo};
+
+        let audio = WavAudio {
+            sample_rate: 32000,
+            format: SampleFormat::Pcm,
+            bits_per_sample: 16,
+            channels: vec![vec![0.25; 3200], vec![-0.5; 3200]],
+            metadata: WavMetadata {
+                cue_points: vec![CuePoint::new(1600, "middle")],
+                sampler: Some(SamplerInfo::new(60).with_loop(SampleLoop::forward(800, 2399))),
+                ..Default::default()
+            },
+        };
+        let converted = audio.resampled(48000, ResampleQuality::Cubic);
+        assert_eq!(converted.sample_rate, 48000);
+        assert_eq!(converted.frames(), 4800);
+        assert_eq!(converted.channels[1][2400], -0.5);
+        assert_eq!(converted.metadata.cue_points[0].position, 2400);
+        let sample_loop = converted.metadata.sampler.unwrap().loops[0];
+        assert_eq!((sample_loop.start, sample_loop.end), (1200, 3599));
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let error = parse_wav(b"RIFX\0\0\0\0WAVE").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid WAV file: missing RIFF/WAVE header"
+        );
+
+        let mut bytes = wav_bytes(1, 1, 16, &[], &[0, 0]);
+        bytes.truncate(36);
+        assert!(matches!(parse_wav(&bytes), Err(WavError::Invalid(_))));
+
+        // ADPCM
+        let error = parse_wav(&wav_bytes(2, 1, 16, &[], &[0, 0])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unsupported WAV format: format tag 0x0002"
+        );
+        let error = parse_wav(&wav_bytes(3, 1, 16, &[], &[0, 0])).unwrap_err();
+        assert!(matches!(error, WavError::Unsupported(_)));
+
+        assert!(matches!(
+            read_wav("/nonexistent/file.wav"),
+            Err(WavError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,1006 @@
+//! WAV file writer
+//!
+//! - [`write_wav_16bit`]: mono 16-bit PCM with truncating conversion
+//! - [`write_wav`]: any number of channels as 8, 16, 24 or 32-bit PCM, or
+//!   32-bit float, configured by a [`WavSpec`]; integer output is rounded,
+//!   optionally with [`Dither`]
+//! - [`WavWriter`]: the same formats written incrementally, for renders too
+//!   long to hold in memory; becomes RF64 beyond 4 GB
+//! - [`write_wav_with_metadata`] and [`WavWriter::metadata_mut`]: INFO tags,
+//!   cue markers and sampler loops (see [`WavMetadata`])
+//!
+//! Files with more than two channels, or integer samples wider than 16
+//! bits, use a `WAVE_FORMAT_EXTENSIBLE` header as the format requires.
+//! Note: Sample rate is only used for the file header, not for any processing.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+
+use super::dither::{Dither, Quantizer};
+use super::metadata::WavMetadata;
+use super::SampleFormat;
+
+const FORMAT_PCM: u16 = 0x0001;
+const FORMAT_FLOAT: u16 = 0x0003;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Tail shared by the sub-format GUIDs of extensible headers
+const SUBFORMAT_GUID_TAIL: [u8; 14] = [
+    0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+];
+
+/// Output format for [`write_wav`] and [`WavWriter`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavSpec {
+    /// Sample rate in Hz
+    pub sample_rate: u32,
+    /// Number of interleaved channels
+    pub channels: u16,
+    /// Integer or floating point samples
+    pub format: SampleFormat,
+    /// 8, 16, 24 or 32 for PCM; 32 for float
+    pub bits_per_sample: u16,
+    /// Noise added when rounding to integers (ignored for float)
+    pub dither: Dither,
+}
+
+impl WavSpec {
+    /// Integer samples without dither
+    ///
+    /// # Arguments
+    /// * `sample_rate` - Sample rate in Hz
+    /// * `channels` - Number of channels
+    /// * `bits_per_sample` - 8, 16, 24 or 32
+    pub fn pcm(sample_rate: u32, channels: u16, bits_per_sample: u16) -> Self {
+        Self {
+            sample_rate,
+            channels,
+            format: SampleFormat::Pcm,
+            bits_per_sample,
+            dither: Dither::None,
+        }
+    }
+
+    /// 32-bit float samples, written without clipping
+    pub fn float(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            sample_rate,
+            channels,
+            format: SampleFormat::Float,
+            bits_per_sample: 32,
+            dither: Dither::None,
+        }
+    }
+
+    /// Dither integer samples when rounding
+    pub fn with_dither(mut self, dither: Dither) -> Self {
+        self.dither = dither;
+        self
+    }
+
+    /// Bytes per frame (one sample of every channel)
+    pub fn block_align(&self) -> u16 {
+        self.channels * (self.bits_per_sample / 8)
+    }
+
+    /// Check that the format can be written
+    fn validate(&self) -> io::Result<()> {
+        let supported = match self.format {
+            SampleFormat::Pcm => matches!(self.bits_per_sample, 8 | 16 | 24 | 32),
+            SampleFormat::Float => self.bits_per_sample == 32,
+        };
+        if !supported {
+            return Err(invalid_input(format!(
+                "Unsupported WAV format: {}-bit {:?}",
+                self.bits_per_sample, self.format
+            )));
+        }
+        if self.channels == 0 || self.sample_rate == 0 {
+            return Err(invalid_input(
+                "WAV files need at least one channel and a positive sample rate".to_string(),
+            ));
+        }
+        // The header stores bytes per frame in 16 bits and per second in 32
+        let block_align = self.channels.checked_mul(self.bits_per_sample / 8);
+        if block_align
+            .and_then(|align| self.sample_rate.checked_mul(align as u32))
+            .is_none()
+        {
+            return Err(invalid_input(format!(
+                "Too many channels or too high a sample rate for a WAV header: {} channels at {} Hz",
+                self.channels, self.sample_rate
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether the header needs `WAVE_FORMAT_EXTENSIBLE`
+    fn is_extensible(&self) -> bool {
+        self.channels > 2 || (self.format == SampleFormat::Pcm && self.bits_per_sample > 16)
+    }
+
+    /// Speaker positions for the extensible header
+    fn channel_mask(&self) -> u32 {
+        match self.channels {
+            1 => 0x4,   // front center
+            2 => 0x3,   // front left, right
+            4 => 0x33,  // quad
+            6 => 0x3F,  // 5.1
+            8 => 0x63F, // 7.1
+            _ => 0,
+        }
+    }
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Size of the `ds64` chunk of an RF64 file, including its header
+const DS64_CHUNK_SIZE: u64 = 36;
+
+/// Write the RIFF header, fmt (and fact) chunks and the data chunk header
+///
+/// `metadata_size` is the size of the chunks following the data, counted
+/// in the RIFF size. Data too large for 32-bit sizes gets an RF64 header, whose `ds64` chunk
+/// holds the 64-bit sizes. With `reserve_ds64`, a small file gets a `JUNK`
+/// chunk of the same size in its place, so the header can be rewritten as
+/// RF64 once the final size is known without moving the data.
+fn write_header(
+    writer: &mut impl Write,
+    spec: &WavSpec,
+    frames: u64,
+    metadata_size: u64,
+    reserve_ds64: bool,
+) -> io::Result<()> {
+    let data_size = frames * spec.block_align() as u64;
+    let format_tag = match spec.format {
+        SampleFormat::Pcm => FORMAT_PCM,
+        SampleFormat::Float => FORMAT_FLOAT,
+    };
+    let fmt_size: u32 = if spec.is_extensible() {
+        40
+    } else if spec.format == SampleFormat::Float {
+        18
+    } else {
+        16
+    };
+    // Non-PCM formats need a fact chunk with the frame count
+    let fact_size = if spec.format == SampleFormat::Pcm {
+        0
+    } else {
+        12
+    };
+    let chunks_size =
+        (8 + fmt_size as u64) + fact_size + 8 + data_size + (data_size & 1) + metadata_size;
+    // Decide on the RIFF size the plain header would store, which includes
+    // a reserved ds64 chunk
+    let reserved = if reserve_ds64 { DS64_CHUNK_SIZE } else { 0 };
+    let rf64 = 4 + reserved + chunks_size > u32::MAX as u64;
+    let riff_size = if rf64 {
+        4 + DS64_CHUNK_SIZE + chunks_size
+    } else {
+        4 + reserved + chunks_size
+    };
+    // Sizes that don't fit in 32 bits are stored in ds64 instead
+    let size32 = |size: u64| if rf64 { u32::MAX } else { size as u32 };
+
+    let byte_rate = spec.sample_rate * spec.block_align() as u32;
+    writer.write_all(if rf64 { b"RF64" } else { b"RIFF" })?;
+    writer.write_all(&size32(riff_size).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    if rf64 {
+        writer.write_all(b"ds64")?;
+        writer.write_all(&(DS64_CHUNK_SIZE as u32 - 8).to_le_bytes())?;
+        writer.write_all(&riff_size.to_le_bytes())?;
+        writer.write_all(&data_size.to_le_bytes())?;
+        writer.write_all(&frames.to_le_bytes())?;
+        writer.write_all(&0u32.to_le_bytes())?; // No table entries
+    } else if reserve_ds64 {
+        writer.write_all(b"JUNK")?;
+        writer.write_all(&(DS64_CHUNK_SIZE as u32 - 8).to_le_bytes())?;
+        writer.write_all(&[0; DS64_CHUNK_SIZE as usize - 8])?;
+    }
+
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&fmt_size.to_le_bytes())?;
+    let tag = if spec.is_extensible() {
+        FORMAT_EXTENSIBLE
+    } else {
+        format_tag
+    };
+    writer.write_all(&tag.to_le_bytes())?;
+    writer.write_all(&spec.channels.to_le_bytes())?;
+    writer.write_all(&spec.sample_rate.to_le_bytes())?;
+    writer.write_all(&byte_rate.to_le_bytes())?;
+    writer.write_all(&spec.block_align().to_le_bytes())?;
+    writer.write_all(&spec.bits_per_sample.to_le_bytes())?;
+    if spec.is_extensible() {
+        writer.write_all(&22u16.to_le_bytes())?; // Extension size
+        writer.write_all(&spec.bits_per_sample.to_le_bytes())?; // Valid bits
+        writer.write_all(&spec.channel_mask().to_le_bytes())?;
+        writer.write_all(&format_tag.to_le_bytes())?;
+        writer.write_all(&SUBFORMAT_GUID_TAIL)?;
+    } else if fmt_size == 18 {
+        writer.write_all(&0u16.to_le_bytes())?; // No extension
+    }
+
+    if fact_size > 0 {
+        writer.write_all(b"fact")?;
+        writer.write_all(&4u32.to_le_bytes())?;
+        writer.write_all(&size32(frames).to_le_bytes())?;
+    }
+
+    writer.write_all(b"data")?;
+    writer.write_all(&size32(data_size).to_le_bytes())
+}
+
+/// Append one sample in the file's encoding
+fn encode_sample(
+    spec: &WavSpec,
+    quantizer: &mut Quantizer,
+    channel: usize,
+    sample: f32,
+    out: &mut Vec<u8>,
+) {
+    if spec.format == SampleFormat::Float {
+        let sample = if sample.is_nan() { 0.0 } else { sample };
+        out.extend_from_slice(&sample.to_le_bytes());
+        return;
+    }
+    let value = quantizer.quantize(channel, sample);
+    match spec.bits_per_sample {
+        // 8-bit samples are unsigned
+        8 => out.push((value + 128) as u8),
+        16 => out.extend_from_slice(&(value as i16).to_le_bytes()),
+        24 => out.extend_from_slice(&value.to_le_bytes()[..3]),
+        _ => out.extend_from_slice(&value.to_le_bytes()),
+    }
+}
+
+/// Write a WAV file in any supported format
+///
+/// # Arguments
+/// * `path` - Output file path
+/// * `spec` - Sample format, channel count, sample rate and dither
+/// * `channels` - Samples of each channel (f32, range [-1.0, 1.0] for
+///   integer formats), all the same length
+///
+/// # Returns
+/// Result indicating success or IO error; an unsupported spec or channels
+/// not matching it give `ErrorKind::InvalidInput`
+///
+/// # Example
+/// ```
+/// use corroza::wav::{write_wav, Dither, WavSpec};
+///
+/// let left = vec![0.0f32; 4800];
+/// let right = vec![0.0f32; 4800];
+/// let spec = WavSpec::pcm(48000, 2, 24).with_dither(Dither::Triangular);
+/// write_wav("/tmp/stereo.wav", &spec, &[left, right]).unwrap();
+/// ```
+pub fn write_wav<C: AsRef<[f32]>>(path: &str, spec: &WavSpec, channels: &[C]) -> io::Result<()> {
+    write_wav_with_metadata(path, spec, channels, &WavMetadata::default())
+}
+
+/// Write a WAV file with tags, markers or sampler data after the audio
+///
+/// Like [`write_wav`]; cue point and loop positions are frames of the
+/// written audio.
+///
+/// # Example
+/// ```
+/// use corroza::wav::{write_wav_with_metadata, CuePoint, SampleLoop, SamplerInfo, WavMetadata, WavSpec};
+///
+/// let metadata = WavMetadata {
+///     title: Some("A4 sustain".to_string()),
+///     cue_points: vec![CuePoint::new(0, "attack")],
+///     sampler: Some(SamplerInfo::new(69).with_loop(SampleLoop::forward(2000, 3999))),
+///     ..Default::default()
+/// };
+/// let samples = vec![0.0f32; 4800];
+/// write_wav_with_metadata("/tmp/a4.wav", &WavSpec::pcm(48000, 1, 24), &[samples], &metadata)
+///     .unwrap();
+/// ```
+pub fn write_wav_with_metadata<C: AsRef<[f32]>>(
+    path: &str,
+    spec: &WavSpec,
+    channels: &[C],
+    metadata: &WavMetadata,
+) -> io::Result<()> {
+    spec.validate()?;
+    if channels.len() != spec.channels as usize {
+        return Err(invalid_input(format!(
+            "Expected {} channels, got {}",
+            spec.channels,
+            channels.len()
+        )));
+    }
+    let frames = channels.first().map_or(0, |c| c.as_ref().len());
+    if channels.iter().any(|c| c.as_ref().len() != frames) {
+        return Err(invalid_input(
+            "All channels must have the same length".to_string(),
+        ));
+    }
+
+    let metadata = metadata.to_chunks(spec.sample_rate);
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_header(
+        &mut writer,
+        spec,
+        frames as u64,
+        metadata.len() as u64,
+        false,
+    )?;
+
+    let mut quantizer = Quantizer::new(spec.dither, spec.bits_per_sample, channels.len());
+    let mut frame_bytes = Vec::with_capacity(spec.block_align() as usize);
+    for i in 0..frames {
+        frame_bytes.clear();
+        for (channel, samples) in channels.iter().enumerate() {
+            encode_sample(
+                spec,
+                &mut quantizer,
+                channel,
+                samples.as_ref()[i],
+                &mut frame_bytes,
+            );
+        }
+        writer.write_all(&frame_bytes)?;
+    }
+    // Chunks are padded to an even length
+    if (frames * spec.block_align() as usize) % 2 == 1 {
+        writer.write_all(&[0])?;
+    }
+    writer.write_all(&metadata)?;
+    writer.flush()
+}
+
+/// Streaming WAV writer
+///
+/// Writes frames as they are produced through a buffered writer, so long
+/// renders need constant memory. The header is written up front with
+/// placeholder sizes and patched by [`WavWriter::finalize`]. The placeholder
+/// data size is 0xFFFFFFFF, so a file that is never finished (e.g. the
+/// process is killed) still reads back up to its last whole frame. Files whose
+/// data outgrows 32-bit sizes (about 4 GB) become RF64. Metadata is
+/// written after the data when the writer finishes, so markers can be
+/// added while rendering through [`WavWriter::metadata_mut`].
+///
+/// Dropping the writer without calling `finalize` patches the header too,
+/// but any error doing so is lost.
+///
+/// # Example
+/// ```
+/// use corroza::wav::{WavSpec, WavWriter};
+///
+/// let mut writer = WavWriter::create("/tmp/stream.wav", WavSpec::pcm(44100, 2, 24)).unwrap();
+/// for _ in 0..10 {
+///     writer.write_interleaved(&[0.0f32; 2 * 64]).unwrap();
+/// }
+/// writer.finalize().unwrap();
+/// ```
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    spec: WavSpec,
+    quantizer: Quantizer,
+    /// Frames written so far
+    frames: u64,
+    /// Encoded bytes waiting to be written
+    bytes: Vec<u8>,
+    metadata: WavMetadata,
+    finalized: bool,
+}
+
+impl WavWriter<BufWriter<File>> {
+    /// Create a file and write its header
+    pub fn create(path: &str, spec: WavSpec) -> io::Result<Self> {
+        spec.validate()?;
+        Self::new(BufWriter::new(File::create(path)?), spec)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Start a WAV stream at the beginning of `writer`
+    ///
+    /// # Returns
+    /// The writer, or an error for an unsupported spec or a failed write
+    pub fn new(mut writer: W, spec: WavSpec) -> io::Result<Self> {
+        spec.validate()?;
+        let mut header = Vec::new();
+        write_header(&mut header, &spec, 0, 0, true)?;
+        // Until finished, the RIFF and data chunks run to the end of the file
+        let data_size_at = header.len() - 4;
+        header[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        header[data_size_at..].copy_from_slice(&u32::MAX.to_le_bytes());
+        writer.write_all(&header)?;
+        Ok(Self {
+            writer,
+            quantizer: Quantizer::new(spec.dither, spec.bits_per_sample, spec.channels as usize),
+            spec,
+            frames: 0,
+            bytes: Vec::new(),
+            metadata: WavMetadata::default(),
+            finalized: false,
+        })
+    }
+
+    /// Output format
+    pub fn spec(&self) -> &WavSpec {
+        &self.spec
+    }
+
+    /// Number of frames written so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Metadata to write when the file is finished
+    pub fn metadata_mut(&mut self) -> &mut WavMetadata {
+        &mut self.metadata
+    }
+
+    /// Replace the metadata to write when the file is finished
+    pub fn set_metadata(&mut self, metadata: WavMetadata) {
+        self.metadata = metadata;
+    }
+
+    /// Write frames of interleaved samples (one sample per channel each)
+    ///
+    /// # Returns
+    /// `ErrorKind::InvalidInput` if `samples` is not a whole number of frames
+    pub fn write_interleaved(&mut self, samples: &[f32]) -> io::Result<()> {
+        let channels = self.spec.channels as usize;
+        if !samples.len().is_multiple_of(channels) {
+            return Err(invalid_input(format!(
+                "{} samples is not a whole number of {}-channel frames",
+                samples.len(),
+                channels
+            )));
+        }
+        self.bytes.clear();
+        for frame in samples.chunks_exact(channels) {
+            for (channel, &sample) in frame.iter().enumerate() {
+                encode_sample(
+                    &self.spec,
+                    &mut self.quantizer,
+                    channel,
+                    sample,
+                    &mut self.bytes,
+                );
+            }
+        }
+        self.write_bytes(samples.len() / channels)
+    }
+
+    /// Write mono samples, copying each one to every channel
+    pub fn write_mono(&mut self, samples: &[f32]) -> io::Result<()> {
+        self.bytes.clear();
+        for &sample in samples {
+            for channel in 0..self.spec.channels as usize {
+                encode_sample(
+                    &self.spec,
+                    &mut self.quantizer,
+                    channel,
+                    sample,
+                    &mut self.bytes,
+                );
+            }
+        }
+        self.write_bytes(samples.len())
+    }
+
+    fn write_bytes(&mut self, frames: usize) -> io::Result<()> {
+        self.writer.write_all(&self.bytes)?;
+        self.frames += frames as u64;
+        Ok(())
+    }
+
+    /// Pad the data chunk, append metadata and rewrite the header with the
+    /// final sizes
+    fn finish(&mut self) -> io::Result<()> {
+        self.finalized = true;
+        // Chunks are padded to an even length
+        if (self.frames * self.spec.block_align() as u64) % 2 == 1 {
+            self.writer.write_all(&[0])?;
+        }
+        let metadata = self.metadata.to_chunks(self.spec.sample_rate);
+        self.writer.write_all(&metadata)?;
+        let end = self.writer.stream_position()?;
+        self.writer.seek(SeekFrom::Start(0))?;
+        let metadata_size = metadata.len() as u64;
+        write_header(
+            &mut self.writer,
+            &self.spec,
+            self.frames,
+            metadata_size,
+            true,
+        )?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()
+    }
+
+    /// Complete the file: write metadata, patch the header sizes and flush
+    pub fn finalize(mut self) -> io::Result<()> {
+        self.finish()
+    }
+}
+
+impl<W: Write + Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        if !self.finalized {
+            let _ = self.finish();
+        }
+    }
+}
+
+/// Write a 16-bit PCM WAV file
+///
+/// # Arguments
+/// * `path` - Output file path
+/// * `samples` - Audio samples (f32, range [-1.0, 1.0])
+/// * `sample_rate` - Sample rate in Hz (only for header)
+///
+/// # Returns
+/// Result indicating success or IO error
+///
+/// # Example
+/// ```
+/// use corroza::wav::write_wav_16bit;
+///
+/// let samples = vec![0.0f32; 16000]; // 1 second of silence at 16kHz
+/// write_wav_16bit("/tmp/output.wav", &samples, 16000).unwrap();
+/// ```
+pub fn write_wav_16bit(path: &str, samples: &[f32], sample_rate: u32) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+
+    // Convert f32 samples to i16
+    let i16_samples: Vec<i16> = samples
+        .iter()
+        .map(|&s| {
+            // Clamp to [-1.0, 1.0] then convert to i16
+            let clamped = s.clamp(-1.0, 1.0);
+            if clamped >= 0.0 {
+                (clamped * i16::MAX as f32) as i16
+            } else {
+                // For negative values, use i16::MIN to ensure -1.0 maps to -32768
+                (clamped * (i16::MIN as i32).abs() as f32) as i16
+            }
+        })
+        .collect();
+
+    let num_channels: u16 = 1; // Mono
+    let bits_per_sample: u16 = 16;
+    let byte_rate = sample_rate * num_channels as u32 * (bits_per_sample / 8) as u32;
+    let block_align = num_channels * (bits_per_sample / 8);
+    let data_size = i16_samples.len() as u32 * 2; // 2 bytes per i16 sample
+    let file_size = 36 + data_size; // 44 - 8 (header excluding RIFF and size)
+
+    // RIFF chunk
+    file.write_all(b"RIFF")?;
+    file.write_all(&file_size.to_le_bytes())?;
+    file.write_all(b"WAVE")?;
+
+    // fmt subchunk
+    file.write_all(b"fmt ")?;
+    file.write_all(&16u32.to_le_bytes())?; // Subchunk size
+    file.write_all(&1u16.to_le_bytes())?; // Audio format (PCM)
+    file.write_all(&num_channels.to_le_bytes())?;
+    file.write_all(&sample_rate.to_le_bytes())?;
+    file.write_all(&byte_rate.to_le_bytes())?;
+    file.write_all(&block_align.to_le_bytes())?;
+    file.write_all(&bits_per_sample.to_le_bytes())?;
+
+    // data subchunk
+    file.write_all(b"data")?;
+    file.write_all(&data_size.to_le_bytes())?;
+
+    // Write sample data
+    for sample in i16_samples {
+        file.write_all(&sample.to_le_bytes())?;
+    }
+
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_write_wav_silence() {
+        let temp_path = "/tmp/test_silence.wav";
+        let samples = vec![0.0f32; 100];
+        write_wav_16bit(temp_path, &samples, 16000).unwrap();
+
+        // Verify file exists and has reasonable size
+        let metadata = fs::metadata(temp_path).unwrap();
+        assert!(metadata.len() > 44); // Minimum WAV header size
+
+        // Clean up
+        fs::remove_file(temp_path).unwrap();
+    }
+
+    #[test]
+    fn test_write_wav_full_scale() {
+        let temp_path = "/tmp/test_full_scale.wav";
+        let samples = vec![1.0f32, -1.0f32, 0.5f32, -0.5f32, 0.0f32];
+        write_wav_16bit(temp_path, &samples, 16000).unwrap();
+
+        // Read back and verify header
+        let data = fs::read(temp_path).unwrap();
+
+        // Check RIFF header
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(&data[8..12], b"WAVE");
+
+        // Check fmt chunk
+        assert_eq!(&data[12..16], b"fmt ");
+        assert_eq!(u16::from_le_bytes([data[20], data[21]]), 1); // PCM format
+        assert_eq!(u16::from_le_bytes([data[22], data[23]]), 1); // Mono
+        assert_eq!(
+            u32::from_le_bytes([data[24], data[25], data[26], data[27]]),
+            16000
+        ); // Sample rate
+
+        // Clean up
+        fs::remove_file(temp_path).unwrap();
+    }
+
+    #[test]
+    fn test_write_wav_clamping() {
+        let temp_path = "/tmp/test_clamping.wav";
+        // Values outside [-1.0, 1.0] should be clamped
+        let samples = vec![2.0f32, -2.0f32, 1.5f32, -1.5f32];
+        write_wav_16bit(temp_path, &samples, 16000).unwrap();
+
+        // Read back and check sample values
+        let data = fs::read(temp_path).unwrap();
+
+        // First sample should be clamped to i16::MAX
+        let first_sample = i16::from_le_bytes([data[44], data[45]]);
+        assert_eq!(first_sample, i16::MAX);
+
+        // Second sample should be clamped to i16::MIN
+        let second_sample = i16::from_le_bytes([data[46], data[47]]);
+        assert_eq!(second_sample, i16::MIN);
+
+        // Clean up
+        fs::remove_file(temp_path).unwrap();
+    }
+
+    #[test]
+    fn test_write_wav_correct_size() {
+        let temp_path = "/tmp/test_size.wav";
+        let num_samples = 1000;
+        let samples = vec![0.0f32; num_samples];
+        write_wav_16bit(temp_path, &samples, 16000).unwrap();
+
+        let data = fs::read(temp_path).unwrap();
+
+        // Check data chunk size
+        let data_chunk_size = u32::from_le_bytes([data[40], data[41], data[42], data[43]]);
+        assert_eq!(data_chunk_size, (num_samples * 2) as u32);
+
+        // Check total file size
+        let riff_chunk_size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+        assert_eq!(riff_chunk_size, 36 + data_chunk_size);
+
+        // Clean up
+        fs::remove_file(temp_path).unwrap();
+    }
+
+    #[test]
+    fn test_write_wav_nan_inf_handling() {
+        let temp_path = "/tmp/test_nan_inf.wav";
+        let samples = vec![
+            0.0f32,
+            1.0,
+            f32::NAN,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            -1.0,
+        ];
+        write_wav_16bit(temp_path, &samples, 16000).unwrap();
+
+        let data = fs::read(temp_path).unwrap();
+
+        let sample0 = i16::from_le_bytes([data[44], data[45]]);
+        let sample1 = i16::from_le_bytes([data[46], data[47]]);
+        let sample2 = i16::from_le_bytes([data[48], data[49]]); // NaN
+        let sample3 = i16::from_le_bytes([data[50], data[51]]); // Inf
+        let sample4 = i16::from_le_bytes([data[52], data[53]]); // -Inf
+        let sample5 = i16::from_le_bytes([data[54], data[55]]);
+
+        assert_eq!(sample0, 0);
+        assert_eq!(sample1, i16::MAX);
+        assert_eq!(sample2, 0); // NaN should become 0
+        assert_eq!(sample3, i16::MAX); // Inf should clamp to max
+        assert_eq!(sample4, i16::MIN); // -Inf should clamp to min
+        assert_eq!(sample5, i16::MIN);
+
+        fs::remove_file(temp_path).unwrap();
+    }
+
+    #[test]
+    fn test_write_24bit_stereo() {
+        let temp_path = "/tmp/test_24bit_stereo.wav";
+        let left: Vec<f32> = (0..500).map(|i| (i as f32 * 0.03).sin() * 0.8).collect();
+        let right: Vec<f32> = left.iter().map(|s| -s * 0.5).collect();
+        write_wav(temp_path, &WavSpec::pcm(48000, 2, 24), &[&left, &right]).unwrap();
+
+        let data = fs::read(temp_path).unwrap();
+        assert_eq!(u16::from_le_bytes([data[20], data[21]]), FORMAT_EXTENSIBLE);
+        assert_eq!(u16::from_le_bytes([data[44], data[45]]), FORMAT_PCM); // Sub-format
+
+        let audio = crate::wav::read_wav(temp_path).unwrap();
+        assert_eq!(audio.sample_rate, 48000);
+        assert_eq!(audio.bits_per_sample, 24);
+        for (read, written) in audio.channels.iter().zip([&left, &right]) {
+            assert_eq!(read.len(), written.len());
+            for (r, w) in read.iter().zip(written.iter()) {
+                assert!((r - w).abs() <= 0.5 / 8_388_608.0 + f32::EPSILON);
+            }
+        }
+
+        fs::remove_file(temp_path).unwrap();
+    }
+
+    #[test]
+    fn test_write_float_keeps_headroom() {
+        let temp_path = "/tmp/test_float.wav";
+        let samples = [1.5f32, -2.0, 0.25, f32::NAN];
+        write_wav(temp_path, &WavSpec::float(44100, 1), &[samples]).unwrap();
+
+        let data = fs::read(temp_path).unwrap();
+        assert_eq!(u16::from_le_bytes([data[20], data[21]]), FORMAT_FLOAT);
+        assert_eq!(&data[38..42], b"fact");
+        assert_eq!(
+            u32::from_le_bytes([data[46], data[47], data[48], data[49]]),
+            4
+        );
+
+        let audio = crate::wav::read_wav(temp_path).unwrap();
+        assert_eq!(audio.format, SampleFormat::Float);
+        assert_eq!(audio.channels, vec![vec![1.5, -2.0, 0.25, 0.0]]);
+
+        fs::remove_file(temp_path).unwrap();
+    }
+
+    #[test]
+    fn test_write_multichannel() {
+        let temp_path = "/tmp/test_multichannel.wav";
+        let channels: Vec<Vec<f32>> = (0..6)
+            .map(|c| {
+                (0..99)
+                    .map(|i| ((c * 100 + i) as f32 - 300.0) / 1024.0)
+                    .collect()
+            })
+            .collect();
+        write_wav(temp_path, &WavSpec::pcm(44100, 6, 16), &channels).unwrap();
+
+        let data = fs::read(temp_path).unwrap();
+        assert_eq!(u16::from_le_bytes([data[20], data[21]]), FORMAT_EXTENSIBLE);
+        assert_eq!(
+            u32::from_le_bytes([data[40], data[41], data[42], data[43]]),
+            0x3F
+        );
+
+        // Multiples of 1/1024 are exact in 16 bits
+        let audio = crate::wav::read_wav(temp_path).unwrap();
+        assert_eq!(audio.channels, channels);
+
+        fs::remove_file(temp_path).unwrap();
+    }
+
+    #[test]
+    fn test_write_8bit_pads_odd_data() {
+        let temp_path = "/tmp/test_8bit.wav";
+        write_wav(temp_path, &WavSpec::pcm(8000, 1, 8), &[[0.0f32, 0.5, -1.0]]).unwrap();
+
+        let data = fs::read(temp_path).unwrap();
+        assert_eq!(data.len() % 2, 0);
+        let riff_size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+        assert_eq!(riff_size as usize, data.len() - 8);
+        assert_eq!(&data[44..47], &[128, 192, 0]);
+
+        fs::remove_file(temp_path).unwrap();
+    }
+
+    #[test]
+    fn test_write_wav_rejects_bad_input() {
+        let temp_path = "/tmp/test_rejected.wav";
+        let samples = vec![0.0f32; 10];
+        let error = write_wav(temp_path, &WavSpec::pcm(44100, 2, 16), &[&samples]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        let short = vec![0.0f32; 5];
+        let error =
+            write_wav(temp_path, &WavSpec::pcm(44100, 2, 16), &[&samples, &short]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        let error = write_wav(temp_path, &WavSpec::pcm(44100, 1, 12), &[&samples]).unwrap_err();
+        assert_eq!(error.to_string(), "Unsupported WAV format: 12-bit Pcm");
+        assert!(fs::metadata(temp_path).is_err());
+    }
+
+    #[test]
+    fn test_wav_writer_matches_write_wav() {
+        let whole_path = "/tmp/test_wav_writer_whole.wav";
+        let stream_path = "/tmp/test_wav_writer_stream.wav";
+        let spec = WavSpec::pcm(44100, 2, 24).with_dither(Dither::Shaped);
+        let left: Vec<f32> = (0..1000).map(|i| (i as f32 * 0.01).sin() * 0.7).collect();
+        let right: Vec<f32> = left.iter().map(|s| s * -0.3).collect();
+        write_wav(whole_path, &spec, &[&left, &right]).unwrap();
+
+        let interleaved: Vec<f32> = left
+            .iter()
+            .zip(&right)
+            .flat_map(|(&l, &r)| [l, r])
+            .collect();
+        let mut writer = WavWriter::create(stream_path, spec).unwrap();
+        for chunk in interleaved.chunks(2 * 77) {
+            writer.write_interleaved(chunk).unwrap();
+        }
+        assert_eq!(writer.frames(), 1000);
+        writer.finalize().unwrap();
+
+        let data = fs::read(stream_path).unwrap();
+        assert_eq!(&data[12..16], b"JUNK");
+        let riff_size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+        assert_eq!(riff_size as usize, data.len() - 8);
+        assert_eq!(
+            crate::wav::read_wav(stream_path).unwrap().channels,
+            crate::wav::read_wav(whole_path).unwrap().channels
+        );
+
+        fs::remove_file(whole_path).unwrap();
+        fs::remove_file(stream_path).unwrap();
+    }
+
+    #[test]
+    fn test_wav_writer_drop_patches_sizes() {
+        let temp_path = "/tmp/test_wav_writer_drop.wav";
+        {
+            let mut writer = WavWriter::create(temp_path, WavSpec::pcm(8000, 1, 8)).unwrap();
+            writer.write_mono(&[0.0, 0.5]).unwrap();
+            writer.write_mono(&[-1.0]).unwrap();
+            writer.write_interleaved(&[0.25, 0.25]).unwrap();
+        }
+
+        let data = fs::read(temp_path).unwrap();
+        assert_eq!(data.len() % 2, 0);
+        let riff_size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+        assert_eq!(riff_size as usize, data.len() - 8);
+        let audio = crate::wav::read_wav(temp_path).unwrap();
+        assert_eq!(audio.channels, vec![vec![0.0, 0.5, -1.0, 0.25, 0.25]]);
+
+        fs::remove_file(temp_path).unwrap();
+    }
+
+    #[test]
+    fn test_unfinished_wav_writer_reads_back() {
+        let mut bytes = Vec::new();
+        let mut writer =
+            WavWriter::new(io::Cursor::new(&mut bytes), WavSpec::pcm(8000, 2, 16)).unwrap();
+        writer.write_mono(&[0.5, -0.5, 0.25]).unwrap();
+        // As if the process died before the header was patched
+        std::mem::forget(writer);
+        bytes.push(0); // Part of the next frame
+
+        let audio = crate::wav::parse_wav(&bytes).unwrap();
+        assert_eq!(audio.channels, vec![vec![0.5, -0.5, 0.25]; 2]);
+    }
+
+    #[test]
+    fn test_wav_writer_rejects_partial_frames() {
+        let temp_path = "/tmp/test_wav_writer_partial.wav";
+        let mut writer = WavWriter::create(temp_path, WavSpec::pcm(8000, 2, 16)).unwrap();
+        let error = writer.write_interleaved(&[0.0; 3]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        writer.write_mono(&[0.25; 3]).unwrap();
+        writer.finalize().unwrap();
+
+        let audio = crate::wav::read_wav(temp_path).unwrap();
+        assert_eq!(audio.channels, vec![vec![0.25; 3]; 2]);
+
+        fs::remove_file(temp_path).unwrap();
+    }
+
+    #[test]
+    fn test_metadata_round_trip() {
+        use crate::wav::{CuePoint, SampleLoop, SamplerInfo};
+
+        let whole_path = "/tmp/test_metadata_whole.wav";
+        let stream_path = "/tmp/test_metadata_stream.wav";
+        let spec = WavSpec::pcm(22050, 1, 8);
+        let samples = [0.5f32; 101];
+        let metadata = WavMetadata {
+            title: Some("Odd".to_string()),
+            software: Some("corroza".to_string()),
+            cue_points: vec![CuePoint::new(10, "start"), CuePoint::new(60, "")],
+            sampler: Some(SamplerInfo::new(60).with_loop(SampleLoop::forward(20, 99))),
+            ..Default::default()
+        };
+        write_wav_with_metadata(whole_path, &spec, &[samples], &metadata).unwrap();
+
+        let mut writer = WavWriter::create(stream_path, spec).unwrap();
+        writer.metadata_mut().title = Some("Odd".to_string());
+        writer.write_mono(&samples[..50]).unwrap();
+        writer.metadata_mut().software = metadata.software.clone();
+        writer.metadata_mut().cue_points = metadata.cue_points.clone();
+        writer.write_mono(&samples[50..]).unwrap();
+        writer.metadata_mut().sampler = metadata.sampler.clone();
+        writer.finalize().unwrap();
+
+        for path in [whole_path, stream_path] {
+            let data = fs::read(path).unwrap();
+            let riff_size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+            assert_eq!(riff_size as usize, data.len() - 8);
+            let audio = crate::wav::read_wav(path).unwrap();
+            assert_eq!(audio.channels, vec![samples.to_vec()]);
+            assert_eq!(audio.metadata, metadata);
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_header_switches_to_rf64() {
+        let read_u32 =
+            |bytes: &[u8], at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let read_u64 =
+            |bytes: &[u8], at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let spec = WavSpec::pcm(48000, 2, 16);
+
+        // Small files keep a plain RIFF header with room reserved for ds64
+        let mut header = Vec::new();
+        write_header(&mut header, &spec, 1000, 0, true).unwrap();
+        assert_eq!(&header[0..4], b"RIFF");
+        assert_eq!(&header[12..16], b"JUNK");
+        assert_eq!(read_u32(&header, 4) as usize, header.len() - 8 + 4000);
+
+        // 3 GiB frames of 4 bytes is far past the 32-bit limit
+        let frames = 3u64 << 30;
+        let data_size = frames * 4;
+        let mut rf64 = Vec::new();
+        write_header(&mut rf64, &spec, frames, 0, true).unwrap();
+        assert_eq!(rf64.len(), header.len());
+        assert_eq!(&rf64[0..4], b"RF64");
+        assert_eq!(read_u32(&rf64, 4), u32::MAX);
+        assert_eq!(&rf64[12..16], b"ds64");
+        assert_eq!(read_u64(&rf64, 20), rf64.len() as u64 - 8 + data_size);
+        assert_eq!(read_u64(&rf64, 28), data_size);
+        assert_eq!(read_u64(&rf64, 36), frames);
+        assert_eq!(&rf64[rf64.len() - 8..rf64.len() - 4], b"data");
+        assert_eq!(read_u32(&rf64, rf64.len() - 4), u32::MAX);
+
+        // Data that only overflows once the reserved ds64 chunk is counted
+        let spec = WavSpec::pcm(48000, 1, 8);
+        let frames = u32::MAX as u64 - 50;
+        let mut plain = Vec::new();
+        write_header(&mut plain, &spec, frames, 0, false).unwrap();
+        assert_eq!(&plain[0..4], b"RIFF");
+        assert_eq!(
+            read_u32(&plain, 4) as u64,
+            plain.len() as u64 - 8 + frames + 1
+        );
+        let mut reserved = Vec::new();
+        write_header(&mut reserved, &spec, frames, 0, true).unwrap();
+        assert_eq!(&reserved[0..4], b"RF64");
+        assert_eq!(
+            read_u64(&reserved, 20),
+            reserved.len() as u64 - 8 + frames + 1
+        );
+    }
+
+    #[test]
+    fn test_spec_rejects_oversized_frames() {
+        assert!(WavSpec::pcm(44100, 16383, 32).validate().is_ok());
+        let error = WavSpec::pcm(44100, 16384, 32).validate().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(WavSpec::float(48000, u16::MAX).validate().is_err());
+        assert!(WavSpec::pcm(u32::MAX, 2, 16).validate().is_err());
+    }
+}